@@ -0,0 +1,103 @@
+//! Compares the request path of the embedded files, which looks up the
+//! response prepared at startup, with preparing it on each request, as the
+//! server did before the build-time records.
+//!
+//! ```shell
+//! cargo bench -p server --bench serve
+//! ```
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use dist::RecordKind;
+use http::{HeaderMap, HeaderValue, Method, header};
+use server::cache_policy::CachePolicy;
+use server::compression_policy::{self, CompressionPolicy};
+use server::mime_types::MimeTypes;
+use server::prepared_file::PreparedFile;
+use std::hint::black_box;
+
+struct Policies {
+    mime_types: MimeTypes,
+    cache_policy: CachePolicy,
+    compression_policy: CompressionPolicy,
+}
+
+impl Policies {
+    fn new() -> Self {
+        Policies {
+            mime_types: MimeTypes::with_defaults(Vec::new()),
+            cache_policy: CachePolicy::with_defaults(Vec::new()),
+            compression_policy: CompressionPolicy::with_defaults(
+                compression_policy::DEFAULT_MIN_SIZE,
+                compression_policy::DEFAULT_MIN_SAVINGS,
+                Vec::new(),
+            ),
+        }
+    }
+
+    fn prepare(&self, index: usize) -> Option<PreparedFile> {
+        PreparedFile::new(
+            &dist::records()[index],
+            &self.mime_types,
+            &self.cache_policy,
+            &self.compression_policy,
+            false,
+        )
+    }
+}
+
+fn paths() -> Vec<&'static str> {
+    dist::records()
+        .iter()
+        .filter(|record| record.kind == RecordKind::File)
+        .map(|record| record.path)
+        .collect()
+}
+
+fn serve(c: &mut Criterion) {
+    let policies = Policies::new();
+    let files = (0..dist::records().len())
+        .map(|index| policies.prepare(index))
+        .collect::<Vec<_>>();
+    let paths = paths();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate, br, zstd"),
+    );
+    // The path of the server: the record is found by the perfect hash and
+    // its response is copied from the one prepared at startup.
+    let prepared = |path: &str| {
+        let (index, _) = dist::lookup(path)?;
+        Some(files[index].as_ref()?.serve(None, &Method::GET, &headers))
+    };
+    // The path before the records: the type, the etags and the encodings
+    // worth serving are worked out again for each request.
+    let baseline = |path: &str| {
+        let (index, _) = dist::lookup(path)?;
+        Some(policies.prepare(index)?.serve(None, &Method::GET, &headers))
+    };
+    for path in &paths {
+        let (prepared, baseline) = (prepared(path).unwrap(), baseline(path).unwrap());
+        assert_eq!(prepared.status(), baseline.status());
+        assert_eq!(prepared.headers(), baseline.headers());
+    }
+    let mut group = c.benchmark_group("serve");
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            for path in &paths {
+                black_box(baseline(black_box(path)));
+            }
+        })
+    });
+    group.bench_function("prepared", |b| {
+        b.iter(|| {
+            for path in &paths {
+                black_box(prepared(black_box(path)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, serve);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+//! 在编译时为每个嵌入的文件生成类型和校验器的记录，以及路径到记录的完美哈希索引
+
+#[path = "mime.rs"]
+mod mime;
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+fn main() {
+    // embed_it 的 path 相对于 workspace 的根目录，也就是 server 目录
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let root = manifest_dir.join("..").join("..").join("dist");
+    println!("cargo:rerun-if-changed={}", root.display());
+    println!("cargo:rerun-if-changed=mime.rs");
+
+    let mut records = Vec::new();
+    walk(&root, &root, &mut records);
+    records.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut out = String::new();
+    writeln!(out, "static RECORDS: [Record; {}] = [", records.len()).unwrap();
+    for record in &records {
+        writeln!(out, "    {},", record.to_tokens()).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    let mut index = phf_codegen::Map::new();
+    for (i, record) in records.iter().enumerate() {
+        index.entry(record.path.as_str(), i.to_string());
+    }
+    writeln!(
+        out,
+        "static INDEX: ::phf::Map<&'static str, usize> = {};",
+        index.build()
+    )
+    .unwrap();
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("records.rs"), out).unwrap();
+}
+
+struct BuildRecord {
+    path: String,
+    file: Option<(String, String)>,
+}
+
+impl BuildRecord {
+    fn to_tokens(&self) -> String {
+        match &self.file {
+            None => format!(
+                "Record {{ path: {:?}, kind: RecordKind::Dir, content_type: \"\", etag: \"\" }}",
+                self.path
+            ),
+            Some((content_type, etag)) => format!(
+                "Record {{ path: {:?}, kind: RecordKind::File, content_type: {content_type:?}, etag: {etag:?} }}",
+                self.path
+            ),
+        }
+    }
+}
+
+fn walk(root: &Path, dir: &Path, records: &mut Vec<BuildRecord>) {
+    records.push(BuildRecord {
+        path: relative_path(root, dir),
+        file: None,
+    });
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(root, &path, records);
+            continue;
+        }
+        let relative_path = relative_path(root, &path);
+        // 和 Dist 的 exclude 保持一致
+        if relative_path.ends_with(".gz") {
+            continue;
+        }
+        let content = fs::read(&path).unwrap();
+        // 和 dist 中 ETagHeaderValue 的格式一致
+        let etag = format!("\"{}\"", blake3::hash(&content).to_hex());
+        records.push(BuildRecord {
+            file: Some((mime::content_type(&relative_path), etag)),
+            path: relative_path,
+        });
+    }
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap()
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
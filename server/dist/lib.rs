@@ -1,5 +1,8 @@
-use embed_it::{Blake3_256Hash, Embed};
+use embed_it::{Blake3_256Hash, BrotliContent, Content, Embed, GzipContent, Meta, ZstdContent};
 use hex::ToHex;
+use std::time::{Duration, SystemTime};
+
+pub mod mime;
 
 #[derive(Embed)]
 #[embed(
@@ -9,8 +12,13 @@ use hex::ToHex;
         field(factory = ETagHeaderValue, name = etag, trait_name = DirEtagField, global)
     ),
     file(
-        derive(Blake3), derive(Zstd), derive(Brotli),
-        field(factory = ETagHeaderValue, name = etag, trait_name = FileEtagField, global)
+        // vite-plugin-compression 生成的 .gz 文件不需要再嵌入，gzip 压缩的内容由 derive(Gzip) 提供
+        exclude(regex = ".*\\.gz$"),
+        derive(Blake3), derive(Zstd), derive(Brotli), derive(Gzip),
+        field(factory = ETagHeaderValue, name = etag, trait_name = FileEtagField, global),
+        field(factory = DeflateContent, name = deflate_content, trait_name = FileDeflateContentField, global),
+        field(factory = EncodedLength, name = encoded_length, trait_name = FileEncodedLengthField, global),
+        field(factory = LastModifiedHeaderValue, name = last_modified, trait_name = FileLastModifiedField, global)
     )
 )]
 pub struct Dist;
@@ -26,6 +34,12 @@ impl ETagHeaderValue {
             value: format!("\"{}\"", hex_sha),
         }
     }
+
+    /// 在原始内容 etag 的基础上追加压缩算法作为后缀，例如 `"<hash>-br"`，
+    /// 同一个文件压缩后的每一种表示都需要有自己的强校验器，否则缓存会把不同编码的内容当成同一份
+    pub fn with_suffix(&self, suffix: &str) -> String {
+        format!("{}-{}\"", self.value.trim_end_matches('"'), suffix)
+    }
 }
 
 impl DirFieldFactory for ETagHeaderValue {
@@ -43,3 +57,258 @@ impl FileFieldFactory for ETagHeaderValue {
         Self::create(data)
     }
 }
+
+/// HTTP 的 deflate 编码是 zlib 格式（RFC 1950），embed_it 没有提供这种压缩，
+/// 这里直接复用嵌入的 gzip 压缩结果：去掉 gzip 的头和尾，换成 zlib 的头和 adler32 校验和，不需要再压缩一次
+pub struct DeflateContent {
+    pub value: Option<Vec<u8>>,
+}
+
+impl DeflateContent {
+    pub fn create<T: Content + GzipContent + ?Sized>(v: &T) -> Self {
+        Self {
+            value: gzip_to_zlib(v.gzip_content(), v.content()),
+        }
+    }
+}
+
+impl FileFieldFactory for DeflateContent {
+    type Field = Self;
+
+    fn create<T: File + ?Sized>(data: &T) -> Self::Field {
+        Self::create(data)
+    }
+}
+
+/// 原始内容和每一种压缩后的表示的长度，服务器根据它们判断压缩是否值得，
+/// 对于 png、woff2 这样已经压缩过的文件，再压缩一次往往会变得更大。
+/// embed_it 会为每个文件都嵌入所有的压缩结果，没有办法按文件省略，所以只能在服务时跳过这些表示
+pub struct EncodedLength {
+    pub identity: u64,
+    pub brotli: u64,
+    pub zstd: u64,
+    pub gzip: u64,
+    pub deflate: Option<u64>,
+}
+
+impl EncodedLength {
+    pub fn create<T: Content + BrotliContent + ZstdContent + GzipContent + ?Sized>(v: &T) -> Self {
+        Self {
+            identity: v.content().len() as u64,
+            brotli: v.brotli_content().len() as u64,
+            zstd: v.zstd_content().len() as u64,
+            gzip: v.gzip_content().len() as u64,
+            // zlib 格式比 deflate 数据多 2 字节的头和 4 字节的 adler32 校验和
+            deflate: gzip_deflate_data(v.gzip_content()).map(|deflate| deflate.len() as u64 + 6),
+        }
+    }
+}
+
+impl FileFieldFactory for EncodedLength {
+    type Field = Self;
+
+    fn create<T: File + ?Sized>(data: &T) -> Self::Field {
+        Self::create(data)
+    }
+}
+
+/// 把 gzip（RFC 1952）格式的压缩内容转换为 zlib（RFC 1950）格式
+fn gzip_to_zlib(gzip: &[u8], content: &[u8]) -> Option<Vec<u8>> {
+    let deflate = gzip_deflate_data(gzip)?;
+    let mut zlib = Vec::with_capacity(deflate.len() + 6);
+    // CM = 8 (deflate)，CINFO = 7 (32K window)，FLEVEL = 3 (最大压缩)
+    zlib.extend_from_slice(&[0x78, 0xda]);
+    zlib.extend_from_slice(deflate);
+    zlib.extend_from_slice(&adler32(content).to_be_bytes());
+    Some(zlib)
+}
+
+/// gzip 格式中去掉头和尾之后的 deflate 数据
+fn gzip_deflate_data(gzip: &[u8]) -> Option<&[u8]> {
+    const FHCRC: u8 = 0b0000_0010;
+    const FEXTRA: u8 = 0b0000_0100;
+    const FNAME: u8 = 0b0000_1000;
+    const FCOMMENT: u8 = 0b0001_0000;
+    // ID1 ID2 CM FLG MTIME(4) XFL OS
+    if gzip.len() < 18 || gzip[0..3] != [0x1f, 0x8b, 0x08] {
+        return None;
+    }
+    let flags = gzip[3];
+    let mut start = 10;
+    if flags & FEXTRA != 0 {
+        let extra_len = u16::from_le_bytes([*gzip.get(start)?, *gzip.get(start + 1)?]) as usize;
+        start += 2 + extra_len;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            start += gzip.get(start..)?.iter().position(|&b| b == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        start += 2;
+    }
+    // CRC32(4) ISIZE(4)
+    gzip.get(start..gzip.len().checked_sub(8)?)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    // 5552 是保证 b 在取模前不会溢出的最大块长度
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// 可复现构建时由构建环境提供的时间戳，设置后所有文件都使用这个时间作为最后修改时间
+const SOURCE_DATE_EPOCH: Option<&str> = option_env!("SOURCE_DATE_EPOCH");
+
+pub struct LastModifiedHeaderValue {
+    pub value: Option<SystemTime>,
+}
+
+impl LastModifiedHeaderValue {
+    pub fn create<T: Meta + ?Sized>(v: &T) -> Self {
+        let modified = SOURCE_DATE_EPOCH
+            .and_then(|epoch| epoch.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .or(v.metadata().modified)
+            // HTTP-date 只精确到秒
+            .map(|modified| SystemTime::UNIX_EPOCH + Duration::from_secs(modified.as_secs()));
+        Self { value: modified }
+    }
+}
+
+impl FileFieldFactory for LastModifiedHeaderValue {
+    type Field = Self;
+
+    fn create<T: File + ?Sized>(data: &T) -> Self::Field {
+        Self::create(data)
+    }
+}
+
+/// 构建时为 `dist` 中每一个目录和文件生成的记录，和 [`Dist`] 中的条目一一对应，
+/// 只有从文件内容就能确定的类型和校验器，不必在运行时再猜测类型或计算校验器。
+/// 原始内容和压缩后的长度来自嵌入的压缩结果（[`EncodedLength`]），缓存策略取决于命令行的配置，
+/// 它们和解析后的 etag 由服务器在启动时准备好，不在记录中
+#[derive(Debug)]
+pub struct Record {
+    /// 相对于 `dist` 的路径，根目录是空字符串
+    pub path: &'static str,
+    pub kind: RecordKind,
+    /// 带有字符集的 Content-Type，目录是空字符串
+    pub content_type: &'static str,
+    /// 原始内容的强校验器，和 [`ETagHeaderValue`] 的格式一致，目录是空字符串
+    pub etag: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordKind {
+    Dir,
+    File,
+}
+
+include!(concat!(env!("OUT_DIR"), "/records.rs"));
+
+/// 通过完美哈希找到路径对应的记录以及它在 [`records`] 中的下标，下标可以用来索引服务器在启动时预先构建的表
+pub fn lookup(path: &str) -> Option<(usize, &'static Record)> {
+    let index = *INDEX.get(path)?;
+    Some((index, &RECORDS[index]))
+}
+
+/// 按路径排序的所有记录
+pub fn records() -> &'static [Record] {
+    &RECORDS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embed_it::Entry;
+    use flate2::Compression;
+    use flate2::read::ZlibDecoder;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn adler32_checksum() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn gzip_to_zlib_round_trip() {
+        let content = "console.log('hello art design pro');\n".repeat(4096);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::new(9));
+        encoder.write_all(content.as_bytes()).unwrap();
+        let gzip = encoder.finish().unwrap();
+
+        let zlib = gzip_to_zlib(&gzip, content.as_bytes()).unwrap();
+        assert_eq!(zlib.len(), gzip_deflate_data(&gzip).unwrap().len() + 6);
+        let mut decoded = String::new();
+        ZlibDecoder::new(zlib.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, content);
+    }
+
+    #[test]
+    fn gzip_with_file_name() {
+        let mut encoder = flate2::GzBuilder::new()
+            .filename("index.html")
+            .comment("art design pro")
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(b"<html></html>").unwrap();
+        let gzip = encoder.finish().unwrap();
+
+        let zlib = gzip_to_zlib(&gzip, b"<html></html>").unwrap();
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(zlib.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"<html></html>");
+    }
+
+    #[test]
+    fn not_gzip() {
+        assert!(gzip_to_zlib(b"definitely not gzip data", b"").is_none());
+    }
+
+    #[test]
+    fn records_match_embedded_entries() {
+        for (index, record) in records().iter().enumerate() {
+            assert_eq!(lookup(record.path).unwrap().0, index);
+            match Dist.get(record.path).unwrap() {
+                Entry::Dir(_) => assert_eq!(record.kind, RecordKind::Dir),
+                Entry::File(file) => {
+                    assert_eq!(record.kind, RecordKind::File);
+                    assert_eq!(record.etag, file.etag().value);
+                    assert_eq!(record.content_type, mime::content_type(record.path));
+                }
+            }
+        }
+        assert_eq!(lookup("").unwrap().1.kind, RecordKind::Dir);
+        assert!(lookup("missing.html").is_none());
+    }
+
+    #[test]
+    fn charset() {
+        assert_eq!(mime::content_type("index.html"), "text/html; charset=utf-8");
+        assert_eq!(
+            mime::content_type("assets/index.js"),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(
+            mime::content_type("logo.svg"),
+            "image/svg+xml; charset=utf-8"
+        );
+        assert_eq!(mime::content_type("favicon.ico"), "image/x-icon");
+        assert_eq!(mime::content_type("LICENSE"), "application/octet-stream");
+    }
+}
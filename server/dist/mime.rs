@@ -0,0 +1,32 @@
+// 构建脚本和库共用这个文件，只能依赖 std 和 mime_guess
+
+/// 根据文件的扩展名猜测 Content-Type，文本类型追加 `; charset=utf-8`，Vite 的构建产物都是 UTF-8 编码
+pub fn content_type(path: &str) -> String {
+    with_charset(
+        mime_guess::from_path(path)
+            .first_raw()
+            .unwrap_or("application/octet-stream"),
+    )
+}
+
+/// 文本类型没有声明字符集时追加 `; charset=utf-8`
+pub fn with_charset(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if is_text(essence) && !content_type.contains(';') {
+        format!("{essence}; charset=utf-8")
+    } else {
+        content_type.to_owned()
+    }
+}
+
+/// 需要声明字符集的类型：text/*、JavaScript、JSON、XML 以及以 +json 和 +xml 结尾的类型，例如 image/svg+xml
+pub fn is_text(essence: &str) -> bool {
+    let essence = essence.to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/javascript" | "application/json" | "application/xml"
+        )
+}
@@ -0,0 +1,79 @@
+-- The users, the roles and the menus of the system management pages.
+
+CREATE TABLE roles (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    code TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    enabled INTEGER NOT NULL DEFAULT 1,
+    create_time TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now'))
+);
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    user_name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    nick_name TEXT NOT NULL DEFAULT '',
+    avatar TEXT NOT NULL DEFAULT '',
+    gender TEXT NOT NULL DEFAULT '',
+    phone TEXT NOT NULL DEFAULT '',
+    email TEXT NOT NULL DEFAULT '',
+    status TEXT NOT NULL DEFAULT '1',
+    create_by TEXT NOT NULL DEFAULT '',
+    create_time TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now')),
+    update_by TEXT NOT NULL DEFAULT '',
+    update_time TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%S', 'now'))
+);
+
+CREATE TABLE user_roles (
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, role_id)
+);
+
+-- A menu without roles is shown to everyone, like a route without
+-- `meta.roles` in the frontend.
+CREATE TABLE menus (
+    id INTEGER PRIMARY KEY,
+    parent_id INTEGER REFERENCES menus (id) ON DELETE CASCADE,
+    name TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL,
+    component TEXT NOT NULL DEFAULT '',
+    title TEXT NOT NULL,
+    icon TEXT NOT NULL DEFAULT '',
+    sort INTEGER NOT NULL DEFAULT 0,
+    is_enable INTEGER NOT NULL DEFAULT 1,
+    keep_alive INTEGER NOT NULL DEFAULT 0,
+    is_hide INTEGER NOT NULL DEFAULT 0,
+    is_hide_tab INTEGER NOT NULL DEFAULT 0,
+    link TEXT NOT NULL DEFAULT '',
+    is_iframe INTEGER NOT NULL DEFAULT 0,
+    show_badge INTEGER NOT NULL DEFAULT 0,
+    show_text_badge TEXT NOT NULL DEFAULT '',
+    fixed_tab INTEGER NOT NULL DEFAULT 0,
+    active_path TEXT NOT NULL DEFAULT '',
+    is_full_page INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX menus_parent_id ON menus (parent_id);
+
+-- The buttons of a menu, checked by the `v-auth` directive.
+CREATE TABLE menu_auths (
+    id INTEGER PRIMARY KEY,
+    menu_id INTEGER NOT NULL REFERENCES menus (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    auth_mark TEXT NOT NULL,
+    sort INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (menu_id, auth_mark)
+);
+
+CREATE TABLE role_menus (
+    role_id INTEGER NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    menu_id INTEGER NOT NULL REFERENCES menus (id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, menu_id)
+);
+
+CREATE TABLE role_auths (
+    role_id INTEGER NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    auth_id INTEGER NOT NULL REFERENCES menu_auths (id) ON DELETE CASCADE,
+    PRIMARY KEY (role_id, auth_id)
+);
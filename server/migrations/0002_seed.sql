@@ -0,0 +1,50 @@
+-- The roles, the accounts and the menus of the mock API, so that the
+-- frontend works out of the box.
+
+INSERT INTO roles (id, name, code, description, enabled, create_time) VALUES
+    (1, '超级管理员', 'R_SUPER', '拥有系统全部权限', 1, '2024-01-01 09:00:00'),
+    (2, '管理员', 'R_ADMIN', '拥有系统管理权限', 1, '2024-02-02 09:00:00'),
+    (3, '普通用户', 'R_USER', '拥有系统普通权限', 1, '2024-03-03 09:00:00'),
+    (4, '财务管理员', 'R_FINANCE', '管理财务相关权限', 1, '2024-04-04 09:00:00'),
+    (5, 'IT管理员', 'R_IT', '管理IT相关权限', 1, '2024-05-05 09:00:00'),
+    (6, '客服', 'R_SERVICE', '处理客户咨询', 0, '2024-06-06 09:00:00'),
+    (7, '访客', 'R_GUEST', '只读访问权限', 0, '2024-07-07 09:00:00');
+
+INSERT INTO users (id, user_name, nick_name, email, create_by, update_by) VALUES
+    (1, 'Super', 'Super', 'super@company.com', 'Super', 'Super'),
+    (2, 'Admin', 'Admin', 'admin@company.com', 'Super', 'Super'),
+    (3, 'User', 'User', 'user@company.com', 'Super', 'Super');
+
+INSERT INTO user_roles (user_id, role_id) VALUES (1, 1), (2, 2), (3, 3);
+
+INSERT INTO menus (id, parent_id, name, path, component, title, icon, sort, keep_alive, is_hide, is_hide_tab, fixed_tab, is_full_page) VALUES
+    (1, NULL, 'Dashboard', '/dashboard', '/index/index', 'menus.dashboard.title', 'ri:pie-chart-line', 1, 0, 0, 0, 0, 0),
+    (11, 1, 'Console', 'console', '/dashboard/console', 'menus.dashboard.console', '', 1, 0, 0, 0, 1, 0),
+    (2, NULL, 'System', '/system', '/index/index', 'menus.system.title', 'ri:user-3-line', 2, 0, 0, 0, 0, 0),
+    (21, 2, 'User', 'user', '/system/user', 'menus.system.user', '', 1, 1, 0, 0, 0, 0),
+    (22, 2, 'Role', 'role', '/system/role', 'menus.system.role', '', 2, 1, 0, 0, 0, 0),
+    (23, 2, 'UserCenter', 'user-center', '/system/user-center', 'menus.system.userCenter', '', 3, 1, 1, 1, 0, 0),
+    (24, 2, 'Menus', 'menu', '/system/menu', 'menus.system.menu', '', 4, 1, 0, 0, 0, 0),
+    (3, NULL, 'Result', '/result', '/index/index', 'menus.result.title', 'ri:checkbox-circle-line', 3, 0, 0, 0, 0, 0),
+    (31, 3, 'ResultSuccess', 'success', '/result/success', 'menus.result.success', 'ri:checkbox-circle-line', 1, 1, 0, 0, 0, 0),
+    (32, 3, 'ResultFail', 'fail', '/result/fail', 'menus.result.fail', 'ri:close-circle-line', 2, 1, 0, 0, 0, 0),
+    (4, NULL, 'Exception', '/exception', '/index/index', 'menus.exception.title', 'ri:error-warning-line', 4, 0, 0, 0, 0, 0),
+    (41, 4, 'Exception403', '403', '/exception/403', 'menus.exception.forbidden', '', 1, 1, 0, 1, 0, 1),
+    (42, 4, 'Exception404', '404', '/exception/404', 'menus.exception.notFound', '', 2, 1, 0, 1, 0, 1),
+    (43, 4, 'Exception500', '500', '/exception/500', 'menus.exception.serverError', '', 3, 1, 0, 1, 0, 1);
+
+INSERT INTO menu_auths (id, menu_id, title, auth_mark, sort) VALUES
+    (1, 24, '新增', 'add', 1),
+    (2, 24, '编辑', 'edit', 2),
+    (3, 24, '删除', 'delete', 3);
+
+INSERT INTO role_menus (role_id, menu_id) VALUES
+    (1, 1), (2, 1),
+    (1, 2), (2, 2),
+    (1, 21), (2, 21),
+    (1, 22),
+    (1, 24);
+
+INSERT INTO role_auths (role_id, auth_id) VALUES
+    (1, 1), (1, 2), (1, 3),
+    (2, 1), (2, 2);
@@ -0,0 +1,23 @@
+-- The passwords and the sessions of the users.
+
+-- The PHC string of the Argon2id hash, empty for the users who can't log in.
+ALTER TABLE users ADD COLUMN password_hash TEXT NOT NULL DEFAULT '';
+
+-- The password of the seeded accounts is 123456, like in the mock API.
+UPDATE users SET password_hash = '$argon2id$v=19$m=19456,t=2,p=1$9Byf0B6LqMhY4a6EPId9yA$l80n6wRghbEx2b0Z8lNzNGOu/7J1Yn1B4TGGXvBtKqA' WHERE id = 1;
+UPDATE users SET password_hash = '$argon2id$v=19$m=19456,t=2,p=1$KZxN+/UbejUc9roIttupow$sIGQj5PuHlsV1pDkeGN6f2QCXmBt3YhlFuAT222ghoI' WHERE id = 2;
+UPDATE users SET password_hash = '$argon2id$v=19$m=19456,t=2,p=1$xig7VUse+3vRFlNJZ5MmLw$DTiC+diK0HTRBEDHsYESAPbuFQUMHXINvcooDg38Xkc' WHERE id = 3;
+
+-- The refresh tokens, stored hashed. The tokens rotated from the same login
+-- share a family, which is revoked as a whole when a revoked token is reused.
+CREATE TABLE refresh_tokens (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    family TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at INTEGER NOT NULL,
+    revoked INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX refresh_tokens_family ON refresh_tokens (family);
+CREATE INDEX refresh_tokens_user_id ON refresh_tokens (user_id);
@@ -0,0 +1,14 @@
+-- The buttons of the user and the role pages, which the access control of
+-- the API requires along with the pages themselves.
+
+INSERT INTO menu_auths (id, menu_id, title, auth_mark, sort) VALUES
+    (4, 21, '新增', 'add', 1),
+    (5, 21, '编辑', 'edit', 2),
+    (6, 21, '删除', 'delete', 3),
+    (7, 22, '新增', 'add', 1),
+    (8, 22, '编辑', 'edit', 2),
+    (9, 22, '删除', 'delete', 3);
+
+INSERT INTO role_auths (role_id, auth_id) VALUES
+    (1, 4), (1, 5), (1, 6), (1, 7), (1, 8), (1, 9),
+    (2, 4), (2, 5);
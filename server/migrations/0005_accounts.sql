@@ -0,0 +1,16 @@
+-- The self-service registration and the password reset, confirmed with a
+-- token mailed to the email address of the user.
+
+-- A registered user can't log in before the email address is verified.
+ALTER TABLE users ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 1;
+
+-- The tokens are stored hashed, each for one purpose: `verify` or `reset`.
+CREATE TABLE email_tokens (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    purpose TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX email_tokens_user_id ON email_tokens (user_id);
@@ -0,0 +1,4 @@
+-- The generation of the access tokens of a user, in their `gen` claim. It is
+-- bumped when the sessions of the user end, e.g. when the password changes,
+-- which refuses the access tokens issued before.
+ALTER TABLE users ADD COLUMN token_generation INTEGER NOT NULL DEFAULT 0;
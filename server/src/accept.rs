@@ -0,0 +1,147 @@
+use crate::derive_header;
+use crate::util::flat_csv::FlatCsv;
+use crate::util::quality::{Quality, QualityValue};
+
+/// `Accept` header, defined in
+/// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-12.5.1)
+///
+/// The `Accept` header field can be used by user agents to specify their
+/// preferences regarding response media types.
+///
+/// # ABNF
+///
+/// ```text
+/// Accept = #( media-range [ weight ] )
+///
+/// media-range    = ( "*/*"
+///                    / ( type "/" "*" )
+///                    / ( type "/" subtype )
+///                  ) parameters
+/// ```
+///
+/// # Example values
+/// * `text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8`
+/// * `application/json, text/plain, */*`
+/// * `audio/*; q=0.2, audio/basic`
+///
+/// # Examples
+///
+/// ```
+/// use headers::HeaderMapExt;
+/// use server::accept::Accept;
+///
+/// let mut headers = http::HeaderMap::new();
+/// headers.insert(http::header::ACCEPT, "text/html, */*;q=0.8".parse().unwrap());
+///
+/// let accept = headers.typed_get::<Accept>().unwrap();
+/// assert!(accept.lists("text/html"));
+/// assert!(!accept.lists("application/json"));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accept(FlatCsv);
+
+derive_header! {
+    Accept(_),
+    name: ACCEPT
+}
+
+impl Accept {
+    /// The media ranges with their quality, the parameters other than the
+    /// weight are kept in the media range.
+    pub fn iter(&self) -> impl Iterator<Item = QualityValue<String>> + '_ {
+        self.0.iter().flat_map(|s| s.parse().ok())
+    }
+
+    /// The quality of the most specific media range matching the media type,
+    /// `None` if no media range matches it at all.
+    pub fn quality_of(&self, media_type: &str) -> Option<Quality> {
+        let (type_, _) = media_type.split_once('/')?;
+        self.iter()
+            .filter_map(|quality_value| {
+                let range = essence(quality_value.value());
+                let specificity = if range.eq_ignore_ascii_case(media_type) {
+                    2
+                } else if range
+                    .strip_suffix("/*")
+                    .is_some_and(|range_type| range_type.eq_ignore_ascii_case(type_))
+                {
+                    1
+                } else if range == "*/*" {
+                    0
+                } else {
+                    return None;
+                };
+                Some((specificity, quality_value.quality()))
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, quality)| quality)
+    }
+
+    /// Whether the media type is listed explicitly, not through a wildcard,
+    /// with a non-zero quality.
+    ///
+    /// Browsers list `text/html` when they navigate to a page, while scripts
+    /// and subresources usually accept `*/*`.
+    pub fn lists(&self, media_type: &str) -> bool {
+        self.iter().any(|quality_value| {
+            essence(quality_value.value()).eq_ignore_ascii_case(media_type)
+                && quality_value.quality() > Quality::ZERO
+        })
+    }
+}
+
+/// The media range without its parameters.
+fn essence(media_range: &str) -> &str {
+    media_range.split(';').next().unwrap_or_default().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::quality::IntoQuality;
+    use headers::HeaderMapExt;
+
+    fn accept(value: &str) -> Accept {
+        let mut map = http::HeaderMap::new();
+        map.insert(http::header::ACCEPT, value.parse().unwrap());
+        map.typed_get().unwrap()
+    }
+
+    #[test]
+    fn navigation() {
+        let accept = accept(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8",
+        );
+        assert!(accept.lists("text/html"));
+        assert!(!accept.lists("application/json"));
+        assert_eq!(
+            accept.quality_of("text/html"),
+            Some(1000_u16.into_quality())
+        );
+        assert_eq!(
+            accept.quality_of("application/json"),
+            Some(800_u16.into_quality())
+        );
+    }
+
+    #[test]
+    fn most_specific_wins() {
+        let accept = accept("text/*;q=0.5, text/html;level=1, */*;q=0.1");
+        assert_eq!(
+            accept.quality_of("text/html"),
+            Some(1000_u16.into_quality())
+        );
+        assert_eq!(accept.quality_of("text/css"), Some(500_u16.into_quality()));
+        assert_eq!(accept.quality_of("image/png"), Some(100_u16.into_quality()));
+        assert_eq!(accept.quality_of("image"), None);
+    }
+
+    #[test]
+    fn refused() {
+        let accept = accept("text/html;q=0, application/json");
+        assert!(!accept.lists("text/html"));
+        assert!(accept.lists("APPLICATION/JSON"));
+        assert_eq!(accept.quality_of("text/html"), Some(Quality::ZERO));
+        assert_eq!(accept.quality_of("text/css"), None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::util::encoding::Encoding;
 use crate::util::flat_csv::FlatCsv;
-use crate::util::quality::QualityValue;
+use crate::util::quality::{Quality, QualityValue};
 use axum::http;
 use headers_core::Error;
 use http::HeaderValue;
@@ -57,8 +57,13 @@ impl AcceptEncoding {
         self.0.iter().flat_map(|s| s.parse().ok())
     }
 
+    /// The coding the client prefers the most, codings refused with `q=0` and
+    /// the `*` wildcard are never chosen.
     pub fn choose(&self) -> Encoding {
-        let mut quality_values = self.iter().collect::<Vec<_>>();
+        let mut quality_values = self
+            .iter()
+            .filter(|q| q.quality() > Quality::ZERO && !is_wildcard(q.value()))
+            .collect::<Vec<_>>();
         quality_values.sort_by_key(|q| std::cmp::Reverse(q.quality()));
         if let Some(encoding) = quality_values.first() {
             encoding.value().clone()
@@ -67,18 +72,94 @@ impl AcceptEncoding {
         }
     }
 
-    pub fn choose_by(&self, accept_encoding: &AcceptEncoding) -> Encoding {
-        let mut choose_values = accept_encoding.iter().collect::<Vec<_>>();
-        choose_values.sort_by_key(|q| std::cmp::Reverse(q.quality()));
-        let mut quality_values = self.iter().collect::<Vec<_>>();
-        quality_values.sort_by_key(|q| std::cmp::Reverse(q.quality()));
-        for v in choose_values {
-            if let Some(v) = quality_values.iter().find(|q| (*q).value() == v.value()) {
-                return v.value().clone();
+    /// The quality the client gives to a coding, either listed explicitly or
+    /// through the `*` wildcard, `None` if the coding isn't mentioned at all.
+    pub fn quality_of(&self, encoding: &Encoding) -> Option<Quality> {
+        let mut wildcard = None;
+        for quality_value in self.iter() {
+            if quality_value.value() == encoding {
+                return Some(quality_value.quality());
+            }
+            if is_wildcard(quality_value.value()) {
+                wildcard = Some(quality_value.quality());
             }
         }
-        Encoding::Identity
+        wildcard
+    }
+
+    /// Whether the client accepts the coding, `identity` is acceptable unless
+    /// it is refused explicitly or through `*;q=0`.
+    pub fn accepts(&self, encoding: &Encoding) -> bool {
+        match self.quality_of(encoding) {
+            Some(quality) => quality > Quality::ZERO,
+            None => *encoding == Encoding::Identity,
+        }
     }
+
+    /// Chooses the coding of the response among the codings the server
+    /// supports, following
+    /// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-12.5.3):
+    ///
+    /// * a coding listed with `q=0` is not acceptable, `*` applies to every
+    ///   coding that isn't listed explicitly,
+    /// * the coding with the highest client quality wins, ties are broken by
+    ///   the server quality and then by the order of `supported`,
+    /// * `identity` is implicitly acceptable, but ranks below every coding the
+    ///   client listed, unless it is listed or refused itself.
+    ///
+    /// Returns `None` when nothing is acceptable, in which case the server
+    /// should answer `406 Not Acceptable`.
+    pub fn negotiate(&self, supported: &AcceptEncoding) -> Option<Encoding> {
+        let mut best: Option<(Quality, Quality, Encoding)> = None;
+        for server in supported.iter() {
+            if *server.value() == Encoding::Identity {
+                continue;
+            }
+            let Some(client_quality) = self.quality_of(server.value()) else {
+                continue;
+            };
+            if client_quality == Quality::ZERO {
+                continue;
+            }
+            let better = best.as_ref().is_none_or(|(best_client, best_server, _)| {
+                (client_quality, server.quality()) > (*best_client, *best_server)
+            });
+            if better {
+                best = Some((client_quality, server.quality(), server.value().clone()));
+            }
+        }
+        match self.quality_of(&Encoding::Identity) {
+            Some(quality) if quality == Quality::ZERO => best.map(|(_, _, encoding)| encoding),
+            Some(quality) => {
+                let server_quality = supported
+                    .iter()
+                    .find(|q| *q.value() == Encoding::Identity)
+                    .map(|q| q.quality())
+                    .unwrap_or(Quality::ZERO);
+                match best {
+                    Some((best_client, best_server, encoding))
+                        if (best_client, best_server) >= (quality, server_quality) =>
+                    {
+                        Some(encoding)
+                    }
+                    _ => Some(Encoding::Identity),
+                }
+            }
+            None => Some(best.map_or(Encoding::Identity, |(_, _, encoding)| encoding)),
+        }
+    }
+
+    /// Same as [`AcceptEncoding::negotiate`], but falls back to `identity`
+    /// when nothing is acceptable.
+    pub fn choose_by(&self, accept_encoding: &AcceptEncoding) -> Encoding {
+        self.negotiate(accept_encoding)
+            .unwrap_or(Encoding::Identity)
+    }
+}
+
+/// `*` matches any available coding not explicitly listed in the header field.
+fn is_wildcard(encoding: &Encoding) -> bool {
+    matches!(encoding, Encoding::Ext(ext) if ext == "*")
 }
 
 impl From<HeaderValue> for AcceptEncoding {
@@ -105,7 +186,7 @@ impl FromIterator<QualityValue<Encoding>> for AcceptEncoding {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::encoding::Encoding::{Ext, Gzip, Identity, Zstd};
+    use crate::util::encoding::Encoding::{Brotli, Ext, Gzip, Identity, Zstd};
     use crate::util::quality::{IntoQuality, QualityValue};
     use headers::HeaderMapExt;
 
@@ -184,8 +265,74 @@ mod tests {
         ]
         .into_iter()
         .collect();
+        // The client prefers zstd, the server preference only breaks ties
         let encoding = accept.choose_by(&support_accept_encoding);
-        assert_eq!(encoding, Identity);
+        assert_eq!(encoding, Zstd);
+    }
+
+    fn negotiate(accept: &str, supported: &[(Encoding, u16)]) -> Option<Encoding> {
+        let supported: AcceptEncoding = supported
+            .iter()
+            .map(|(encoding, q)| QualityValue::new(encoding.clone(), q.into_quality()))
+            .collect();
+        test_decode::<AcceptEncoding>(&[accept])
+            .unwrap()
+            .negotiate(&supported)
+    }
+
+    #[test]
+    fn negotiate_refusals() {
+        let supported = [(Zstd, 1000), (Brotli, 800), (Gzip, 600)];
+        assert_eq!(negotiate("zstd;q=0, br", &supported), Some(Brotli));
+        assert_eq!(negotiate("zstd;q=0, br;q=0", &supported), Some(Identity));
+        assert_eq!(negotiate("zstd;q=0, identity;q=0", &supported), None);
+        assert_eq!(negotiate("compress, identity;q=0", &supported), None);
+        assert_eq!(negotiate("*;q=0", &supported), None);
+        assert_eq!(negotiate("*;q=0, identity", &supported), Some(Identity));
+    }
+
+    #[test]
+    fn negotiate_wildcard() {
+        let supported = [(Zstd, 1000), (Brotli, 800), (Gzip, 600)];
+        assert_eq!(negotiate("*", &supported), Some(Zstd));
+        assert_eq!(negotiate("gzip, *;q=0.5", &supported), Some(Gzip));
+        assert_eq!(negotiate("zstd;q=0, *", &supported), Some(Brotli));
+        assert_eq!(negotiate("*;q=0.5, identity", &supported), Some(Identity));
+    }
+
+    #[test]
+    fn negotiate_ties() {
+        let supported = [(Zstd, 1000), (Brotli, 800), (Gzip, 600)];
+        assert_eq!(negotiate("gzip, br", &supported), Some(Brotli));
+        assert_eq!(negotiate("gzip, br;q=0.9", &supported), Some(Gzip));
+        assert_eq!(
+            negotiate("gzip;q=0.5, identity;q=0.5", &supported),
+            Some(Gzip)
+        );
+        assert_eq!(
+            negotiate("gzip;q=0.4, identity;q=0.5", &supported),
+            Some(Identity)
+        );
+    }
+
+    #[test]
+    fn negotiate_identity() {
+        let supported = [(Zstd, 1000), (Brotli, 800)];
+        // An empty header means only identity is acceptable
+        assert_eq!(negotiate("", &supported), Some(Identity));
+        assert_eq!(negotiate("compress", &supported), Some(Identity));
+        assert_eq!(negotiate("gzip;q=0.1", &[(Gzip, 600)]), Some(Gzip));
+        assert_eq!(negotiate("GZIP, x-gzip", &[(Gzip, 600)]), Some(Gzip));
+    }
+
+    #[test]
+    fn accepts() {
+        let accept = test_decode::<AcceptEncoding>(&["br;q=0.5, identity;q=0"]).unwrap();
+        assert!(accept.accepts(&Brotli));
+        assert!(!accept.accepts(&Identity));
+        assert!(!accept.accepts(&Zstd));
+        let accept = test_decode::<AcceptEncoding>(&["br"]).unwrap();
+        assert!(accept.accepts(&Identity));
     }
 
     #[test]
@@ -0,0 +1,63 @@
+use crate::derive_header;
+use crate::util::flat_csv::FlatCsv;
+use http::HeaderValue;
+
+/// `Accept-Ranges` header, defined in [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-14.3)
+///
+/// The `Accept-Ranges` header field allows a server to indicate that it
+/// supports range requests for the target resource.
+///
+/// # ABNF
+///
+/// ```text
+/// Accept-Ranges     = acceptable-ranges
+/// acceptable-ranges = 1#range-unit / "none"
+/// ```
+///
+/// # Example values
+/// * `bytes`
+/// * `none`
+/// * `unknown-unit`
+///
+/// # Examples
+///
+/// ```
+/// use headers::HeaderMapExt;
+/// use server::accept_ranges::AcceptRanges;
+///
+/// let mut headers = http::HeaderMap::new();
+///
+/// headers.typed_insert(AcceptRanges::bytes());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct AcceptRanges(FlatCsv);
+
+derive_header! {
+    AcceptRanges(_),
+    name: ACCEPT_RANGES
+}
+
+const ACCEPT_RANGES_BYTES: &str = "bytes";
+const ACCEPT_RANGES_NONE: &str = "none";
+
+impl AcceptRanges {
+    /// A constructor to easily create the common `Accept-Ranges: bytes` header.
+    pub fn bytes() -> Self {
+        AcceptRanges(HeaderValue::from_static(ACCEPT_RANGES_BYTES).into())
+    }
+
+    /// Check if the unit is `bytes`.
+    pub fn is_bytes(&self) -> bool {
+        self.0.value == ACCEPT_RANGES_BYTES
+    }
+
+    /// A constructor to easily create the common `Accept-Ranges: none` header.
+    pub fn none() -> Self {
+        AcceptRanges(HeaderValue::from_static(ACCEPT_RANGES_NONE).into())
+    }
+
+    /// Check if the unit is `none`.
+    pub fn is_none(&self) -> bool {
+        self.0.value == ACCEPT_RANGES_NONE
+    }
+}
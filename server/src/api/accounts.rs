@@ -0,0 +1,688 @@
+use crate::api::ApiError;
+use crate::api::auth::{
+    DEACTIVATED, blocking, hash_password, hash_token, random_bytes, revoke_user, unix_now,
+};
+use crate::api::mailer::{Mail, Mailer};
+use crate::api::store::Store;
+use crate::api::throttle::LoginThrottle;
+use crate::api::types::{PasswordResetParams, RegisterParams, ResetPasswordParams, VerifyParams};
+use crate::api_response::ApiResponse;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{ConnectInfo, State};
+use axum::{Extension, Json};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+pub const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The role of the registered users.
+pub const DEFAULT_ROLE: &str = "R_USER";
+
+/// The lengths of the user names and the passwords, like in the register
+/// view of the frontend.
+const USER_NAME_LENGTH: RangeInclusive<usize> = 3..=20;
+const PASSWORD_MIN_LENGTH: usize = 6;
+
+const VERIFY: &str = "verify";
+const RESET: &str = "reset";
+
+#[derive(Clone, Debug)]
+pub struct AccountOptions {
+    /// How long a token mailed can be used.
+    pub token_lifetime: Duration,
+}
+
+impl Default for AccountOptions {
+    fn default() -> Self {
+        AccountOptions {
+            token_lifetime: DEFAULT_TOKEN_LIFETIME,
+        }
+    }
+}
+
+/// A token to mail to a user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailToken {
+    pub user_name: String,
+    pub email: String,
+    pub token: String,
+}
+
+/// Creates a user who can log in once the email address is verified with
+/// the token, with the [`DEFAULT_ROLE`]. The users whose tokens expired
+/// before they were verified are deleted, so their names can be taken again.
+///
+/// The password of the params is hashed by the caller, before the [`Store`]
+/// is locked, as Argon2 is slow on purpose.
+pub fn register(
+    connection: &mut Connection,
+    params: &RegisterParams,
+    password_hash: &str,
+    now: u64,
+    lifetime: Duration,
+) -> Result<EmailToken, ApiError> {
+    let user_name = params.user_name.trim();
+    if !USER_NAME_LENGTH.contains(&user_name.chars().count()) {
+        return Err(ApiError::BadRequest(format!(
+            "The user name must have {} to {} characters",
+            USER_NAME_LENGTH.start(),
+            USER_NAME_LENGTH.end()
+        )));
+    }
+    validate_password(&params.password)?;
+    let email = params.email.trim();
+    if email.parse::<lettre::Address>().is_err() {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid email address {email:?}"
+        )));
+    }
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "DELETE FROM users WHERE NOT email_verified AND NOT EXISTS \
+         (SELECT 1 FROM email_tokens t WHERE t.user_id = users.id AND t.expires_at > ?1)",
+        [now],
+    )?;
+    transaction.execute(
+        "INSERT INTO users (user_name, email, password_hash, email_verified, create_by, \
+         update_by) VALUES (?1, ?2, ?3, 0, ?1, ?1)",
+        params![user_name, email, password_hash],
+    )?;
+    let user_id = transaction.last_insert_rowid() as u64;
+    transaction.execute(
+        "INSERT INTO user_roles (user_id, role_id) SELECT ?1, id FROM roles WHERE code = ?2",
+        params![user_id, DEFAULT_ROLE],
+    )?;
+    let token = insert_token(&transaction, user_id, VERIFY, now, lifetime)?;
+    transaction.commit()?;
+    Ok(EmailToken {
+        user_name: user_name.to_owned(),
+        email: email.to_owned(),
+        token,
+    })
+}
+
+/// Verifies the email address of the user the token was mailed to.
+pub fn verify_email(connection: &mut Connection, token: &str, now: u64) -> Result<(), ApiError> {
+    let transaction = connection.transaction()?;
+    let user_id = take_token(&transaction, token, VERIFY, now)?;
+    transaction.execute(
+        "UPDATE users SET email_verified = 1 WHERE id = ?1",
+        [user_id],
+    )?;
+    transaction.commit()?;
+    Ok(())
+}
+
+/// A token to reset the password of the active user with this user name,
+/// or else with this verified email address. `None` when there is no such
+/// user, which isn't told to the requester.
+pub fn request_reset(
+    connection: &Connection,
+    user_name: &str,
+    now: u64,
+    lifetime: Duration,
+) -> Result<Option<EmailToken>, ApiError> {
+    let user = connection
+        .query_row(
+            "SELECT id, user_name, email FROM users WHERE (user_name = ?1 OR email = ?1 \
+             COLLATE NOCASE) AND email != '' AND email_verified AND status != ?2 \
+             ORDER BY user_name = ?1 DESC, id LIMIT 1",
+            params![user_name.trim(), DEACTIVATED],
+            |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((user_id, user_name, email)) = user else {
+        return Ok(None);
+    };
+    let token = insert_token(connection, user_id, RESET, now, lifetime)?;
+    Ok(Some(EmailToken {
+        user_name,
+        email,
+        token,
+    }))
+}
+
+/// Replaces the password of the user the token was mailed to, and ends the
+/// sessions of the user. The name of the user. The password of the params is
+/// hashed by the caller, like in [`register`].
+pub fn reset_password(
+    connection: &mut Connection,
+    params: &ResetPasswordParams,
+    password_hash: &str,
+    now: u64,
+) -> Result<String, ApiError> {
+    validate_password(&params.password)?;
+    let transaction = connection.transaction()?;
+    let user_id = take_token(&transaction, &params.token, RESET, now)?;
+    // The token was mailed to the address, which is verified by the way.
+    let user_name = transaction.query_row(
+        "UPDATE users SET password_hash = ?2, email_verified = 1, update_by = user_name, \
+         update_time = strftime('%Y-%m-%d %H:%M:%S', 'now') WHERE id = ?1 RETURNING user_name",
+        params![user_id, password_hash],
+        |row| row.get(0),
+    )?;
+    revoke_user(&transaction, user_id)?;
+    transaction.commit()?;
+    Ok(user_name)
+}
+
+fn validate_password(password: &str) -> Result<(), ApiError> {
+    if password.chars().count() < PASSWORD_MIN_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "The password must have at least {PASSWORD_MIN_LENGTH} characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Stores a new token of the user for the purpose, which replaces the
+/// previous one. The expired tokens are cleaned up on the way.
+fn insert_token(
+    connection: &Connection,
+    user_id: u64,
+    purpose: &str,
+    now: u64,
+    lifetime: Duration,
+) -> rusqlite::Result<String> {
+    connection.execute(
+        "DELETE FROM email_tokens WHERE expires_at <= ?1 OR (user_id = ?2 AND purpose = ?3)",
+        params![now, user_id, purpose],
+    )?;
+    let token = URL_SAFE_NO_PAD.encode(random_bytes::<24>());
+    connection.execute(
+        "INSERT INTO email_tokens (user_id, purpose, token_hash, expires_at) \
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            user_id,
+            purpose,
+            hash_token(&token),
+            now.saturating_add(lifetime.as_secs())
+        ],
+    )?;
+    Ok(token)
+}
+
+/// Uses up the token, the id of its user.
+fn take_token(
+    connection: &Connection,
+    token: &str,
+    purpose: &str,
+    now: u64,
+) -> Result<u64, ApiError> {
+    let taken = connection
+        .query_row(
+            "DELETE FROM email_tokens WHERE token_hash = ?1 AND purpose = ?2 \
+             RETURNING user_id, expires_at",
+            params![hash_token(token), purpose],
+            |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)),
+        )
+        .optional()?;
+    match taken {
+        Some((user_id, expires_at)) if now < expires_at => Ok(user_id),
+        _ => Err(ApiError::BadRequest(
+            "The token is invalid or expired".to_owned(),
+        )),
+    }
+}
+
+/// The self-service registration and password reset, whose tokens are
+/// mailed by the [`Mailer`].
+///
+/// Each request which mails a token counts like a failed login, per email
+/// address or user name and per IP address, in a throttle apart from the
+/// logins, so that the mails can't be sent in bulk.
+#[derive(Debug)]
+pub struct Accounts {
+    store: Arc<Store>,
+    throttle: Arc<LoginThrottle>,
+    mail_throttle: LoginThrottle,
+    mailer: Arc<dyn Mailer>,
+    options: AccountOptions,
+}
+
+impl Accounts {
+    pub fn new(
+        store: Arc<Store>,
+        throttle: Arc<LoginThrottle>,
+        mailer: Arc<dyn Mailer>,
+        options: AccountOptions,
+    ) -> Self {
+        Accounts {
+            store,
+            mail_throttle: throttle.separate(),
+            throttle,
+            mailer,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &AccountOptions {
+        &self.options
+    }
+
+    /// Counts a request which mails a token to the address or the user, or
+    /// refuses it while they or the IP address have to wait.
+    fn throttle_mail(&self, target: &str, ip: Option<IpAddr>) -> Result<(), ApiError> {
+        let attempt = self
+            .mail_throttle
+            .attempt(target, ip)
+            .map_err(|error| error.error)?;
+        attempt.count();
+        Ok(())
+    }
+
+    /// Mails the token in the background, so that the answer takes as long
+    /// whether a user is found or not. A failure is only logged.
+    fn mail(&self, token: EmailToken, subject: &str, purpose: &str) {
+        let minutes = self.options.token_lifetime.as_secs().div_ceil(60);
+        let mail = Mail {
+            to: token.email,
+            subject: subject.to_owned(),
+            body: format!(
+                "Hello {},\n\nThe token to {purpose} is:\n\n{}\n\nIt expires in {minutes} \
+                 minutes. If you didn't ask for it, ignore this mail.",
+                token.user_name, token.token
+            ),
+        };
+        let mailer = Arc::clone(&self.mailer);
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = mailer.send(&mail) {
+                error!("Failed to mail {}: {error}", mail.to);
+            }
+        });
+    }
+}
+
+/// The handler of `POST /api/auth/register`, throttled per email address
+/// and per IP address.
+pub async fn register_user(
+    State(accounts): State<Arc<Accounts>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    params: Result<Json<RegisterParams>, JsonRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Json(params) = params?;
+    let ip = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    accounts.throttle_mail(&params.email, ip)?;
+    let password = params.password.clone();
+    let password_hash = blocking(move || hash_password(&password)).await;
+    let lifetime = accounts.options.token_lifetime;
+    let token = accounts
+        .store
+        .run(move |connection| register(connection, &params, &password_hash, unix_now(), lifetime))
+        .await?;
+    accounts.mail(
+        token,
+        "Verify your email address",
+        "verify your email address",
+    );
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `POST /api/auth/register/verify`.
+pub async fn verify_user(
+    State(accounts): State<Arc<Accounts>>,
+    params: Result<Json<VerifyParams>, JsonRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Json(params) = params?;
+    accounts
+        .store
+        .run(move |connection| verify_email(connection, &params.token, unix_now()))
+        .await?;
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `POST /api/auth/password-reset`, which succeeds whether
+/// the user exists or not. It is throttled per user name or email address
+/// asked for, whether it exists too, and per IP address.
+pub async fn request_password_reset(
+    State(accounts): State<Arc<Accounts>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    params: Result<Json<PasswordResetParams>, JsonRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Json(params) = params?;
+    let ip = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    accounts.throttle_mail(&params.user_name, ip)?;
+    let lifetime = accounts.options.token_lifetime;
+    let token = accounts
+        .store
+        .run(move |connection| request_reset(connection, &params.user_name, unix_now(), lifetime))
+        .await?;
+    if let Some(token) = token {
+        accounts.mail(token, "Reset your password", "reset your password");
+    }
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `POST /api/auth/password-reset/confirm`, which also ends
+/// the lockout of the account.
+pub async fn confirm_password_reset(
+    State(accounts): State<Arc<Accounts>>,
+    params: Result<Json<ResetPasswordParams>, JsonRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Json(params) = params?;
+    let password = params.password.clone();
+    let password_hash = blocking(move || hash_password(&password)).await;
+    let user_name = accounts
+        .store
+        .run(move |connection| reset_password(connection, &params, &password_hash, unix_now()))
+        .await?;
+    accounts.throttle.unlock(&user_name);
+    Ok(ApiResponse::success(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::auth::{Auth, AuthOptions};
+    use crate::api::mailer::MailError;
+    use crate::api::throttle::ThrottleOptions;
+    use crate::api::types::LoginParams;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::post;
+    use http::{StatusCode, header};
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    const NOW: u64 = 1_700_000_000;
+    const LIFETIME: Duration = DEFAULT_TOKEN_LIFETIME;
+
+    fn register_params(user_name: &str) -> RegisterParams {
+        RegisterParams {
+            user_name: user_name.to_owned(),
+            password: "secret".to_owned(),
+            email: format!("{user_name}@example.com"),
+        }
+    }
+
+    fn login(connection: &Connection, user_name: &str, password: &str) -> Result<(), ApiError> {
+        let params = LoginParams {
+            user_name: user_name.to_owned(),
+            password: password.to_owned(),
+        };
+        let auth = Auth::random(AuthOptions::default());
+        auth.login(connection, &params, NOW).map(|_| ())
+    }
+
+    #[test]
+    fn registration() {
+        let secret = hash_password("secret");
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let token = register(
+            &mut connection,
+            &register_params("Alice"),
+            &secret,
+            NOW,
+            LIFETIME,
+        )
+        .unwrap();
+        assert_eq!(token.email, "Alice@example.com");
+        assert!(matches!(
+            login(&connection, "alice", "secret"),
+            Err(ApiError::Forbidden)
+        ));
+        assert!(matches!(
+            register(
+                &mut connection,
+                &register_params("alice"),
+                &secret,
+                NOW,
+                LIFETIME
+            ),
+            Err(ApiError::Conflict(_))
+        ));
+        verify_email(&mut connection, &token.token, NOW + 1).unwrap();
+        login(&connection, "alice", "secret").unwrap();
+        // A token is used once.
+        assert!(matches!(
+            verify_email(&mut connection, &token.token, NOW + 1),
+            Err(ApiError::BadRequest(_))
+        ));
+        let roles: String = connection
+            .query_row(
+                "SELECT r.code FROM user_roles ur JOIN roles r ON r.id = ur.role_id \
+                 JOIN users u ON u.id = ur.user_id WHERE u.user_name = 'Alice'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(roles, DEFAULT_ROLE);
+    }
+
+    #[test]
+    fn expiry() {
+        let secret = hash_password("secret");
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let token = register(
+            &mut connection,
+            &register_params("bob"),
+            &secret,
+            NOW,
+            LIFETIME,
+        )
+        .unwrap();
+        let expired = NOW + LIFETIME.as_secs();
+        assert!(matches!(
+            verify_email(&mut connection, &token.token, expired),
+            Err(ApiError::BadRequest(_))
+        ));
+        // The name of a user never verified can be taken again.
+        register(
+            &mut connection,
+            &register_params("bob"),
+            &secret,
+            expired,
+            LIFETIME,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let secret = hash_password("secret");
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let mut try_register =
+            |params: &RegisterParams| register(&mut connection, params, &secret, NOW, LIFETIME);
+        assert!(try_register(&register_params("ab")).is_err());
+        assert!(
+            try_register(&RegisterParams {
+                password: "12345".to_owned(),
+                ..register_params("carol")
+            })
+            .is_err()
+        );
+        assert!(
+            try_register(&RegisterParams {
+                email: "carol".to_owned(),
+                ..register_params("carol")
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn password_reset() {
+        let new_secret = hash_password("new secret");
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        assert_eq!(
+            request_reset(&connection, "nobody", NOW, LIFETIME).unwrap(),
+            None
+        );
+        // The reset is asked with the user name or the email address.
+        let first = request_reset(&connection, "admin", NOW, LIFETIME)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.email, "admin@company.com");
+        let token = request_reset(&connection, "ADMIN@company.com", NOW, LIFETIME)
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.user_name, "Admin");
+
+        let params = |token: &EmailToken| ResetPasswordParams {
+            token: token.token.clone(),
+            password: "new secret".to_owned(),
+        };
+        // A new token replaces the previous one.
+        assert!(reset_password(&mut connection, &params(&first), &new_secret, NOW).is_err());
+        assert_eq!(
+            reset_password(&mut connection, &params(&token), &new_secret, NOW).unwrap(),
+            "Admin"
+        );
+        assert!(matches!(
+            login(&connection, "Admin", "123456"),
+            Err(ApiError::BadRequest(_))
+        ));
+        login(&connection, "Admin", "new secret").unwrap();
+
+        let token = request_reset(&connection, "admin", NOW, LIFETIME)
+            .unwrap()
+            .unwrap();
+        assert!(
+            reset_password(
+                &mut connection,
+                &params(&token),
+                &new_secret,
+                NOW + LIFETIME.as_secs()
+            )
+            .is_err()
+        );
+
+        // A deactivated user can't reset the password.
+        connection
+            .execute("UPDATE users SET status = ?1 WHERE id = 3", [DEACTIVATED])
+            .unwrap();
+        assert_eq!(
+            request_reset(&connection, "User", NOW, LIFETIME).unwrap(),
+            None
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct MemoryMailer(Mutex<Vec<Mail>>);
+
+    impl Mailer for MemoryMailer {
+        fn send(&self, mail: &Mail) -> Result<(), MailError> {
+            self.0.lock().unwrap().push(mail.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn handlers() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let mailer = Arc::new(MemoryMailer::default());
+        let accounts = Accounts::new(
+            Arc::clone(&store),
+            Arc::new(LoginThrottle::new(ThrottleOptions::default())),
+            Arc::clone(&mailer) as Arc<dyn Mailer>,
+            AccountOptions::default(),
+        );
+        let app = Router::new()
+            .route("/api/auth/register", post(register_user))
+            .route("/api/auth/register/verify", post(verify_user))
+            .with_state(Arc::new(accounts));
+        let post = |path: &str, body: serde_json::Value| {
+            let request = http::Request::post(path)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        let params = serde_json::json!({
+            "userName": "dave",
+            "password": "secret",
+            "email": "dave@example.com",
+        });
+        assert_eq!(post("/api/auth/register", params).await, StatusCode::OK);
+        let mail = loop {
+            if let Some(mail) = mailer.0.lock().unwrap().pop() {
+                break mail;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(mail.to, "dave@example.com");
+        let token = mail.body.lines().nth(4).unwrap();
+        let params = serde_json::json!({ "token": token });
+        assert_eq!(
+            post("/api/auth/register/verify", params.clone()).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            post("/api/auth/register/verify", params).await,
+            StatusCode::BAD_REQUEST
+        );
+        login(&store.lock(), "dave", "secret").unwrap();
+    }
+
+    #[tokio::test]
+    async fn throttled() {
+        let throttle = LoginThrottle::new(ThrottleOptions {
+            base_delay: Duration::from_secs(60),
+            ..ThrottleOptions::default()
+        });
+        let accounts = Accounts::new(
+            Arc::new(Store::open_in_memory().unwrap()),
+            Arc::new(throttle),
+            Arc::new(MemoryMailer::default()),
+            AccountOptions::default(),
+        );
+        let app = Router::new()
+            .route("/api/auth/register", post(register_user))
+            .route("/api/auth/password-reset", post(request_password_reset))
+            .with_state(Arc::new(accounts));
+        let post = |path: &str, body: serde_json::Value, ip: [u8; 4]| {
+            let request = http::Request::post(path)
+                .header(header::CONTENT_TYPE, "application/json")
+                .extension(ConnectInfo(SocketAddr::from((ip, 50000))))
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        let reset = |user_name: &str, ip: [u8; 4]| {
+            let params = serde_json::json!({ "userName": user_name });
+            post("/api/auth/password-reset", params, ip)
+        };
+        let ip = [192, 168, 1, 7];
+        assert_eq!(reset("admin", ip).await, StatusCode::OK);
+        assert_eq!(reset("Admin", ip).await, StatusCode::TOO_MANY_REQUESTS);
+        // Whether the user exists isn't told.
+        assert_eq!(reset("nobody", ip).await, StatusCode::OK);
+        assert_eq!(reset("nobody", ip).await, StatusCode::TOO_MANY_REQUESTS);
+
+        let register = |user_name: &str| {
+            let params = serde_json::json!({
+                "userName": user_name,
+                "password": "secret",
+                "email": "erin@example.com",
+            });
+            post("/api/auth/register", params, ip)
+        };
+        assert_eq!(register("erin").await, StatusCode::OK);
+        assert_eq!(register("erin2").await, StatusCode::TOO_MANY_REQUESTS);
+
+        // The IP address waits after as many requests as lock an account.
+        for user_name in ["a", "b", "c"] {
+            assert_eq!(reset(user_name, ip).await, StatusCode::OK);
+        }
+        assert_eq!(reset("d", ip).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(reset("d", [192, 168, 1, 8]).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,736 @@
+use crate::api::ApiError;
+use crate::api::jwt::{Claims, Jwt};
+use crate::api::rbac::Access;
+use crate::api::store::Store;
+use crate::api::throttle::{LoginError, LoginThrottle};
+use crate::api::types::{LoginParams, LoginResponse, RefreshParams, UserInfo};
+use crate::api_response::ApiResponse;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{ConnectInfo, FromRequestParts, State};
+use axum::{Extension, Json};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http::request::Parts;
+use http::{HeaderMap, header};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime};
+use tracing::error;
+
+pub const DEFAULT_ACCESS_TOKEN_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+pub const DEFAULT_REFRESH_TOKEN_LIFETIME: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The user status of the frontend whose accounts can't log in, `注销`.
+pub const DEACTIVATED: &str = "4";
+
+/// A hash to verify the password against when the user doesn't exist, so
+/// that the time of the answer doesn't tell whether it does.
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password(""));
+
+#[derive(Clone, Debug)]
+pub struct AuthOptions {
+    /// How long an access token is accepted. The frontend logs out when it
+    /// gets a `401`, so this is the length of a session without a refresh.
+    pub access_token_lifetime: Duration,
+    /// How long a refresh token can be exchanged, renewed on each exchange.
+    pub refresh_token_lifetime: Duration,
+}
+
+impl Default for AuthOptions {
+    fn default() -> Self {
+        AuthOptions {
+            access_token_lifetime: DEFAULT_ACCESS_TOKEN_LIFETIME,
+            refresh_token_lifetime: DEFAULT_REFRESH_TOKEN_LIFETIME,
+        }
+    }
+}
+
+/// The authentication of the API.
+///
+/// A login issues a signed access token, a [`Jwt`], and a refresh token
+/// stored hashed in the database. A refresh token is used once: exchanging
+/// it revokes it and issues a new pair. The refresh tokens descending from a
+/// login form a family, and when a revoked token is presented again, e.g.
+/// stolen and used by two parties, the whole family is revoked.
+#[derive(Debug)]
+pub struct Auth {
+    jwt: Jwt,
+    options: AuthOptions,
+}
+
+/// The session issued to a user.
+struct Session<'a> {
+    user_id: u64,
+    user_name: &'a str,
+    family: &'a str,
+    token_generation: u64,
+}
+
+/// The account a user logs in to, read before the password is verified.
+#[derive(Debug)]
+struct Credentials {
+    user_id: u64,
+    user_name: String,
+    password_hash: String,
+    status: String,
+    email_verified: bool,
+    token_generation: u64,
+}
+
+impl Auth {
+    pub fn new(secret: Vec<u8>, options: AuthOptions) -> Self {
+        Auth {
+            jwt: Jwt::new(secret),
+            options,
+        }
+    }
+
+    /// With a random secret, the tokens are invalidated when the server
+    /// restarts.
+    pub fn random(options: AuthOptions) -> Self {
+        Auth::new(random_bytes::<32>().to_vec(), options)
+    }
+
+    pub fn options(&self) -> &AuthOptions {
+        &self.options
+    }
+
+    /// The claims of a valid access token.
+    pub fn verify(&self, token: &str, now: u64) -> Result<Claims, ApiError> {
+        self.jwt
+            .verify(token, now)
+            .map_err(|_| ApiError::Unauthorized)
+    }
+
+    /// Checks the password and opens a session, on a connection the caller
+    /// holds. The handler of `POST /api/auth/login` doesn't hold the [`Store`]
+    /// while the password is verified instead, see [`login`].
+    pub fn login(
+        &self,
+        connection: &Connection,
+        params: &LoginParams,
+        now: u64,
+    ) -> Result<LoginResponse, ApiError> {
+        let credentials = credentials(connection, &params.user_name)?;
+        let credentials = check_credentials(credentials, &params.password)?;
+        self.open_session(connection, &credentials, now)
+    }
+
+    /// Opens a session once the credentials are checked. The expired refresh
+    /// tokens are cleaned up on the way.
+    fn open_session(
+        &self,
+        connection: &Connection,
+        credentials: &Credentials,
+        now: u64,
+    ) -> Result<LoginResponse, ApiError> {
+        connection.execute("DELETE FROM refresh_tokens WHERE expires_at <= ?1", [now])?;
+        let family = URL_SAFE_NO_PAD.encode(random_bytes::<16>());
+        let session = Session {
+            user_id: credentials.user_id,
+            user_name: &credentials.user_name,
+            family: &family,
+            token_generation: credentials.token_generation,
+        };
+        self.issue(connection, &session, now)
+    }
+
+    /// Exchanges a refresh token for a new pair of tokens.
+    pub fn refresh(
+        &self,
+        connection: &mut Connection,
+        refresh_token: &str,
+        now: u64,
+    ) -> Result<LoginResponse, ApiError> {
+        let transaction = connection.transaction()?;
+        let token = transaction
+            .query_row(
+                "SELECT t.id, t.family, t.expires_at, t.revoked, u.id, u.user_name, u.status, \
+                 u.token_generation FROM refresh_tokens t JOIN users u ON u.id = t.user_id \
+                 WHERE t.token_hash = ?1",
+                [hash_token(refresh_token)],
+                |row| {
+                    Ok((
+                        row.get::<_, u64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u64>(2)?,
+                        row.get::<_, bool>(3)?,
+                        row.get::<_, u64>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, u64>(7)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((id, family, expires_at, revoked, user_id, user_name, status, token_generation)) =
+            token
+        else {
+            return Err(ApiError::Unauthorized);
+        };
+        if revoked || status == DEACTIVATED {
+            revoke_family(&transaction, &family)?;
+            transaction.commit()?;
+            return Err(ApiError::Unauthorized);
+        }
+        if expires_at <= now {
+            return Err(ApiError::Unauthorized);
+        }
+        transaction.execute("UPDATE refresh_tokens SET revoked = 1 WHERE id = ?1", [id])?;
+        let session = Session {
+            user_id,
+            user_name: &user_name,
+            family: &family,
+            token_generation,
+        };
+        let tokens = self.issue(&transaction, &session, now)?;
+        transaction.commit()?;
+        Ok(tokens)
+    }
+
+    fn issue(
+        &self,
+        connection: &Connection,
+        session: &Session,
+        now: u64,
+    ) -> Result<LoginResponse, ApiError> {
+        let refresh_token = URL_SAFE_NO_PAD.encode(random_bytes::<32>());
+        connection.execute(
+            "INSERT INTO refresh_tokens (user_id, family, token_hash, expires_at) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                session.user_id,
+                session.family,
+                hash_token(&refresh_token),
+                now + self.options.refresh_token_lifetime.as_secs()
+            ],
+        )?;
+        let token = self.jwt.sign(&Claims {
+            sub: session.user_id,
+            name: session.user_name.to_owned(),
+            iat: now,
+            exp: now + self.options.access_token_lifetime.as_secs(),
+            generation: session.token_generation,
+        });
+        Ok(LoginResponse {
+            token,
+            refresh_token,
+        })
+    }
+}
+
+fn wrong_password() -> ApiError {
+    ApiError::BadRequest("Wrong user name or password".to_owned())
+}
+
+/// The credentials of the user of the name, if any.
+fn credentials(connection: &Connection, user_name: &str) -> Result<Option<Credentials>, ApiError> {
+    let credentials = connection
+        .query_row(
+            "SELECT id, user_name, password_hash, status, email_verified, token_generation \
+             FROM users WHERE user_name = ?1",
+            [user_name.trim()],
+            |row| {
+                Ok(Credentials {
+                    user_id: row.get(0)?,
+                    user_name: row.get(1)?,
+                    password_hash: row.get(2)?,
+                    status: row.get(3)?,
+                    email_verified: row.get(4)?,
+                    token_generation: row.get(5)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(credentials)
+}
+
+/// Verifies the password, and that the user may log in. Argon2 is slow on
+/// purpose, so it doesn't run under the lock of the [`Store`].
+fn check_credentials(
+    credentials: Option<Credentials>,
+    password: &str,
+) -> Result<Credentials, ApiError> {
+    let Some(credentials) = credentials else {
+        verify_password(password, &DUMMY_HASH);
+        return Err(wrong_password());
+    };
+    if !verify_password(password, &credentials.password_hash) {
+        return Err(wrong_password());
+    }
+    // A registered user logs in once the email address is verified.
+    if credentials.status == DEACTIVATED || !credentials.email_verified {
+        return Err(ApiError::Forbidden);
+    }
+    Ok(credentials)
+}
+
+/// Ends the session of the refresh token, a token that doesn't exist is
+/// ignored.
+pub fn revoke_session(connection: &Connection, refresh_token: &str) -> Result<(), ApiError> {
+    connection.execute(
+        "UPDATE refresh_tokens SET revoked = 1 WHERE family = \
+         (SELECT family FROM refresh_tokens WHERE token_hash = ?1)",
+        [hash_token(refresh_token)],
+    )?;
+    Ok(())
+}
+
+/// Ends every session of the user, e.g. when the password changes. The
+/// access tokens already issued are refused too, see [`check_session`].
+pub fn revoke_user(connection: &Connection, user_id: u64) -> Result<(), ApiError> {
+    connection.execute(
+        "UPDATE refresh_tokens SET revoked = 1 WHERE user_id = ?1",
+        [user_id],
+    )?;
+    connection.execute(
+        "UPDATE users SET token_generation = token_generation + 1 WHERE id = ?1",
+        [user_id],
+    )?;
+    Ok(())
+}
+
+/// Checks that the session of a valid access token hasn't ended since it was
+/// issued: the user still exists, isn't deactivated, and their sessions
+/// weren't revoked.
+pub fn check_session(connection: &Connection, claims: &Claims) -> Result<(), ApiError> {
+    let user = connection
+        .query_row(
+            "SELECT status, token_generation FROM users WHERE id = ?1",
+            [claims.sub],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)),
+        )
+        .optional()?;
+    match user {
+        Some((status, generation)) if status != DEACTIVATED && generation == claims.generation => {
+            Ok(())
+        }
+        _ => Err(ApiError::Unauthorized),
+    }
+}
+
+fn revoke_family(connection: &Connection, family: &str) -> rusqlite::Result<usize> {
+    connection.execute(
+        "UPDATE refresh_tokens SET revoked = 1 WHERE family = ?1",
+        [family],
+    )
+}
+
+/// The `UserInfo` of the frontend, with the codes of the enabled roles of the
+/// user and the marks of the buttons they grant, see [`Access`].
+pub fn user_info(connection: &Connection, user_id: u64) -> Result<UserInfo, ApiError> {
+    let user = connection.query_row(
+        "SELECT id, user_name, email, avatar FROM users WHERE id = ?1",
+        [user_id],
+        |row| {
+            Ok(UserInfo {
+                buttons: Vec::new(),
+                roles: Vec::new(),
+                user_id: row.get(0)?,
+                user_name: row.get(1)?,
+                email: row.get(2)?,
+                avatar: Some(row.get::<_, String>(3)?).filter(|avatar| !avatar.is_empty()),
+            })
+        },
+    )?;
+    let access = Access::load(connection, user_id)?;
+    Ok(UserInfo {
+        buttons: access.buttons(),
+        roles: access.roles().to_vec(),
+        ..user
+    })
+}
+
+/// The PHC string of the Argon2id hash of the password, with a random salt.
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&random_bytes::<16>()).expect("16 bytes are a valid salt");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("the default parameters of Argon2 are valid")
+        .to_string()
+}
+
+/// Runs slow work, e.g. Argon2, on a blocking thread, so that it neither
+/// blocks the runtime nor holds the lock of the [`Store`].
+pub async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
+/// Whether the password matches the PHC string, an empty or a malformed hash
+/// matches nothing.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// The refresh tokens are random, so a fast hash is enough to keep a leaked
+/// database from being usable.
+pub(crate) fn hash_token(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::fill(&mut bytes).expect("the random source of the operating system works");
+    bytes
+}
+
+/// The seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The token of the `Authorization` header, with or without the `Bearer`
+/// scheme. The frontend sends the token alone.
+pub fn authorization_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?.trim();
+    let token = match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        _ => value,
+    };
+    (!token.is_empty()).then_some(token)
+}
+
+/// The user of a request, authenticated by the access token of the
+/// `Authorization` header. A handler taking it answers `401` to the requests
+/// without a valid token, or whose session has ended, see [`check_session`].
+///
+/// The [`Auth`] and the [`Store`] are looked up in the extensions of the
+/// request, so that the handlers of any state can take it. Without them no
+/// token can be verified, so the request is unauthorized too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthUser {
+    pub id: u64,
+    pub name: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let (Some(auth), Some(store)) = (
+            parts.extensions.get::<Arc<Auth>>(),
+            parts.extensions.get::<Arc<Store>>(),
+        ) else {
+            error!("The Auth or the Store extension isn't added to the router");
+            return Err(ApiError::Unauthorized);
+        };
+        let token = authorization_token(&parts.headers).ok_or(ApiError::Unauthorized)?;
+        let claims = auth.verify(token, unix_now())?;
+        let claims = store
+            .run(move |connection| check_session(connection, &claims).map(|()| claims))
+            .await?;
+        Ok(AuthUser {
+            id: claims.sub,
+            name: claims.name,
+        })
+    }
+}
+
+/// The handler of `POST /api/auth/login`, throttled per account and per IP
+/// address by the [`LoginThrottle`].
+pub async fn login(
+    State(store): State<Arc<Store>>,
+    State(auth): State<Arc<Auth>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    params: Result<Json<LoginParams>, JsonRejection>,
+) -> Result<ApiResponse<LoginResponse>, LoginError> {
+    let Json(params) = params.map_err(ApiError::from)?;
+    let ip = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip());
+    let attempt = throttle.attempt(&params.user_name, ip)?;
+    let now = throttle.now();
+    // The password is verified between two runs of the store, not under its
+    // lock.
+    let tokens = async {
+        let user_name = params.user_name;
+        let credentials = store
+            .run(move |connection| credentials(connection, &user_name))
+            .await?;
+        let password = params.password;
+        let credentials = blocking(move || check_credentials(credentials, &password)).await?;
+        store
+            .run(move |connection| auth.open_session(connection, &credentials, now))
+            .await
+    }
+    .await;
+    match tokens {
+        Ok(tokens) => {
+            attempt.succeeded();
+            Ok(ApiResponse::success(tokens))
+        }
+        // Only a wrong user name or password is a guess.
+        Err(error @ ApiError::BadRequest(_)) => Err(attempt.failed(error)),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// The handler of `POST /api/auth/refresh`.
+pub async fn refresh(
+    State(store): State<Arc<Store>>,
+    State(auth): State<Arc<Auth>>,
+    params: Result<Json<RefreshParams>, JsonRejection>,
+) -> Result<ApiResponse<LoginResponse>, ApiError> {
+    let Json(params) = params?;
+    let tokens = store
+        .run(move |connection| auth.refresh(connection, &params.refresh_token, unix_now()))
+        .await?;
+    Ok(ApiResponse::success(tokens))
+}
+
+/// The handler of `POST /api/auth/logout`.
+pub async fn logout(
+    State(store): State<Arc<Store>>,
+    params: Result<Json<RefreshParams>, JsonRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Json(params) = params?;
+    store
+        .run(move |connection| revoke_session(connection, &params.refresh_token))
+        .await?;
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `GET /api/user/info`.
+pub async fn get_user_info(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+) -> Result<ApiResponse<UserInfo>, ApiError> {
+    // The token of a deleted user is refused like an invalid one.
+    let info = store
+        .run(move |connection| match user_info(connection, user.id) {
+            Err(ApiError::NotFound) => Err(ApiError::Unauthorized),
+            info => info,
+        })
+        .await?;
+    Ok(ApiResponse::success(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn login_params(user_name: &str, password: &str) -> LoginParams {
+        LoginParams {
+            user_name: user_name.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    fn passwords() {
+        let hash = hash_password("123456");
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("123456"));
+        assert!(verify_password("123456", &hash));
+        assert!(!verify_password("1234567", &hash));
+        assert!(!verify_password("", ""));
+    }
+
+    #[test]
+    fn login() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let auth = Auth::random(AuthOptions::default());
+        let tokens = auth
+            .login(&connection, &login_params("admin", "123456"), NOW)
+            .unwrap();
+        let claims = auth.verify(&tokens.token, NOW + 1).unwrap();
+        assert_eq!((claims.sub, claims.name.as_str()), (2, "Admin"));
+        assert!(matches!(
+            auth.verify(&tokens.token, NOW + DEFAULT_ACCESS_TOKEN_LIFETIME.as_secs()),
+            Err(ApiError::Unauthorized)
+        ));
+        assert!(matches!(
+            auth.login(&connection, &login_params("Admin", "654321"), NOW),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            auth.login(&connection, &login_params("Nobody", "123456"), NOW),
+            Err(ApiError::BadRequest(_))
+        ));
+        connection
+            .execute("UPDATE users SET status = '4' WHERE id = 2", [])
+            .unwrap();
+        assert!(matches!(
+            auth.login(&connection, &login_params("Admin", "123456"), NOW),
+            Err(ApiError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn rotation() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let auth = Auth::random(AuthOptions::default());
+        let first = auth
+            .login(&connection, &login_params("Super", "123456"), NOW)
+            .unwrap();
+        let second = auth
+            .refresh(&mut connection, &first.refresh_token, NOW + 60)
+            .unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert_eq!(auth.verify(&second.token, NOW + 61).unwrap().iat, NOW + 60);
+
+        // Reusing a rotated token revokes the tokens issued after it too.
+        assert!(matches!(
+            auth.refresh(&mut connection, &first.refresh_token, NOW + 120),
+            Err(ApiError::Unauthorized)
+        ));
+        assert!(matches!(
+            auth.refresh(&mut connection, &second.refresh_token, NOW + 120),
+            Err(ApiError::Unauthorized)
+        ));
+        assert!(matches!(
+            auth.refresh(&mut connection, "unknown", NOW),
+            Err(ApiError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn expiry_and_logout() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let auth = Auth::random(AuthOptions {
+            access_token_lifetime: Duration::from_secs(60),
+            refresh_token_lifetime: Duration::from_secs(600),
+        });
+        let tokens = auth
+            .login(&connection, &login_params("User", "123456"), NOW)
+            .unwrap();
+        assert!(matches!(
+            auth.refresh(&mut connection, &tokens.refresh_token, NOW + 600),
+            Err(ApiError::Unauthorized)
+        ));
+
+        let tokens = auth
+            .login(&connection, &login_params("User", "123456"), NOW)
+            .unwrap();
+        revoke_session(&connection, &tokens.refresh_token).unwrap();
+        assert!(matches!(
+            auth.refresh(&mut connection, &tokens.refresh_token, NOW + 1),
+            Err(ApiError::Unauthorized)
+        ));
+
+        let tokens = auth
+            .login(&connection, &login_params("User", "123456"), NOW)
+            .unwrap();
+        revoke_user(&connection, 3).unwrap();
+        assert!(matches!(
+            auth.refresh(&mut connection, &tokens.refresh_token, NOW + 1),
+            Err(ApiError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn info() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let info = user_info(&connection, 2).unwrap();
+        assert_eq!(info.roles, ["R_ADMIN"]);
+        assert_eq!(info.buttons, ["add", "edit"]);
+        assert_eq!(info.email, "admin@company.com");
+        assert_eq!(info.avatar, None);
+        // A disabled role grants nothing.
+        connection
+            .execute("UPDATE roles SET enabled = 0 WHERE code = 'R_ADMIN'", [])
+            .unwrap();
+        let info = user_info(&connection, 2).unwrap();
+        assert!(info.roles.is_empty() && info.buttons.is_empty());
+        assert!(matches!(
+            user_info(&connection, 99),
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn authorization() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(authorization_token(&headers), None);
+        headers.insert(header::AUTHORIZATION, "abc.def.ghi".parse().unwrap());
+        assert_eq!(authorization_token(&headers), Some("abc.def.ghi"));
+        headers.insert(header::AUTHORIZATION, "Bearer abc.def.ghi".parse().unwrap());
+        assert_eq!(authorization_token(&headers), Some("abc.def.ghi"));
+    }
+
+    #[tokio::test]
+    async fn missing_auth() {
+        let request = http::Request::builder()
+            .header(header::AUTHORIZATION, "abc.def.ghi")
+            .body(())
+            .unwrap();
+        let (mut parts, ()) = request.into_parts();
+        assert!(matches!(
+            AuthUser::from_request_parts(&mut parts, &()).await,
+            Err(ApiError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn ended_sessions() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let auth = Arc::new(Auth::random(AuthOptions::default()));
+        let token = |user_name: &str| {
+            auth.login(
+                &store.lock(),
+                &login_params(user_name, "123456"),
+                unix_now(),
+            )
+            .unwrap()
+            .token
+        };
+        let (super_, admin, user) = (token("Super"), token("Admin"), token("User"));
+        let extract = |token: &str| {
+            let request = http::Request::builder()
+                .header(header::AUTHORIZATION, token)
+                .extension(Arc::clone(&auth))
+                .extension(Arc::clone(&store))
+                .body(())
+                .unwrap();
+            async move {
+                let (mut parts, ()) = request.into_parts();
+                AuthUser::from_request_parts(&mut parts, &()).await
+            }
+        };
+        assert_eq!(extract(&super_).await.unwrap().id, 1);
+        assert_eq!(extract(&admin).await.unwrap().id, 2);
+        assert_eq!(extract(&user).await.unwrap().id, 3);
+
+        // The tokens issued before the sessions of the user end are refused,
+        // the new ones are not.
+        revoke_user(&store.lock(), 3).unwrap();
+        assert!(matches!(extract(&user).await, Err(ApiError::Unauthorized)));
+        assert_eq!(extract(&token("User")).await.unwrap().id, 3);
+
+        store
+            .lock()
+            .execute("UPDATE users SET status = ?1 WHERE id = 2", [DEACTIVATED])
+            .unwrap();
+        assert!(matches!(extract(&admin).await, Err(ApiError::Unauthorized)));
+
+        store
+            .lock()
+            .execute("DELETE FROM users WHERE id = 1", [])
+            .unwrap();
+        assert!(matches!(
+            extract(&super_).await,
+            Err(ApiError::Unauthorized)
+        ));
+    }
+}
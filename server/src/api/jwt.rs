@@ -0,0 +1,155 @@
+use crate::error_type;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// The header of every token, which is checked as is.
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// The claims of an access token. The times are in seconds since the Unix
+/// epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// The id of the user.
+    pub sub: u64,
+    /// The name of the user when the token was issued.
+    pub name: String,
+    pub iat: u64,
+    pub exp: u64,
+    /// The token generation of the user when the token was issued, the token
+    /// is refused once it is bumped.
+    #[serde(rename = "gen", default)]
+    pub generation: u64,
+}
+
+/// A JSON Web Token signed with HMAC-SHA256, RFC 7519.
+///
+/// # Examples
+///
+/// ```
+/// use server::api::jwt::{Claims, Jwt};
+///
+/// let jwt = Jwt::new(b"secret".to_vec());
+/// let claims = Claims {
+///     sub: 1,
+///     name: "Super".to_owned(),
+///     iat: 1_700_000_000,
+///     exp: 1_700_000_600,
+///     generation: 0,
+/// };
+/// let token = jwt.sign(&claims);
+/// assert_eq!(jwt.verify(&token, 1_700_000_001).unwrap(), claims);
+/// assert!(jwt.verify(&token, 1_700_000_600).is_err());
+/// ```
+#[derive(Clone)]
+pub struct Jwt {
+    secret: Vec<u8>,
+}
+
+error_type!(InvalidToken);
+
+impl Jwt {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Jwt { secret }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+
+    pub fn sign(&self, claims: &Claims) -> String {
+        let claims = serde_json::to_vec(claims).expect("the claims are serializable");
+        let payload = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(HEADER),
+            URL_SAFE_NO_PAD.encode(claims)
+        );
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// The claims of a token signed with the secret that hasn't expired at
+    /// `now`. The signature is compared in constant time.
+    pub fn verify(&self, token: &str, now: u64) -> Result<Claims, InvalidToken> {
+        let invalid = || InvalidToken { _inner: () };
+        let (payload, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+        let (header, claims) = payload.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+        // Only the header of this server is accepted, so `alg` can't be
+        // downgraded.
+        if URL_SAFE_NO_PAD.decode(header).map_err(|_| invalid())? != HEADER.as_bytes() {
+            return Err(invalid());
+        }
+        let claims = URL_SAFE_NO_PAD.decode(claims).map_err(|_| invalid())?;
+        let claims: Claims = serde_json::from_slice(&claims).map_err(|_| invalid())?;
+        if claims.exp <= now {
+            return Err(invalid());
+        }
+        Ok(claims)
+    }
+}
+
+impl std::fmt::Debug for Jwt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jwt").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> Claims {
+        Claims {
+            sub: 2,
+            name: "Admin".to_owned(),
+            iat: 100,
+            exp: 200,
+            generation: 1,
+        }
+    }
+
+    #[test]
+    fn compact_serialization() {
+        let token = Jwt::new(b"secret".to_vec()).sign(&claims());
+        let parts = token.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9");
+        assert_eq!(
+            URL_SAFE_NO_PAD.decode(parts[1]).unwrap(),
+            br#"{"sub":2,"name":"Admin","iat":100,"exp":200,"gen":1}"#
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let jwt = Jwt::new(b"secret".to_vec());
+        let token = jwt.sign(&claims());
+        assert!(Jwt::new(b"other".to_vec()).verify(&token, 150).is_err());
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let forged = URL_SAFE_NO_PAD.encode(br#"{"sub":1,"name":"Super","iat":100,"exp":200}"#);
+        assert!(
+            jwt.verify(&format!("{header}.{forged}.{signature}"), 150)
+                .is_err()
+        );
+        let none = URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#);
+        assert!(jwt.verify(&format!("{none}.{forged}."), 150).is_err());
+        assert!(jwt.verify("not a token", 150).is_err());
+    }
+
+    #[test]
+    fn expiry() {
+        let jwt = Jwt::new(b"secret".to_vec());
+        let token = jwt.sign(&claims());
+        assert!(jwt.verify(&token, 199).is_ok());
+        assert!(jwt.verify(&token, 200).is_err());
+    }
+}
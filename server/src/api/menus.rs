@@ -0,0 +1,544 @@
+use crate::api::auth::AuthUser;
+use crate::api::rbac::Access;
+use crate::api::store::Store;
+use crate::api::types::{AuthForm, AuthItem, MenuForm, MenuMeta, MenuRecord};
+use crate::api::{ApiError, split_codes};
+use crate::api_response::ApiResponse;
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, PathRejection};
+use axum::extract::{Path, State};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A menu as stored, before it is put in the tree.
+struct Menu {
+    parent_id: Option<u64>,
+    record: MenuRecord,
+}
+
+fn menu(row: &Row) -> rusqlite::Result<Menu> {
+    Ok(Menu {
+        parent_id: row.get("parent_id")?,
+        record: MenuRecord {
+            id: row.get("id")?,
+            path: row.get("path")?,
+            name: row.get("name")?,
+            component: row.get("component")?,
+            meta: MenuMeta {
+                title: row.get("title")?,
+                icon: row.get("icon")?,
+                sort: row.get("sort")?,
+                is_enable: row.get("is_enable")?,
+                keep_alive: row.get("keep_alive")?,
+                is_hide: row.get("is_hide")?,
+                is_hide_tab: row.get("is_hide_tab")?,
+                link: row.get("link")?,
+                is_iframe: row.get("is_iframe")?,
+                show_badge: row.get("show_badge")?,
+                show_text_badge: row.get("show_text_badge")?,
+                fixed_tab: row.get("fixed_tab")?,
+                active_path: row.get("active_path")?,
+                is_full_page: row.get("is_full_page")?,
+                roles: split_codes(row.get("roles")?),
+                auth_list: Vec::new(),
+            },
+            children: Vec::new(),
+        },
+    })
+}
+
+fn auth(row: &Row) -> rusqlite::Result<AuthItem> {
+    Ok(AuthItem {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        auth_mark: row.get("auth_mark")?,
+        sort: row.get("sort")?,
+    })
+}
+
+/// The menus as the `AppRouteRecord` tree of the frontend, sorted by `sort`.
+/// `meta.roles` lists the roles granted the menu. Unless `all`, the disabled
+/// menus and their children are left out, as the sidebar needs.
+pub fn tree(connection: &Connection, all: bool) -> Result<Vec<MenuRecord>, ApiError> {
+    let mut auths: HashMap<u64, Vec<AuthItem>> = HashMap::new();
+    let mut statement = connection
+        .prepare("SELECT id, menu_id, title, auth_mark, sort FROM menu_auths ORDER BY sort, id")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        auths
+            .entry(row.get("menu_id")?)
+            .or_default()
+            .push(auth(row)?);
+    }
+    let menus = connection
+        .prepare(
+            "SELECT m.*, (SELECT group_concat(r.code, ',' ORDER BY r.id) FROM role_menus rm \
+             JOIN roles r ON r.id = rm.role_id WHERE rm.menu_id = m.id) AS roles \
+             FROM menus m ORDER BY m.sort, m.id",
+        )?
+        .query_map([], menu)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut children: HashMap<Option<u64>, Vec<MenuRecord>> = HashMap::new();
+    for Menu {
+        parent_id,
+        mut record,
+    } in menus
+    {
+        if all || record.meta.is_enable {
+            record.meta.auth_list = auths.remove(&record.id).unwrap_or_default();
+            children.entry(parent_id).or_default().push(record);
+        }
+    }
+    Ok(assemble(&mut children, None))
+}
+
+fn assemble(
+    children: &mut HashMap<Option<u64>, Vec<MenuRecord>>,
+    parent_id: Option<u64>,
+) -> Vec<MenuRecord> {
+    let mut records = children.remove(&parent_id).unwrap_or_default();
+    for record in &mut records {
+        record.children = assemble(children, Some(record.id));
+    }
+    records
+}
+
+pub fn find(connection: &Connection, id: u64) -> Result<MenuRecord, ApiError> {
+    Ok(connection
+        .query_row(
+            "SELECT m.*, (SELECT group_concat(r.code, ',' ORDER BY r.id) FROM role_menus rm \
+             JOIN roles r ON r.id = rm.role_id WHERE rm.menu_id = m.id) AS roles \
+             FROM menus m WHERE m.id = ?1",
+            [id],
+            menu,
+        )?
+        .record)
+}
+
+pub fn insert(connection: &mut Connection, form: &MenuForm) -> Result<MenuRecord, ApiError> {
+    validate(form)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO menus (parent_id, name, path, component, title, icon, sort, is_enable, \
+         keep_alive, is_hide, is_hide_tab, link, is_iframe, show_badge, show_text_badge, \
+         fixed_tab, active_path, is_full_page) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+        params![
+            form.parent_id,
+            form.name,
+            form.path,
+            form.component,
+            form.title,
+            form.icon,
+            form.sort,
+            form.is_enable,
+            form.keep_alive,
+            form.is_hide,
+            form.is_hide_tab,
+            form.link,
+            form.is_iframe,
+            form.show_badge,
+            form.show_text_badge,
+            form.fixed_tab,
+            form.active_path,
+            form.is_full_page
+        ],
+    )?;
+    let id = transaction.last_insert_rowid() as u64;
+    if let Some(roles) = &form.roles {
+        set_roles(&transaction, id, roles)?;
+    }
+    transaction.commit()?;
+    find(connection, id)
+}
+
+/// Replaces every field of the menu, and its roles when the form has some.
+/// A menu can't be moved under itself or one of its children.
+pub fn update(
+    connection: &mut Connection,
+    id: u64,
+    form: &MenuForm,
+) -> Result<MenuRecord, ApiError> {
+    validate(form)?;
+    let transaction = connection.transaction()?;
+    if let Some(parent_id) = form.parent_id {
+        let cycle: bool = transaction.query_row(
+            "WITH RECURSIVE descendants (id) AS (SELECT ?1 UNION \
+             SELECT m.id FROM menus m JOIN descendants d ON m.parent_id = d.id) \
+             SELECT EXISTS (SELECT 1 FROM descendants WHERE id = ?2)",
+            [id, parent_id],
+            |row| row.get(0),
+        )?;
+        if cycle {
+            return Err(ApiError::BadRequest(
+                "A menu can't be moved under itself".to_owned(),
+            ));
+        }
+    }
+    let updated = transaction.execute(
+        "UPDATE menus SET parent_id = ?2, name = ?3, path = ?4, component = ?5, title = ?6, \
+         icon = ?7, sort = ?8, is_enable = ?9, keep_alive = ?10, is_hide = ?11, \
+         is_hide_tab = ?12, link = ?13, is_iframe = ?14, show_badge = ?15, \
+         show_text_badge = ?16, fixed_tab = ?17, active_path = ?18, is_full_page = ?19 \
+         WHERE id = ?1",
+        params![
+            id,
+            form.parent_id,
+            form.name,
+            form.path,
+            form.component,
+            form.title,
+            form.icon,
+            form.sort,
+            form.is_enable,
+            form.keep_alive,
+            form.is_hide,
+            form.is_hide_tab,
+            form.link,
+            form.is_iframe,
+            form.show_badge,
+            form.show_text_badge,
+            form.fixed_tab,
+            form.active_path,
+            form.is_full_page
+        ],
+    )?;
+    if updated == 0 {
+        return Err(ApiError::NotFound);
+    }
+    if let Some(roles) = &form.roles {
+        set_roles(&transaction, id, roles)?;
+    }
+    transaction.commit()?;
+    find(connection, id)
+}
+
+/// Deletes the menu with its children and their buttons.
+pub fn delete(connection: &Connection, id: u64) -> Result<(), ApiError> {
+    match connection.execute("DELETE FROM menus WHERE id = ?1", [id])? {
+        0 => Err(ApiError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+fn validate(form: &MenuForm) -> Result<(), ApiError> {
+    for (field, value) in [
+        ("name", &form.name),
+        ("path", &form.path),
+        ("title", &form.title),
+    ] {
+        if value.trim().is_empty() {
+            return Err(ApiError::BadRequest(format!("The {field} is empty")));
+        }
+    }
+    Ok(())
+}
+
+/// Grants the menu to exactly the roles with these codes.
+fn set_roles(connection: &Connection, menu_id: u64, codes: &[String]) -> Result<(), ApiError> {
+    connection.execute("DELETE FROM role_menus WHERE menu_id = ?1", [menu_id])?;
+    for code in codes {
+        let role_id: u64 = connection
+            .query_row("SELECT id FROM roles WHERE code = ?1", [code], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .ok_or_else(|| ApiError::BadRequest(format!("Unknown role {code}")))?;
+        connection.execute(
+            "INSERT OR IGNORE INTO role_menus (role_id, menu_id) VALUES (?1, ?2)",
+            [role_id, menu_id],
+        )?;
+    }
+    Ok(())
+}
+
+fn find_auth(connection: &Connection, id: u64) -> Result<AuthItem, ApiError> {
+    Ok(connection.query_row(
+        "SELECT id, title, auth_mark, sort FROM menu_auths WHERE id = ?1",
+        [id],
+        auth,
+    )?)
+}
+
+/// Adds a button to the menu, whose marks are unique.
+pub fn insert_auth(
+    connection: &Connection,
+    menu_id: u64,
+    form: &AuthForm,
+) -> Result<AuthItem, ApiError> {
+    validate_auth(form)?;
+    find(connection, menu_id)?;
+    connection.execute(
+        "INSERT INTO menu_auths (menu_id, title, auth_mark, sort) VALUES (?1, ?2, ?3, ?4)",
+        params![menu_id, form.title, form.auth_mark, form.sort],
+    )?;
+    find_auth(connection, connection.last_insert_rowid() as u64)
+}
+
+pub fn update_auth(
+    connection: &Connection,
+    id: u64,
+    form: &AuthForm,
+) -> Result<AuthItem, ApiError> {
+    validate_auth(form)?;
+    let updated = connection.execute(
+        "UPDATE menu_auths SET title = ?2, auth_mark = ?3, sort = ?4 WHERE id = ?1",
+        params![id, form.title, form.auth_mark, form.sort],
+    )?;
+    match updated {
+        0 => Err(ApiError::NotFound),
+        _ => find_auth(connection, id),
+    }
+}
+
+pub fn delete_auth(connection: &Connection, id: u64) -> Result<(), ApiError> {
+    match connection.execute("DELETE FROM menu_auths WHERE id = ?1", [id])? {
+        0 => Err(ApiError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+fn validate_auth(form: &AuthForm) -> Result<(), ApiError> {
+    if form.title.trim().is_empty() || form.auth_mark.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "The title or the mark is empty".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// The handler of `GET /api/v3/system/menus/simple`, the menus of the
+/// sidebar the user is granted.
+pub async fn menus(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+) -> Result<ApiResponse<Vec<MenuRecord>>, ApiError> {
+    let menus = store
+        .run(move |connection| Ok(Access::load(connection, user.id)?.into_menus()))
+        .await?;
+    Ok(ApiResponse::success(menus))
+}
+
+/// The handler of `GET /api/menu/list`, the disabled menus included.
+pub async fn list_menus(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+) -> Result<ApiResponse<Vec<MenuRecord>>, ApiError> {
+    let menus = store.run(|connection| tree(connection, true)).await?;
+    Ok(ApiResponse::success(menus))
+}
+
+/// The handler of `POST /api/menu`.
+pub async fn create_menu(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    form: Result<Json<MenuForm>, JsonRejection>,
+) -> Result<ApiResponse<MenuRecord>, ApiError> {
+    let Json(form) = form?;
+    let menu = store
+        .run(move |connection| insert(connection, &form))
+        .await?;
+    Ok(ApiResponse::success(menu))
+}
+
+/// The handler of `PUT /api/menu/{id}`.
+pub async fn update_menu(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    form: Result<Json<MenuForm>, JsonRejection>,
+) -> Result<ApiResponse<MenuRecord>, ApiError> {
+    let (Path(id), Json(form)) = (id?, form?);
+    let menu = store
+        .run(move |connection| update(connection, id, &form))
+        .await?;
+    Ok(ApiResponse::success(menu))
+}
+
+/// The handler of `DELETE /api/menu/{id}`.
+pub async fn delete_menu(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Path(id) = id?;
+    store.run(move |connection| delete(connection, id)).await?;
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `POST /api/menu/{id}/auth`.
+pub async fn create_menu_auth(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    form: Result<Json<AuthForm>, JsonRejection>,
+) -> Result<ApiResponse<AuthItem>, ApiError> {
+    let (Path(id), Json(form)) = (id?, form?);
+    let auth = store
+        .run(move |connection| insert_auth(connection, id, &form))
+        .await?;
+    Ok(ApiResponse::success(auth))
+}
+
+/// The handler of `PUT /api/menu/auth/{id}`.
+pub async fn update_menu_auth(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    form: Result<Json<AuthForm>, JsonRejection>,
+) -> Result<ApiResponse<AuthItem>, ApiError> {
+    let (Path(id), Json(form)) = (id?, form?);
+    let auth = store
+        .run(move |connection| update_auth(connection, id, &form))
+        .await?;
+    Ok(ApiResponse::success(auth))
+}
+
+/// The handler of `DELETE /api/menu/auth/{id}`.
+pub async fn delete_menu_auth(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Path(id) = id?;
+    store
+        .run(move |connection| delete_auth(connection, id))
+        .await?;
+    Ok(ApiResponse::success(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(parent_id: Option<u64>, name: &str) -> MenuForm {
+        serde_json::from_value(serde_json::json!({
+            "parentId": parent_id,
+            "name": name,
+            "path": name.to_lowercase(),
+            "title": format!("menus.{name}"),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn seeded_tree() {
+        let store = Store::open_in_memory().unwrap();
+        let menus = tree(&store.lock(), false).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(include_str!("../../fixtures/mock-api/menus.json")).unwrap();
+        let names = |menus: &serde_json::Value| {
+            menus
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|menu| menu["name"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let menus = serde_json::to_value(&menus).unwrap();
+        assert_eq!(names(&menus), names(&expected));
+        assert_eq!(
+            names(&menus[1]["children"]),
+            names(&expected[1]["children"])
+        );
+        assert_eq!(menus[1]["meta"]["roles"], expected[1]["meta"]["roles"]);
+        assert_eq!(
+            menus[1]["children"][1]["meta"]["roles"],
+            serde_json::json!(["R_SUPER"])
+        );
+        assert_eq!(
+            menus[1]["children"][3]["meta"]["authList"][2],
+            serde_json::json!({"id": 3, "title": "删除", "authMark": "delete", "sort": 3})
+        );
+        assert!(menus[2]["meta"].get("roles").is_none());
+        assert!(menus[2]["meta"].get("authList").is_none());
+    }
+
+    #[test]
+    fn crud() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let mut article = form(None, "Article");
+        article.roles = Some(vec!["R_ADMIN".to_owned()]);
+        let menu = insert(&mut connection, &article).unwrap();
+        assert_eq!(menu.meta.roles, ["R_ADMIN"]);
+        let list = insert(&mut connection, &form(Some(menu.id), "ArticleList")).unwrap();
+
+        let mut disabled = form(None, "Article");
+        disabled.is_enable = false;
+        // The roles are kept when the form has none.
+        let menu = update(&mut connection, menu.id, &disabled).unwrap();
+        assert_eq!(menu.meta.roles, ["R_ADMIN"]);
+        let enabled = tree(&connection, false).unwrap();
+        assert!(enabled.iter().all(|menu| menu.name != "Article"));
+        let all = tree(&connection, true).unwrap();
+        let article = all.iter().find(|menu| menu.name == "Article").unwrap();
+        assert_eq!(article.children[0].id, list.id);
+
+        assert!(matches!(
+            insert(&mut connection, &form(None, "Article")),
+            Err(ApiError::Conflict(_))
+        ));
+        assert!(matches!(
+            insert(&mut connection, &form(Some(99), "Orphan")),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        // Deleting a menu deletes its children.
+        delete(&connection, menu.id).unwrap();
+        assert!(matches!(
+            find(&connection, list.id),
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn cycle() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let system = form(Some(24), "System");
+        assert!(matches!(
+            update(&mut connection, 2, &system),
+            Err(ApiError::BadRequest(_))
+        ));
+        let system = form(Some(2), "System");
+        assert!(matches!(
+            update(&mut connection, 2, &system),
+            Err(ApiError::BadRequest(_))
+        ));
+        let console = form(Some(2), "Console");
+        assert_eq!(
+            update(&mut connection, 11, &console).unwrap().name,
+            "Console"
+        );
+    }
+
+    #[test]
+    fn auths() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let form = |auth_mark: &str| AuthForm {
+            title: "导出".to_owned(),
+            auth_mark: auth_mark.to_owned(),
+            sort: 4,
+        };
+        let auth = insert_auth(&connection, 24, &form("export")).unwrap();
+        assert_eq!(auth.id, 10);
+        assert!(matches!(
+            insert_auth(&connection, 24, &form("add")),
+            Err(ApiError::Conflict(_))
+        ));
+        assert!(matches!(
+            insert_auth(&connection, 99, &form("export")),
+            Err(ApiError::NotFound)
+        ));
+        let auth = update_auth(&connection, 10, &form("download")).unwrap();
+        assert_eq!(auth.auth_mark, "download");
+        delete_auth(&connection, 10).unwrap();
+        assert!(matches!(
+            delete_auth(&connection, 10),
+            Err(ApiError::NotFound)
+        ));
+    }
+}
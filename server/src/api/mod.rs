@@ -0,0 +1,262 @@
+//! The backend of the system management pages of the frontend, the users,
+//! the roles and the menus under `src/views/system`, persisted in SQLite.
+//!
+//! The handlers answer with the [`ApiResponse`] envelope, and the lists with
+//! a [`PaginatedResponse`](crate::api_response::PaginatedResponse).
+
+use crate::api::auth::Auth;
+use crate::api::store::Store;
+use crate::api::throttle::LoginThrottle;
+use crate::api_response::ApiResponse;
+use axum::extract::FromRef;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::response::{IntoResponse, Response};
+use http::{HeaderValue, StatusCode, header};
+use rusqlite::ffi;
+use rusqlite::types::Value;
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+use tracing::error;
+
+pub mod accounts;
+pub mod auth;
+pub mod jwt;
+pub mod mailer;
+pub mod menus;
+pub mod rbac;
+pub mod roles;
+pub mod store;
+pub mod throttle;
+pub mod types;
+pub mod users;
+
+/// The errors of the API, each answered with its status and a message.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request is malformed or refers to records that don't exist.
+    BadRequest(String),
+    /// The request has no valid access token.
+    Unauthorized,
+    /// The user isn't allowed to do it.
+    Forbidden,
+    NotFound,
+    /// The request clashes with a unique record, e.g. a taken user name.
+    Conflict(String),
+    /// Too many failed logins, which can be retried after this many seconds.
+    TooManyRequests(u64),
+    /// An unexpected error of the database, answered with a `500` whose
+    /// message doesn't leak the details.
+    Database(rusqlite::Error),
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(msg) | ApiError::Conflict(msg) => f.write_str(msg),
+            ApiError::Unauthorized => f.write_str("Unauthorized"),
+            ApiError::Forbidden => f.write_str("Forbidden"),
+            ApiError::NotFound => f.write_str("Not Found"),
+            ApiError::TooManyRequests(secs) => {
+                write!(f, "Too many failed logins, try again in {secs} seconds")
+            }
+            ApiError::Database(error) => write!(f, "Database error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApiError::Database(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(error: rusqlite::Error) -> Self {
+        match &error {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::NotFound,
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.extended_code {
+                ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY => {
+                    ApiError::Conflict("Already exists".to_owned())
+                }
+                ffi::SQLITE_CONSTRAINT_FOREIGNKEY => {
+                    ApiError::BadRequest("Refers to a record that doesn't exist".to_owned())
+                }
+                _ => ApiError::Database(error),
+            },
+            _ => ApiError::Database(error),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
+impl ApiError {
+    /// The response of the error whose `data` is the given one instead of
+    /// `null`, e.g. to ask the frontend for a CAPTCHA.
+    pub fn into_response_with<T: Serialize>(self, data: T) -> Response {
+        let retry_after = match self {
+            ApiError::TooManyRequests(secs) => Some(secs),
+            _ => None,
+        };
+        let ApiResponse { code, msg, .. } = match self {
+            ApiError::BadRequest(msg) => ApiResponse::message(StatusCode::BAD_REQUEST, msg),
+            ApiError::Unauthorized => ApiResponse::error(StatusCode::UNAUTHORIZED),
+            ApiError::Forbidden => ApiResponse::error(StatusCode::FORBIDDEN),
+            ApiError::NotFound => ApiResponse::error(StatusCode::NOT_FOUND),
+            ApiError::Conflict(msg) => ApiResponse::message(StatusCode::CONFLICT, msg),
+            ApiError::TooManyRequests(_) => {
+                ApiResponse::message(StatusCode::TOO_MANY_REQUESTS, self.to_string())
+            }
+            ApiError::Database(error) => {
+                error!("The database failed: {error}");
+                ApiResponse::error(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+        let mut response = ApiResponse { code, msg, data }.into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        self.into_response_with(())
+    }
+}
+
+/// The state of the handlers of the API, from which they extract the parts
+/// they need.
+#[derive(Clone, Debug)]
+pub struct ApiState {
+    pub store: Arc<Store>,
+    pub auth: Arc<Auth>,
+    pub throttle: Arc<LoginThrottle>,
+}
+
+impl FromRef<ApiState> for Arc<Store> {
+    fn from_ref(state: &ApiState) -> Self {
+        Arc::clone(&state.store)
+    }
+}
+
+impl FromRef<ApiState> for Arc<Auth> {
+    fn from_ref(state: &ApiState) -> Self {
+        Arc::clone(&state.auth)
+    }
+}
+
+impl FromRef<ApiState> for Arc<LoginThrottle> {
+    fn from_ref(state: &ApiState) -> Self {
+        Arc::clone(&state.throttle)
+    }
+}
+
+/// The `WHERE` clause of a search, built from the fields the search sets.
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    clauses: Vec<String>,
+    values: Vec<Value>,
+}
+
+impl Filter {
+    /// The column equals the value.
+    pub(crate) fn eq(&mut self, column: &str, value: Option<impl Into<Value>>) -> &mut Self {
+        if let Some(value) = value {
+            self.values.push(value.into());
+            self.clauses
+                .push(format!("{column} = ?{}", self.values.len()));
+        }
+        self
+    }
+
+    /// The column contains the text, ignoring the case of ASCII letters.
+    pub(crate) fn contains(&mut self, column: &str, text: Option<&str>) -> &mut Self {
+        if let Some(text) = text {
+            self.values.push(text.to_owned().into());
+            self.clauses.push(format!(
+                "instr(lower({column}), lower(?{})) > 0",
+                self.values.len()
+            ));
+        }
+        self
+    }
+
+    /// The clause, empty when the search sets nothing.
+    pub(crate) fn sql(&self) -> String {
+        if self.clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.clauses.join(" AND "))
+        }
+    }
+
+    pub(crate) fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+/// Splits the codes joined by `group_concat`.
+pub(crate) fn split_codes(codes: Option<String>) -> Vec<String> {
+    codes
+        .map(|codes| codes.split(',').map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            ApiError::from(rusqlite::Error::QueryReturnedNoRows),
+            ApiError::NotFound
+        ));
+        let response = ApiError::Conflict("Already exists".to_owned()).into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = ApiError::Database(rusqlite::Error::InvalidQuery).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let response = ApiError::TooManyRequests(30).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    }
+
+    #[test]
+    fn filter() {
+        let mut filter = Filter::default();
+        assert_eq!(filter.sql(), "");
+        filter
+            .eq("id", Some(1_i64))
+            .eq("status", None::<String>)
+            .contains("name", Some("ad"));
+        assert_eq!(
+            filter.sql(),
+            " WHERE id = ?1 AND instr(lower(name), lower(?2)) > 0"
+        );
+        assert_eq!(filter.values().len(), 2);
+    }
+}
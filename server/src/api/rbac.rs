@@ -0,0 +1,655 @@
+use crate::api::auth::{Auth, DEACTIVATED, authorization_token, check_session, unix_now};
+use crate::api::menus::tree;
+use crate::api::store::Store;
+use crate::api::types::{MenuRecord, RolePermissions};
+use crate::api::{ApiError, split_codes};
+use crate::error_type;
+use crate::path_pattern::PathPattern;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::{HeaderMap, Method};
+use rusqlite::{Connection, params};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// The role that is granted every menu and every button, so that the
+/// permissions can always be repaired.
+pub const SUPER_ROLE: &str = "R_SUPER";
+
+/// What a user must be granted, a menu by the name of its route and
+/// optionally one of its buttons by its mark, e.g. `User` or `User:add`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Permission {
+    menu: String,
+    auth_mark: Option<String>,
+}
+
+error_type!(InvalidPermission);
+
+impl Permission {
+    pub fn new(menu: impl Into<String>, auth_mark: Option<String>) -> Self {
+        Permission {
+            menu: menu.into(),
+            auth_mark,
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = InvalidPermission;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (menu, auth_mark) = match s.split_once(':') {
+            Some((menu, auth_mark)) => (menu.trim(), Some(auth_mark.trim())),
+            None => (s.trim(), None),
+        };
+        if menu.is_empty() || auth_mark.is_some_and(str::is_empty) {
+            return Err(InvalidPermission { _inner: () });
+        }
+        Ok(Permission::new(menu, auth_mark.map(str::to_owned)))
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.auth_mark {
+            Some(auth_mark) => write!(f, "{}:{auth_mark}", self.menu),
+            None => f.write_str(&self.menu),
+        }
+    }
+}
+
+/// What a request needs to reach its handler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Requirement {
+    /// Anyone, e.g. the login.
+    Public,
+    /// A valid access token.
+    Authenticated,
+    /// A valid access token of a user granted the permission.
+    Permission(Permission),
+}
+
+impl FromStr for Requirement {
+    type Err = InvalidPermission;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "public" => Ok(Requirement::Public),
+            "authenticated" => Ok(Requirement::Authenticated),
+            permission => permission.parse().map(Requirement::Permission),
+        }
+    }
+}
+
+/// Assigns a [`Requirement`] to the requests of a method, or of any method
+/// with `*`, whose path matches a pattern.
+///
+/// The textual form is `<method> <pattern>=<requirement>`, where the pattern
+/// is a [`PathPattern`] and the requirement is `public`, `authenticated` or a
+/// [`Permission`]. A `GET` rule applies to `HEAD` too, which is answered by
+/// the same handler.
+///
+/// # Examples
+///
+/// ```
+/// use http::{HeaderMap, Method};
+/// use server::api::rbac::AccessRule;
+///
+/// let rule: AccessRule = "DELETE api/user/*=User:delete".parse().unwrap();
+/// assert!(rule.requirement(&Method::DELETE, "/api/user/4").is_some());
+/// assert!(rule.requirement(&Method::GET, "/api/user/4").is_none());
+/// ```
+#[derive(Clone, Debug)]
+pub struct AccessRule {
+    method: Option<Method>,
+    pattern: PathPattern,
+    requirement: Requirement,
+}
+
+error_type!(InvalidAccessRule);
+
+impl AccessRule {
+    pub fn new(method: Option<Method>, pattern: PathPattern, requirement: Requirement) -> Self {
+        AccessRule {
+            method,
+            pattern,
+            requirement,
+        }
+    }
+
+    /// The requirement of this rule if the request matches it.
+    pub fn requirement(&self, method: &Method, path: &str) -> Option<&Requirement> {
+        let method_matches = self
+            .method
+            .as_ref()
+            .is_none_or(|m| m == method || (m == Method::GET && method == Method::HEAD));
+        (method_matches && self.pattern.is_match(path)).then_some(&self.requirement)
+    }
+}
+
+impl FromStr for AccessRule {
+    type Err = InvalidAccessRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidAccessRule { _inner: () };
+        let (request, requirement) = s.rsplit_once('=').ok_or_else(invalid)?;
+        let (method, pattern) = request.trim().split_once(' ').ok_or_else(invalid)?;
+        let method = match method {
+            "*" => None,
+            method => Some(method.parse().map_err(|_| invalid())?),
+        };
+        Ok(AccessRule {
+            method,
+            pattern: pattern.trim().parse().map_err(|_| invalid())?,
+            requirement: requirement.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// An ordered list of [`AccessRule`]s, the first matching rule wins. A
+/// request matching no rule passes.
+///
+/// # Examples
+///
+/// ```
+/// use http::{HeaderMap, Method};
+/// use server::api::rbac::{AccessPolicy, Requirement};
+///
+/// let policy = AccessPolicy::default();
+/// assert_eq!(
+///     policy.requirement(&Method::POST, "/api/auth/login"),
+///     Some(&Requirement::Public)
+/// );
+/// assert_eq!(
+///     policy.requirement(&Method::GET, "/api/user/info"),
+///     Some(&Requirement::Authenticated)
+/// );
+/// assert_eq!(policy.requirement(&Method::GET, "/index.html"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct AccessPolicy {
+    rules: Vec<AccessRule>,
+}
+
+impl AccessPolicy {
+    /// A policy that only consists of the given rules.
+    pub fn new(rules: Vec<AccessRule>) -> Self {
+        AccessPolicy { rules }
+    }
+
+    /// The given rules are checked before the default rules.
+    pub fn with_defaults(rules: Vec<AccessRule>) -> Self {
+        let mut policy = AccessPolicy::new(rules);
+        policy.rules.extend(AccessPolicy::default_rules());
+        policy
+    }
+
+    /// The default rules protect the handlers of the API with the pages of
+    /// the system management and their `add`, `edit` and `delete` buttons.
+    /// The login is public, and the rest of the API needs a user logged in.
+    pub fn default_rules() -> Vec<AccessRule> {
+        [
+            "POST api/auth/**=public",
+            "GET api/user/info=authenticated",
+            "GET api/user/list=User",
+            "POST api/user=User:add",
+            "GET api/user/*=User",
+            "PUT api/user/*=User:edit",
+            "DELETE api/user/*=User:delete",
+            "POST api/user/*/unlock=User:edit",
+            "GET api/role/list=Role",
+            "POST api/role=Role:add",
+            "PUT api/role/*=Role:edit",
+            "DELETE api/role/*=Role:delete",
+            "GET api/role/*/permissions=Role",
+            "PUT api/role/*/permissions=Role:edit",
+            "GET api/menu/list=Menus",
+            "POST api/menu=Menus:add",
+            "POST api/menu/*/auth=Menus:add",
+            "PUT api/menu/**=Menus:edit",
+            "DELETE api/menu/**=Menus:delete",
+            "* api/**=authenticated",
+        ]
+        .into_iter()
+        .map(|rule| rule.parse().expect("the default access rules are valid"))
+        .collect()
+    }
+
+    /// The requirement of the first rule matching the request.
+    pub fn requirement(&self, method: &Method, path: &str) -> Option<&Requirement> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.requirement(method, path))
+    }
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        AccessPolicy::new(AccessPolicy::default_rules())
+    }
+}
+
+/// What a user is granted: the menus of the sidebar and the buttons of these
+/// menus, through the enabled roles of the user.
+///
+/// A menu without roles is granted to everyone, and a menu is only granted
+/// with its parent. A directory whose children are all withheld is withheld
+/// too. The buttons are granted role by role.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Access {
+    roles: Vec<String>,
+    menus: Vec<MenuRecord>,
+}
+
+impl Access {
+    /// Filters the menu tree for the roles and the ids of the buttons they
+    /// are granted.
+    pub fn new(roles: Vec<String>, auth_ids: &HashSet<u64>, menus: Vec<MenuRecord>) -> Self {
+        let is_super = roles.iter().any(|role| role == SUPER_ROLE);
+        let menus = if is_super {
+            menus
+        } else {
+            filter(menus, &roles, auth_ids)
+        };
+        Access { roles, menus }
+    }
+
+    /// The access of the user to the enabled menus. A deactivated user has no
+    /// roles, whatever the tokens they still hold.
+    pub fn load(connection: &Connection, user_id: u64) -> Result<Self, ApiError> {
+        let roles = connection.query_row(
+            "SELECT group_concat(r.code, ',' ORDER BY r.id) FROM user_roles ur \
+             JOIN users u ON u.id = ur.user_id AND u.status != ?2 \
+             JOIN roles r ON r.id = ur.role_id WHERE ur.user_id = ?1 AND r.enabled",
+            params![user_id, DEACTIVATED],
+            |row| row.get(0),
+        )?;
+        let auth_ids = connection
+            .prepare(
+                "SELECT DISTINCT ra.auth_id FROM user_roles ur \
+                 JOIN users u ON u.id = ur.user_id AND u.status != ?2 \
+                 JOIN roles r ON r.id = ur.role_id AND r.enabled \
+                 JOIN role_auths ra ON ra.role_id = r.id WHERE ur.user_id = ?1",
+            )?
+            .query_map(params![user_id, DEACTIVATED], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Access::new(
+            split_codes(roles),
+            &auth_ids,
+            tree(connection, false)?,
+        ))
+    }
+
+    /// The codes of the enabled roles.
+    pub fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    pub fn is_super(&self) -> bool {
+        self.roles.iter().any(|role| role == SUPER_ROLE)
+    }
+
+    /// The menus granted, whose `authList` only has the buttons granted.
+    pub fn menus(&self) -> &[MenuRecord] {
+        &self.menus
+    }
+
+    pub fn into_menus(self) -> Vec<MenuRecord> {
+        self.menus
+    }
+
+    /// The marks of the buttons granted in any menu, the `buttons` of the
+    /// `UserInfo` of the frontend.
+    pub fn buttons(&self) -> Vec<String> {
+        let mut buttons = Vec::new();
+        visit(&self.menus, &mut |menu| {
+            buttons.extend(
+                menu.meta
+                    .auth_list
+                    .iter()
+                    .map(|auth| auth.auth_mark.clone()),
+            );
+        });
+        buttons.sort();
+        buttons.dedup();
+        buttons
+    }
+
+    /// The ids of the menus and of the buttons granted.
+    pub fn permissions(&self) -> RolePermissions {
+        let mut permissions = RolePermissions::default();
+        visit(&self.menus, &mut |menu| {
+            permissions.menu_ids.push(menu.id);
+            permissions
+                .auth_ids
+                .extend(menu.meta.auth_list.iter().map(|auth| auth.id));
+        });
+        permissions.menu_ids.sort_unstable();
+        permissions.auth_ids.sort_unstable();
+        permissions.auth_ids.dedup();
+        permissions
+    }
+
+    pub fn allows(&self, permission: &Permission) -> bool {
+        if self.is_super() {
+            return true;
+        }
+        let mut allowed = false;
+        visit(&self.menus, &mut |menu| {
+            allowed |= menu.name == permission.menu
+                && permission.auth_mark.as_ref().is_none_or(|auth_mark| {
+                    menu.meta
+                        .auth_list
+                        .iter()
+                        .any(|auth| auth.auth_mark == *auth_mark)
+                });
+        });
+        allowed
+    }
+}
+
+fn filter(menus: Vec<MenuRecord>, roles: &[String], auth_ids: &HashSet<u64>) -> Vec<MenuRecord> {
+    menus
+        .into_iter()
+        .filter_map(|mut menu| {
+            let granted = menu.meta.roles.is_empty()
+                || menu.meta.roles.iter().any(|role| roles.contains(role));
+            if !granted {
+                return None;
+            }
+            let directory = !menu.children.is_empty();
+            menu.children = filter(menu.children, roles, auth_ids);
+            if directory && menu.children.is_empty() {
+                return None;
+            }
+            menu.meta
+                .auth_list
+                .retain(|auth| auth_ids.contains(&auth.id));
+            Some(menu)
+        })
+        .collect()
+}
+
+fn visit(menus: &[MenuRecord], f: &mut impl FnMut(&MenuRecord)) {
+    for menu in menus {
+        f(menu);
+        visit(&menu.children, f);
+    }
+}
+
+/// The state of [`access_control`].
+#[derive(Debug)]
+pub struct AccessControl {
+    policy: AccessPolicy,
+    store: Arc<Store>,
+    auth: Arc<Auth>,
+}
+
+impl AccessControl {
+    pub fn new(policy: AccessPolicy, store: Arc<Store>, auth: Arc<Auth>) -> Self {
+        AccessControl {
+            policy,
+            store,
+            auth,
+        }
+    }
+
+    /// Whether a request may reach its handler.
+    pub async fn check(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+    ) -> Result<(), ApiError> {
+        let requirement = self.policy.requirement(method, path);
+        let permission = match requirement {
+            None | Some(Requirement::Public) => return Ok(()),
+            Some(Requirement::Authenticated) => None,
+            Some(Requirement::Permission(permission)) => Some(permission.clone()),
+        };
+        let token = authorization_token(headers).ok_or(ApiError::Unauthorized)?;
+        let claims = self.auth.verify(token, unix_now())?;
+        let allowed = self
+            .store
+            .run(move |connection| {
+                check_session(connection, &claims)?;
+                match permission {
+                    Some(permission) => {
+                        Ok(Access::load(connection, claims.sub)?.allows(&permission))
+                    }
+                    None => Ok(true),
+                }
+            })
+            .await?;
+        if allowed {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden)
+        }
+    }
+}
+
+/// A middleware that answers `401` or `403` to the requests whose user
+/// doesn't meet the [`Requirement`] of the [`AccessPolicy`].
+pub async fn access_control(
+    State(access_control): State<Arc<AccessControl>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let checked = access_control
+        .check(request.method(), request.uri().path(), request.headers())
+        .await;
+    match checked {
+        Ok(()) => next.run(request).await,
+        Err(error) => error.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::auth::{AuthOptions, revoke_user};
+    use crate::api::types::LoginParams;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::middleware;
+    use axum::routing::any;
+    use http::{StatusCode, header};
+    use tower::ServiceExt;
+
+    fn names(menus: &[MenuRecord]) -> Vec<&str> {
+        menus.iter().map(|menu| menu.name.as_str()).collect()
+    }
+
+    #[test]
+    fn rules() {
+        let rule: AccessRule = "* regex:^api/=authenticated".parse().unwrap();
+        assert_eq!(
+            rule.requirement(&Method::PATCH, "/api/x"),
+            Some(&Requirement::Authenticated)
+        );
+        let rule: AccessRule = "GET api/user/list=User".parse().unwrap();
+        assert!(rule.requirement(&Method::HEAD, "/api/user/list").is_some());
+        assert!(rule.requirement(&Method::POST, "/api/user/list").is_none());
+        let rule: AccessRule = "HEAD api/user/list=User".parse().unwrap();
+        assert!(rule.requirement(&Method::GET, "/api/user/list").is_none());
+        assert!("GET api/user".parse::<AccessRule>().is_err());
+        assert!("api/user=User".parse::<AccessRule>().is_err());
+        assert!("GET api/user=User:".parse::<AccessRule>().is_err());
+        assert_eq!(
+            "Menus:add".parse::<Permission>().unwrap().to_string(),
+            "Menus:add"
+        );
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = AccessPolicy::default();
+        let requirement = |method, path| policy.requirement(&method, path).cloned();
+        assert_eq!(
+            requirement(Method::PUT, "/api/role/2/permissions"),
+            Some(Requirement::Permission("Role:edit".parse().unwrap()))
+        );
+        assert_eq!(
+            requirement(Method::DELETE, "/api/menu/auth/3"),
+            Some(Requirement::Permission("Menus:delete".parse().unwrap()))
+        );
+        assert_eq!(
+            requirement(Method::GET, "/api/v3/system/menus/simple"),
+            Some(Requirement::Authenticated)
+        );
+        assert_eq!(requirement(Method::GET, "/dashboard/console"), None);
+    }
+
+    #[test]
+    fn menus() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let admin = Access::load(&connection, 2).unwrap();
+        assert_eq!(
+            names(admin.menus()),
+            ["Dashboard", "System", "Result", "Exception"]
+        );
+        assert_eq!(names(&admin.menus()[1].children), ["User", "UserCenter"]);
+        assert_eq!(admin.buttons(), ["add", "edit"]);
+        assert!(admin.allows(&"User:edit".parse().unwrap()));
+        assert!(!admin.allows(&"User:delete".parse().unwrap()));
+        assert!(!admin.allows(&"Role".parse().unwrap()));
+
+        // The children of a menu withheld are withheld with it.
+        let user = Access::load(&connection, 3).unwrap();
+        assert_eq!(names(user.menus()), ["Result", "Exception"]);
+        assert!(user.buttons().is_empty());
+
+        let super_ = Access::load(&connection, 1).unwrap();
+        assert!(super_.is_super());
+        assert_eq!(super_.menus(), tree(&connection, false).unwrap());
+        assert!(super_.allows(&"Anything:at-all".parse().unwrap()));
+    }
+
+    #[test]
+    fn disabled_role() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        connection
+            .execute("UPDATE roles SET enabled = 0 WHERE code = 'R_ADMIN'", [])
+            .unwrap();
+        let admin = Access::load(&connection, 2).unwrap();
+        assert!(admin.roles().is_empty());
+        assert_eq!(names(admin.menus()), ["Result", "Exception"]);
+    }
+
+    #[test]
+    fn deactivated_user() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        connection
+            .execute("UPDATE users SET status = ?1 WHERE id = 1", [DEACTIVATED])
+            .unwrap();
+        let super_ = Access::load(&connection, 1).unwrap();
+        assert!(super_.roles().is_empty());
+        assert!(!super_.allows(&"User".parse().unwrap()));
+        assert_eq!(names(super_.menus()), ["Result", "Exception"]);
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let auth = Arc::new(Auth::random(AuthOptions::default()));
+        let token = |user_name: &str| {
+            let params = LoginParams {
+                user_name: user_name.to_owned(),
+                password: "123456".to_owned(),
+            };
+            auth.login(&store.lock(), &params, unix_now())
+                .unwrap()
+                .token
+        };
+        let (super_, admin, user) = (token("Super"), token("Admin"), token("User"));
+        let access_control = AccessControl::new(
+            AccessPolicy::default(),
+            Arc::clone(&store),
+            Arc::clone(&auth),
+        );
+        let app = Router::new()
+            .route("/{*path}", any(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(access_control),
+                super::access_control,
+            ));
+        let status = |method: Method, path: &str, token: Option<&str>| {
+            let mut request = http::Request::builder().method(method).uri(path);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, token);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(
+            status(Method::POST, "/api/auth/login", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::GET, "/index.html", None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::GET, "/api/user/list", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Method::GET, "/api/user/list", Some("forged")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Method::GET, "/api/user/info", Some(&admin)).await,
+            StatusCode::OK
+        );
+        // A HEAD request is answered like a GET, so it needs the same
+        // permission.
+        assert_eq!(
+            status(Method::HEAD, "/api/user/list", Some(&user)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Method::HEAD, "/api/user/list", Some(&admin)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::PUT, "/api/user/3", Some(&admin)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Method::DELETE, "/api/user/3", Some(&admin)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Method::POST, "/api/role", Some(&admin)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(Method::DELETE, "/api/menu/auth/1", Some(&super_)).await,
+            StatusCode::OK
+        );
+        // The token of a user whose sessions ended is refused, even where
+        // no permission is needed.
+        revoke_user(&store.lock(), 2).unwrap();
+        assert_eq!(
+            status(Method::GET, "/api/user/info", Some(&admin)).await,
+            StatusCode::UNAUTHORIZED
+        );
+        store
+            .lock()
+            .execute("UPDATE users SET status = ?1 WHERE id = 1", [DEACTIVATED])
+            .unwrap();
+        assert_eq!(
+            status(Method::GET, "/api/user/list", Some(&super_)).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
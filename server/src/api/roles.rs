@@ -0,0 +1,482 @@
+use crate::api::auth::AuthUser;
+use crate::api::rbac::{Access, SUPER_ROLE};
+use crate::api::store::Store;
+use crate::api::types::{RoleForm, RoleListItem, RolePermissions, RoleSearchParams, page};
+use crate::api::{ApiError, Filter};
+use crate::api_response::{ApiResponse, PaginatedResponse};
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use rusqlite::{Connection, Row, params, params_from_iter};
+use std::sync::Arc;
+
+const COLUMNS: &str = "id, name, code, description, enabled, create_time";
+
+fn role(row: &Row) -> rusqlite::Result<RoleListItem> {
+    Ok(RoleListItem {
+        role_id: row.get(0)?,
+        role_name: row.get(1)?,
+        role_code: row.get(2)?,
+        description: row.get(3)?,
+        enabled: row.get(4)?,
+        create_time: row.get(5)?,
+    })
+}
+
+/// A page of the roles matching the search, like
+/// [`RoleSearchParams::matches`].
+pub fn list(
+    connection: &Connection,
+    params: &RoleSearchParams,
+) -> Result<PaginatedResponse<RoleListItem>, ApiError> {
+    let (current, size) = page(params.current, params.size);
+    let mut filter = Filter::default();
+    filter
+        .eq("id", params.role_id.map(|id| id as i64))
+        .contains("name", params.role_name.as_deref())
+        .contains("code", params.role_code.as_deref())
+        .contains("description", params.description.as_deref())
+        .eq("enabled", params.enabled);
+    let total = connection.query_row(
+        &format!("SELECT count(*) FROM roles{}", filter.sql()),
+        params_from_iter(filter.values()),
+        |row| row.get(0),
+    )?;
+    let records = connection
+        .prepare(&format!(
+            "SELECT {COLUMNS} FROM roles{} ORDER BY id LIMIT {size} OFFSET {}",
+            filter.sql(),
+            (current - 1).saturating_mul(size)
+        ))?
+        .query_map(params_from_iter(filter.values()), role)?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(PaginatedResponse {
+        records,
+        current,
+        size,
+        total,
+    })
+}
+
+pub fn find(connection: &Connection, id: u64) -> Result<RoleListItem, ApiError> {
+    Ok(connection.query_row(
+        &format!("SELECT {COLUMNS} FROM roles WHERE id = ?1"),
+        [id],
+        role,
+    )?)
+}
+
+pub fn insert(connection: &Connection, form: &RoleForm) -> Result<RoleListItem, ApiError> {
+    validate(form)?;
+    connection.execute(
+        "INSERT INTO roles (name, code, description, enabled) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            form.role_name.trim(),
+            form.role_code,
+            form.description,
+            form.enabled
+        ],
+    )?;
+    find(connection, connection.last_insert_rowid() as u64)
+}
+
+/// Updates the role on behalf of the operator, who may change any role when
+/// they are a super administrator, and otherwise only the roles they hold but
+/// the super role. The super role keeps its code and stays enabled, and no
+/// other role takes its code.
+pub fn update(
+    connection: &Connection,
+    id: u64,
+    form: &RoleForm,
+    operator: &AuthUser,
+) -> Result<RoleListItem, ApiError> {
+    validate(form)?;
+    let role = find(connection, id)?;
+    authorize(connection, operator, &role)?;
+    let is_super = role.role_code == SUPER_ROLE;
+    if is_super != (form.role_code == SUPER_ROLE) || (is_super && !form.enabled) {
+        return Err(ApiError::Forbidden);
+    }
+    let updated = connection.execute(
+        "UPDATE roles SET name = ?2, code = ?3, description = ?4, enabled = ?5 WHERE id = ?1",
+        params![
+            id,
+            form.role_name.trim(),
+            form.role_code,
+            form.description,
+            form.enabled
+        ],
+    )?;
+    match updated {
+        0 => Err(ApiError::NotFound),
+        _ => find(connection, id),
+    }
+}
+
+/// Deletes the role, which its users and its menus lose, on behalf of the
+/// operator, who may change it as in [`update`]. The super role is never
+/// deleted.
+pub fn delete(connection: &Connection, id: u64, operator: &AuthUser) -> Result<(), ApiError> {
+    let role = find(connection, id)?;
+    authorize(connection, operator, &role)?;
+    if role.role_code == SUPER_ROLE {
+        return Err(ApiError::Forbidden);
+    }
+    match connection.execute("DELETE FROM roles WHERE id = ?1", [id])? {
+        0 => Err(ApiError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the operator may change the role. A super administrator may
+/// change any role, and anyone else only the roles they hold but the super
+/// role, so that they can't make themselves super. Returns the access of the
+/// operator.
+fn authorize(
+    connection: &Connection,
+    operator: &AuthUser,
+    role: &RoleListItem,
+) -> Result<Access, ApiError> {
+    let access = Access::load(connection, operator.id)?;
+    let held = role.role_code != SUPER_ROLE && access.roles().contains(&role.role_code);
+    if access.is_super() || held {
+        Ok(access)
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
+/// The role codes are joined with commas in the queries, and the frontend
+/// compares them as they are.
+fn validate(form: &RoleForm) -> Result<(), ApiError> {
+    if form.role_name.trim().is_empty() {
+        return Err(ApiError::BadRequest("The role name is empty".to_owned()));
+    }
+    if form.role_code.is_empty()
+        || !form
+            .role_code
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Invalid role code {:?}",
+            form.role_code
+        )));
+    }
+    Ok(())
+}
+
+pub fn permissions(connection: &Connection, id: u64) -> Result<RolePermissions, ApiError> {
+    find(connection, id)?;
+    let ids = |sql: &str| -> rusqlite::Result<Vec<u64>> {
+        connection
+            .prepare(sql)?
+            .query_map([id], |row| row.get(0))?
+            .collect()
+    };
+    Ok(RolePermissions {
+        menu_ids: ids("SELECT menu_id FROM role_menus WHERE role_id = ?1 ORDER BY menu_id")?,
+        auth_ids: ids("SELECT auth_id FROM role_auths WHERE role_id = ?1 ORDER BY auth_id")?,
+    })
+}
+
+/// Grants exactly these menus and buttons to the role on behalf of the
+/// operator, who may change it as in [`update`]. Anyone but a super
+/// administrator can only add the menus and the buttons they are granted
+/// themselves.
+pub fn set_permissions(
+    connection: &mut Connection,
+    id: u64,
+    granted: &RolePermissions,
+    operator: &AuthUser,
+) -> Result<RolePermissions, ApiError> {
+    let transaction = connection.transaction()?;
+    let role = find(&transaction, id)?;
+    let access = authorize(&transaction, operator, &role)?;
+    if !access.is_super() {
+        let current = permissions(&transaction, id)?;
+        let own = access.permissions();
+        // The ids granted anew that the operator isn't granted.
+        let foreign = |granted: &[u64], current: &[u64], own: &[u64]| {
+            granted
+                .iter()
+                .any(|id| !current.contains(id) && !own.contains(id))
+        };
+        if foreign(&granted.menu_ids, &current.menu_ids, &own.menu_ids)
+            || foreign(&granted.auth_ids, &current.auth_ids, &own.auth_ids)
+        {
+            return Err(ApiError::Forbidden);
+        }
+    }
+    transaction.execute("DELETE FROM role_menus WHERE role_id = ?1", [id])?;
+    transaction.execute("DELETE FROM role_auths WHERE role_id = ?1", [id])?;
+    for menu_id in &granted.menu_ids {
+        transaction.execute(
+            "INSERT OR IGNORE INTO role_menus (role_id, menu_id) VALUES (?1, ?2)",
+            [id, *menu_id],
+        )?;
+    }
+    for auth_id in &granted.auth_ids {
+        transaction.execute(
+            "INSERT OR IGNORE INTO role_auths (role_id, auth_id) VALUES (?1, ?2)",
+            [id, *auth_id],
+        )?;
+    }
+    transaction.commit()?;
+    permissions(connection, id)
+}
+
+/// The handler of `GET /api/role/list`.
+pub async fn list_roles(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    params: Result<Query<RoleSearchParams>, QueryRejection>,
+) -> Result<ApiResponse<PaginatedResponse<RoleListItem>>, ApiError> {
+    let Query(params) = params?;
+    let roles = store
+        .run(move |connection| list(connection, &params))
+        .await?;
+    Ok(ApiResponse::success(roles))
+}
+
+/// The handler of `POST /api/role`.
+pub async fn create_role(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    form: Result<Json<RoleForm>, JsonRejection>,
+) -> Result<ApiResponse<RoleListItem>, ApiError> {
+    let Json(form) = form?;
+    let role = store
+        .run(move |connection| insert(connection, &form))
+        .await?;
+    Ok(ApiResponse::success(role))
+}
+
+/// The handler of `PUT /api/role/{id}`.
+pub async fn update_role(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    form: Result<Json<RoleForm>, JsonRejection>,
+) -> Result<ApiResponse<RoleListItem>, ApiError> {
+    let (Path(id), Json(form)) = (id?, form?);
+    let role = store
+        .run(move |connection| update(connection, id, &form, &user))
+        .await?;
+    Ok(ApiResponse::success(role))
+}
+
+/// The handler of `DELETE /api/role/{id}`.
+pub async fn delete_role(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Path(id) = id?;
+    store
+        .run(move |connection| delete(connection, id, &user))
+        .await?;
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `GET /api/role/{id}/permissions`.
+pub async fn get_role_permissions(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<RolePermissions>, ApiError> {
+    let Path(id) = id?;
+    let permissions = store
+        .run(move |connection| permissions(connection, id))
+        .await?;
+    Ok(ApiResponse::success(permissions))
+}
+
+/// The handler of `PUT /api/role/{id}/permissions`.
+pub async fn update_role_permissions(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    permissions: Result<Json<RolePermissions>, JsonRejection>,
+) -> Result<ApiResponse<RolePermissions>, ApiError> {
+    let (Path(id), Json(permissions)) = (id?, permissions?);
+    let permissions = store
+        .run(move |connection| set_permissions(connection, id, &permissions, &user))
+        .await?;
+    Ok(ApiResponse::success(permissions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(role_code: &str) -> RoleForm {
+        serde_json::from_value(serde_json::json!({
+            "roleName": "审计员",
+            "roleCode": role_code,
+        }))
+        .unwrap()
+    }
+
+    fn operator(id: u64, name: &str) -> AuthUser {
+        AuthUser {
+            id,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn crud() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let super_ = operator(1, "Super");
+        let role = insert(&connection, &form("R_AUDIT")).unwrap();
+        assert_eq!(role.role_id, 8);
+        assert!(role.enabled);
+        let role = update(&connection, 8, &form("R_AUDITOR"), &super_).unwrap();
+        assert_eq!(role.role_code, "R_AUDITOR");
+        assert!(matches!(
+            insert(&connection, &form("R_ADMIN")),
+            Err(ApiError::Conflict(_))
+        ));
+        assert!(matches!(
+            insert(&connection, &form("R_A,R_B")),
+            Err(ApiError::BadRequest(_))
+        ));
+
+        // The users of a deleted role lose it.
+        delete(&connection, 2, &super_).unwrap();
+        let roles: Option<String> = connection
+            .query_row(
+                "SELECT group_concat(role_id) FROM user_roles WHERE user_id = 2",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(roles, None);
+        assert!(matches!(
+            delete(&connection, 2, &super_),
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn search() {
+        let store = Store::open_in_memory().unwrap();
+        let connection = store.lock();
+        let params = RoleSearchParams {
+            enabled: Some(false),
+            ..RoleSearchParams::default()
+        };
+        let page = list(&connection, &params).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.records[0].role_code, "R_SERVICE");
+        let params = RoleSearchParams {
+            role_code: Some("r_s".to_owned()),
+            ..RoleSearchParams::default()
+        };
+        assert_eq!(list(&connection, &params).unwrap().total, 2);
+    }
+
+    #[test]
+    fn permissions() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let super_ = operator(1, "Super");
+        assert_eq!(
+            super::permissions(&connection, 2).unwrap(),
+            RolePermissions {
+                menu_ids: vec![1, 2, 21],
+                auth_ids: vec![1, 2, 4, 5],
+            }
+        );
+        let permissions = RolePermissions {
+            menu_ids: vec![3, 31, 3],
+            auth_ids: vec![],
+        };
+        assert_eq!(
+            set_permissions(&mut connection, 2, &permissions, &super_).unwrap(),
+            RolePermissions {
+                menu_ids: vec![3, 31],
+                auth_ids: vec![],
+            }
+        );
+        // Nothing changes when a menu doesn't exist.
+        let permissions = RolePermissions {
+            menu_ids: vec![1, 99],
+            auth_ids: vec![],
+        };
+        assert!(matches!(
+            set_permissions(&mut connection, 2, &permissions, &super_),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert_eq!(
+            super::permissions(&connection, 2).unwrap().menu_ids,
+            [3, 31]
+        );
+        assert!(matches!(
+            set_permissions(&mut connection, 99, &RolePermissions::default(), &super_),
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn escalation() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let (super_, admin) = (operator(1, "Super"), operator(2, "Admin"));
+        let mut role = form("R_ADMIN");
+        role.description = "管理员".to_owned();
+        assert!(update(&connection, 2, &role, &admin).is_ok());
+        // The administrator can't make their role super, nor change the super
+        // role or a role they don't hold.
+        for (id, code, operator) in [
+            (2, "R_SUPER", &admin),
+            (1, "R_SUPER", &admin),
+            (3, "R_USER", &admin),
+            (1, "R_ROOT", &super_),
+        ] {
+            assert!(matches!(
+                update(&connection, id, &form(code), operator),
+                Err(ApiError::Forbidden)
+            ));
+        }
+        let mut disabled = form("R_SUPER");
+        disabled.enabled = false;
+        assert!(matches!(
+            update(&connection, 1, &disabled, &super_),
+            Err(ApiError::Forbidden)
+        ));
+        for (id, operator) in [(1, &admin), (3, &admin), (1, &super_)] {
+            assert!(matches!(
+                delete(&connection, id, operator),
+                Err(ApiError::Forbidden)
+            ));
+        }
+
+        // The administrator only adds the menus and the buttons they have.
+        let granted = |menu_ids: &[u64], auth_ids: &[u64]| RolePermissions {
+            menu_ids: menu_ids.to_vec(),
+            auth_ids: auth_ids.to_vec(),
+        };
+        for (id, granted) in [
+            (2, granted(&[1, 2, 21, 22], &[1, 2, 4, 5])),
+            (2, granted(&[1, 2, 21], &[1, 2, 4, 5, 6])),
+            (3, granted(&[], &[])),
+        ] {
+            assert!(matches!(
+                set_permissions(&mut connection, id, &granted, &admin),
+                Err(ApiError::Forbidden)
+            ));
+        }
+        let granted = granted(&[1, 2, 21], &[1]);
+        assert_eq!(
+            set_permissions(&mut connection, 2, &granted, &admin).unwrap(),
+            granted
+        );
+        assert_eq!(
+            set_permissions(&mut connection, 3, &granted, &super_).unwrap(),
+            granted
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use crate::api::ApiError;
+use rusqlite::Connection;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The migrations of the schema, applied in order. The version of a database
+/// is the number of the migrations applied to it, kept in `user_version`.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/0001_system.sql"),
+    include_str!("../../migrations/0002_seed.sql"),
+    include_str!("../../migrations/0003_auth.sql"),
+    include_str!("../../migrations/0004_rbac.sql"),
+    include_str!("../../migrations/0005_accounts.sql"),
+    include_str!("../../migrations/0006_token_generation.sql"),
+];
+
+/// How long a statement waits for a lock held by another connection, e.g.
+/// an administrator inspecting the file.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The SQLite database of the API.
+///
+/// The connection is shared behind a mutex, and the queries run on the
+/// blocking threads of Tokio.
+///
+/// # Examples
+///
+/// ```
+/// use server::api::store::Store;
+///
+/// let store = Store::open_in_memory().unwrap();
+/// assert_eq!(store.version().unwrap(), 6);
+/// ```
+#[derive(Debug)]
+pub struct Store {
+    connection: Mutex<Connection>,
+}
+
+/// The reasons a database can't be opened.
+#[derive(Debug)]
+pub enum StoreError {
+    Database(rusqlite::Error),
+    /// The database was migrated by a newer server, whose schema this one
+    /// doesn't know.
+    NewerSchema {
+        version: usize,
+    },
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Database(error) => write!(f, "{error}"),
+            StoreError::NewerSchema { version } => write!(
+                f,
+                "the database is at version {version}, newer than the {} migrations of the server",
+                MIGRATIONS.len()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self {
+        StoreError::Database(error)
+    }
+}
+
+impl Store {
+    /// Opens or creates the database file and migrates it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Store::new(connection)
+    }
+
+    /// A migrated database living in memory, lost when dropped.
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Store::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<Self, StoreError> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        migrate(&mut connection)?;
+        Ok(Store {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// The number of the migrations applied to the database.
+    pub fn version(&self) -> rusqlite::Result<usize> {
+        version(&self.lock())
+    }
+
+    /// Runs the queries on a blocking thread.
+    pub async fn run<T, F>(self: &Arc<Self>, f: F) -> Result<T, ApiError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, ApiError> + Send + 'static,
+    {
+        let store = Arc::clone(self);
+        match tokio::task::spawn_blocking(move || f(&mut store.lock())).await {
+            Ok(result) => result,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+
+    /// A panic in a query doesn't leave the connection in a broken state, an
+    /// unfinished transaction is rolled back when dropped.
+    pub(crate) fn lock(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn version(connection: &Connection) -> rusqlite::Result<usize> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Applies the migrations the database lacks, each in its own transaction.
+fn migrate(connection: &mut Connection) -> Result<(), StoreError> {
+    let current = version(connection)?;
+    if current > MIGRATIONS.len() {
+        return Err(StoreError::NewerSchema { version: current });
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reopen() {
+        let path = std::env::temp_dir().join(format!("server-store-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let store = Store::open(&path).unwrap();
+            assert_eq!(store.version().unwrap(), MIGRATIONS.len());
+            store
+                .lock()
+                .execute("DELETE FROM users WHERE user_name = 'User'", [])
+                .unwrap();
+        }
+        // The migrations already applied aren't applied again, the seed
+        // doesn't come back.
+        let store = Store::open(&path).unwrap();
+        let users: u64 = store
+            .lock()
+            .query_row("SELECT count(*) FROM users", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(users, 2);
+        store
+            .lock()
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(store);
+        assert!(matches!(
+            Store::open(&path),
+            Err(StoreError::NewerSchema { version }) if version == MIGRATIONS.len() + 1
+        ));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let store = Arc::new(Store::open_in_memory().unwrap());
+        let roles = store
+            .run(|connection| {
+                Ok(connection
+                    .query_row("SELECT count(*) FROM roles", [], |row| row.get::<_, u64>(0))?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(roles, 7);
+    }
+}
@@ -0,0 +1,531 @@
+use crate::api::ApiError;
+use crate::api::auth::unix_now;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+pub const DEFAULT_ACCOUNT_LIMIT: u32 = 5;
+pub const DEFAULT_IP_LIMIT: u32 = 20;
+pub const DEFAULT_BASE_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+pub const DEFAULT_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// The time of the throttle, in seconds since the Unix epoch, which the
+/// tests move forward at will.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// The time of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        unix_now()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ThrottleOptions {
+    /// The failed logins of an account that lock it.
+    pub account_limit: u32,
+    /// The failed logins from an IP address that lock it out, higher than
+    /// the limit of an account as an office shares one.
+    pub ip_limit: u32,
+    /// The wait after the first failed login, doubled by each next one.
+    pub base_delay: Duration,
+    /// The longest wait before the lockout.
+    pub max_delay: Duration,
+    /// How long a lockout lasts, and how long the failed logins are
+    /// remembered.
+    pub lockout: Duration,
+    /// The failed logins of an account or from an IP address after which the
+    /// answers ask for a CAPTCHA, `None` to never ask.
+    pub captcha_after: Option<u32>,
+}
+
+impl Default for ThrottleOptions {
+    fn default() -> Self {
+        ThrottleOptions {
+            account_limit: DEFAULT_ACCOUNT_LIMIT,
+            ip_limit: DEFAULT_IP_LIMIT,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            lockout: DEFAULT_LOCKOUT,
+            captcha_after: None,
+        }
+    }
+}
+
+/// The failed logins of an account or from an IP address.
+#[derive(Clone, Copy, Debug)]
+struct Failures {
+    count: u32,
+    last: u64,
+    blocked_until: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    accounts: HashMap<String, Failures>,
+    ips: HashMap<IpAddr, Failures>,
+    /// The accounts with a login being checked, so that the guesses can't be
+    /// sent at once before the first one fails.
+    pending: HashSet<String>,
+}
+
+/// Slows down the guessing of passwords.
+///
+/// Each failed login makes the account wait before the next one, twice as long
+/// as the previous wait, until too many failures lock it out. So does an IP
+/// address after as many failures as lock an account, of any accounts, until
+/// more failures lock it out. The failures are forgotten when there is none for
+/// the duration of the lockout, and those of an account when it logs in. An
+/// administrator can unlock an account before.
+///
+/// # Examples
+///
+/// ```
+/// use server::api::ApiError;
+/// use server::api::throttle::{LoginThrottle, ThrottleOptions};
+///
+/// let throttle = LoginThrottle::new(ThrottleOptions::default());
+/// let attempt = throttle.attempt("admin", None).unwrap();
+/// attempt.failed(ApiError::BadRequest("Wrong password".to_owned()));
+/// assert!(throttle.attempt("admin", None).is_err());
+/// assert!(throttle.unlock("Admin"));
+/// assert!(throttle.attempt("admin", None).is_ok());
+/// ```
+#[derive(Debug)]
+pub struct LoginThrottle {
+    options: ThrottleOptions,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+}
+
+impl LoginThrottle {
+    pub fn new(options: ThrottleOptions) -> Self {
+        LoginThrottle::with_clock(options, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(options: ThrottleOptions, clock: Arc<dyn Clock>) -> Self {
+        LoginThrottle {
+            options,
+            clock,
+            state: Mutex::default(),
+        }
+    }
+
+    /// A throttle with the same options and clock, whose accounts and IP
+    /// addresses are counted apart, e.g. to throttle other requests than the
+    /// logins.
+    pub fn separate(&self) -> Self {
+        LoginThrottle::with_clock(self.options.clone(), Arc::clone(&self.clock))
+    }
+
+    pub fn options(&self) -> &ThrottleOptions {
+        &self.options
+    }
+
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Starts a login of the account, refused while the account or the IP
+    /// address has to wait or while another login of the account is checked.
+    /// The user names are compared ignoring the case, like in the database.
+    pub fn attempt(&self, user_name: &str, ip: Option<IpAddr>) -> Result<Attempt<'_>, LoginError> {
+        let account = user_name.trim().to_lowercase();
+        let now = self.now();
+        let mut state = self.lock();
+        let blocked_until = [
+            state.accounts.get(&account),
+            ip.and_then(|ip| state.ips.get(&ip)),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|failures| !self.forgotten(failures, now))
+        .map(|failures| failures.blocked_until)
+        .max()
+        .unwrap_or_default();
+        let challenge = self.challenge(&state, &account, ip, now);
+        if blocked_until > now {
+            return Err(LoginError::new(
+                ApiError::TooManyRequests(blocked_until - now),
+                challenge,
+            ));
+        }
+        if !state.pending.insert(account.clone()) {
+            return Err(LoginError::new(ApiError::TooManyRequests(1), challenge));
+        }
+        Ok(Attempt {
+            throttle: self,
+            account,
+            ip,
+        })
+    }
+
+    /// Forgets the failed logins of the account, e.g. to end its lockout.
+    /// Whether it had any.
+    pub fn unlock(&self, user_name: &str) -> bool {
+        let account = user_name.trim().to_lowercase();
+        self.lock().accounts.remove(&account).is_some()
+    }
+
+    /// The failures are forgotten after a lockout, so the next failure
+    /// doesn't lock again at once.
+    fn forgotten(&self, failures: &Failures, now: u64) -> bool {
+        now >= failures.last.saturating_add(self.options.lockout.as_secs())
+    }
+
+    fn challenge(
+        &self,
+        state: &State,
+        account: &str,
+        ip: Option<IpAddr>,
+        now: u64,
+    ) -> Option<Challenge> {
+        let captcha_after = self.options.captcha_after?;
+        let count = |failures: Option<&Failures>| {
+            failures
+                .filter(|failures| !self.forgotten(failures, now))
+                .map_or(0, |failures| failures.count)
+        };
+        let count =
+            count(state.accounts.get(account)).max(count(ip.and_then(|ip| state.ips.get(&ip))));
+        (count >= captcha_after).then_some(Challenge { captcha: true })
+    }
+
+    /// Counts a failure, after which the wait is doubled for each failure
+    /// past the `free` ones.
+    fn record<K: Eq + Hash>(
+        &self,
+        failures: &mut HashMap<K, Failures>,
+        key: K,
+        (free, limit): (u32, u32),
+        now: u64,
+    ) {
+        failures.retain(|_, failures| !self.forgotten(failures, now));
+        let failures = failures.entry(key).or_insert(Failures {
+            count: 0,
+            last: now,
+            blocked_until: now,
+        });
+        failures.count += 1;
+        failures.last = now;
+        let delay = if failures.count >= limit {
+            self.options.lockout
+        } else if failures.count > free {
+            self.options
+                .base_delay
+                .saturating_mul(1 << (failures.count - free - 1).min(31))
+                .min(self.options.max_delay)
+        } else {
+            Duration::ZERO
+        };
+        failures.blocked_until = now + delay.as_secs();
+    }
+}
+
+/// A login being checked, which ends with [`succeeded`](Attempt::succeeded)
+/// or [`failed`](Attempt::failed).
+#[derive(Debug)]
+pub struct Attempt<'a> {
+    throttle: &'a LoginThrottle,
+    account: String,
+    ip: Option<IpAddr>,
+}
+
+impl Attempt<'_> {
+    pub fn succeeded(self) {
+        self.throttle.lock().accounts.remove(&self.account);
+    }
+
+    /// Counts the failure of the login with this error.
+    pub fn failed(self, error: ApiError) -> LoginError {
+        let challenge = self.record();
+        LoginError::new(error, challenge)
+    }
+
+    /// Counts the attempt like a failure whatever its outcome, e.g. for the
+    /// requests which mail a token.
+    pub fn count(self) {
+        self.record();
+    }
+
+    fn record(&self) -> Option<Challenge> {
+        let throttle = self.throttle;
+        let now = throttle.now();
+        let mut state = throttle.lock();
+        let options = &throttle.options;
+        throttle.record(
+            &mut state.accounts,
+            self.account.clone(),
+            (0, options.account_limit),
+            now,
+        );
+        if let Some(ip) = self.ip {
+            // Some users of an office may mistype their passwords before
+            // its IP address has to wait.
+            let limits = (options.account_limit, options.ip_limit);
+            throttle.record(&mut state.ips, ip, limits, now);
+        }
+        throttle.challenge(&state, &self.account, self.ip, now)
+    }
+}
+
+impl Drop for Attempt<'_> {
+    fn drop(&mut self) {
+        self.throttle.lock().pending.remove(&self.account);
+    }
+}
+
+/// The `data` of the answer to a failed login.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Challenge {
+    /// The frontend should show a CAPTCHA before the next login.
+    pub captcha: bool,
+}
+
+/// The error of a login, with a [`Challenge`] when the throttle asks for a
+/// CAPTCHA.
+#[derive(Debug)]
+pub struct LoginError {
+    pub error: ApiError,
+    pub challenge: Option<Challenge>,
+}
+
+impl LoginError {
+    pub fn new(error: ApiError, challenge: Option<Challenge>) -> Self {
+        LoginError { error, challenge }
+    }
+}
+
+impl From<ApiError> for LoginError {
+    fn from(error: ApiError) -> Self {
+        LoginError::new(error, None)
+    }
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        match self.challenge {
+            Some(challenge) => self.error.into_response_with(challenge),
+            None => self.error.into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ApiState;
+    use crate::api::auth::{self, Auth, AuthOptions};
+    use crate::api::store::Store;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::post;
+    use http::{StatusCode, header};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tower::ServiceExt;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[derive(Debug)]
+    struct MockClock(AtomicU64);
+
+    impl MockClock {
+        fn advance(&self, secs: u64) {
+            self.0.fetch_add(secs, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn throttle(options: ThrottleOptions) -> (LoginThrottle, Arc<MockClock>) {
+        let clock = Arc::new(MockClock(AtomicU64::new(NOW)));
+        let throttle = LoginThrottle::with_clock(options, Arc::clone(&clock) as Arc<dyn Clock>);
+        (throttle, clock)
+    }
+
+    fn wrong() -> ApiError {
+        ApiError::BadRequest("Wrong user name or password".to_owned())
+    }
+
+    fn retry_after(result: Result<Attempt, LoginError>) -> Option<u64> {
+        match result {
+            Ok(_) => None,
+            Err(LoginError {
+                error: ApiError::TooManyRequests(secs),
+                ..
+            }) => Some(secs),
+            Err(error) => panic!("unexpected {error}"),
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let (throttle, clock) = throttle(ThrottleOptions::default());
+        for delay in [1, 2, 4, 8] {
+            throttle.attempt("Admin", None).unwrap().failed(wrong());
+            assert_eq!(retry_after(throttle.attempt("admin", None)), Some(delay));
+            clock.advance(delay - 1);
+            assert_eq!(retry_after(throttle.attempt("admin", None)), Some(1));
+            clock.advance(1);
+        }
+        // Other accounts don't wait.
+        assert_eq!(retry_after(throttle.attempt("super", None)), None);
+
+        // A login forgets the failures.
+        throttle.attempt("admin", None).unwrap().succeeded();
+        throttle.attempt("admin", None).unwrap().failed(wrong());
+        assert_eq!(retry_after(throttle.attempt("admin", None)), Some(1));
+    }
+
+    #[test]
+    fn lockout() {
+        let (throttle, clock) = throttle(ThrottleOptions::default());
+        for _ in 0..DEFAULT_ACCOUNT_LIMIT {
+            throttle.attempt("admin", None).unwrap().failed(wrong());
+            clock.advance(DEFAULT_MAX_DELAY.as_secs());
+        }
+        let lockout = DEFAULT_LOCKOUT.as_secs() - DEFAULT_MAX_DELAY.as_secs();
+        assert_eq!(retry_after(throttle.attempt("admin", None)), Some(lockout));
+        clock.advance(lockout);
+        // The failures are forgotten with the lockout.
+        throttle.attempt("admin", None).unwrap().failed(wrong());
+        assert_eq!(retry_after(throttle.attempt("admin", None)), Some(1));
+
+        assert!(throttle.unlock("ADMIN"));
+        assert!(!throttle.unlock("admin"));
+        assert_eq!(retry_after(throttle.attempt("admin", None)), None);
+    }
+
+    #[test]
+    fn ip() {
+        let options = ThrottleOptions {
+            ip_limit: 8,
+            ..ThrottleOptions::default()
+        };
+        let (throttle, clock) = throttle(options);
+        let ip = Some(IpAddr::from([192, 168, 1, 7]));
+        for account in ["a", "b", "c", "d", "e"] {
+            throttle.attempt(account, ip).unwrap().failed(wrong());
+        }
+        throttle.attempt("f", ip).unwrap().failed(wrong());
+        assert_eq!(retry_after(throttle.attempt("g", ip)), Some(1));
+        clock.advance(1);
+        throttle.attempt("g", ip).unwrap().failed(wrong());
+        assert_eq!(retry_after(throttle.attempt("h", ip)), Some(2));
+        clock.advance(2);
+        throttle.attempt("h", ip).unwrap().failed(wrong());
+        assert_eq!(
+            retry_after(throttle.attempt("i", ip)),
+            Some(DEFAULT_LOCKOUT.as_secs())
+        );
+        // An unlocked account is still locked out from the IP address.
+        throttle.unlock("i");
+        assert!(throttle.attempt("i", ip).is_err());
+        let other = Some(IpAddr::from([192, 168, 1, 8]));
+        assert_eq!(retry_after(throttle.attempt("i", other)), None);
+    }
+
+    #[test]
+    fn pending() {
+        let (throttle, _) = throttle(ThrottleOptions::default());
+        let attempt = throttle.attempt("admin", None).unwrap();
+        assert_eq!(retry_after(throttle.attempt("Admin", None)), Some(1));
+        drop(attempt);
+        assert_eq!(retry_after(throttle.attempt("admin", None)), None);
+    }
+
+    #[test]
+    fn separate() {
+        let (throttle, clock) = throttle(ThrottleOptions::default());
+        let separate = throttle.separate();
+        separate.attempt("admin", None).unwrap().count();
+        assert_eq!(retry_after(separate.attempt("admin", None)), Some(1));
+        assert_eq!(retry_after(throttle.attempt("admin", None)), None);
+        // The clock is shared.
+        clock.advance(1);
+        assert_eq!(retry_after(separate.attempt("admin", None)), None);
+    }
+
+    #[test]
+    fn captcha() {
+        let options = ThrottleOptions {
+            captcha_after: Some(2),
+            ..ThrottleOptions::default()
+        };
+        let (throttle, clock) = throttle(options);
+        let error = throttle.attempt("admin", None).unwrap().failed(wrong());
+        assert_eq!(error.challenge, None);
+        clock.advance(1);
+        let error = throttle.attempt("admin", None).unwrap().failed(wrong());
+        assert_eq!(error.challenge, Some(Challenge { captcha: true }));
+        let error = throttle.attempt("admin", None).unwrap_err();
+        assert_eq!(error.challenge, Some(Challenge { captcha: true }));
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+
+    #[tokio::test]
+    async fn login() {
+        let (throttle, clock) = throttle(ThrottleOptions {
+            account_limit: 2,
+            ..ThrottleOptions::default()
+        });
+        let state = ApiState {
+            store: Arc::new(Store::open_in_memory().unwrap()),
+            auth: Arc::new(Auth::random(AuthOptions::default())),
+            throttle: Arc::new(throttle),
+        };
+        let app = Router::new()
+            .route("/api/auth/login", post(auth::login))
+            .with_state(state.clone());
+        let status = |password: &str| {
+            let request = http::Request::post("/api/auth/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(format!(
+                    r#"{{"userName":"Admin","password":"{password}"}}"#
+                )))
+                .unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(status("wrong").await, StatusCode::BAD_REQUEST);
+        assert_eq!(status("123456").await, StatusCode::TOO_MANY_REQUESTS);
+        clock.advance(1);
+        assert_eq!(status("wrong").await, StatusCode::BAD_REQUEST);
+        clock.advance(60);
+        assert_eq!(status("123456").await, StatusCode::TOO_MANY_REQUESTS);
+        state.throttle.unlock("Admin");
+        assert_eq!(status("123456").await, StatusCode::OK);
+    }
+}
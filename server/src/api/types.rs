@@ -0,0 +1,382 @@
+//! The types of the requests and the responses of the API, mirroring the
+//! declarations of the frontend in `src/types/api/api.d.ts`.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The page size of the lists when the request has no `size`, like the
+/// `useTable` hook of the frontend.
+pub const DEFAULT_PAGE_SIZE: u64 = 10;
+
+/// The page and the page size of a search, pages are numbered from 1.
+pub fn page(current: Option<u64>, size: Option<u64>) -> (u64, u64) {
+    (
+        current.unwrap_or(1).max(1),
+        size.unwrap_or(DEFAULT_PAGE_SIZE).max(1),
+    )
+}
+
+/// The `UserListItem` of the frontend.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserListItem {
+    pub id: u64,
+    pub avatar: String,
+    pub status: String,
+    pub user_name: String,
+    pub user_gender: String,
+    pub nick_name: String,
+    pub user_phone: String,
+    pub user_email: String,
+    pub user_roles: Vec<String>,
+    pub create_by: String,
+    pub create_time: String,
+    pub update_by: String,
+    pub update_time: String,
+}
+
+/// The `RoleListItem` of the frontend.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleListItem {
+    pub role_id: u64,
+    pub role_name: String,
+    pub role_code: String,
+    pub description: String,
+    pub enabled: bool,
+    pub create_time: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginParams {
+    pub user_name: String,
+    pub password: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginResponse {
+    pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    pub buttons: Vec<String>,
+    pub roles: Vec<String>,
+    pub user_id: u64,
+    pub user_name: String,
+    pub email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+}
+
+/// The `UserSearchParams` of the frontend. The forms of the frontend send
+/// the fields they don't filter on as empty strings, which are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserSearchParams {
+    #[serde(deserialize_with = "non_empty")]
+    pub id: Option<u64>,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_name: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_gender: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_phone: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub user_email: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub status: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub current: Option<u64>,
+    #[serde(deserialize_with = "non_empty")]
+    pub size: Option<u64>,
+}
+
+impl UserSearchParams {
+    /// The id, the status and the gender must be equal, the other fields
+    /// are searched for case-insensitively. The search form sends the gender
+    /// as `1` or `2`, which stand for `男` and `女`.
+    pub fn matches(&self, user: &UserListItem) -> bool {
+        let gender = self.user_gender.as_deref().map(gender);
+        self.id.is_none_or(|id| id == user.id)
+            && contains(&self.user_name, &user.user_name)
+            && gender.is_none_or(|gender| gender == user.user_gender)
+            && contains(&self.user_phone, &user.user_phone)
+            && contains(&self.user_email, &user.user_email)
+            && self
+                .status
+                .as_ref()
+                .is_none_or(|status| *status == user.status)
+    }
+}
+
+/// The `RoleSearchParams` of the frontend.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RoleSearchParams {
+    #[serde(deserialize_with = "non_empty")]
+    pub role_id: Option<u64>,
+    #[serde(deserialize_with = "non_empty")]
+    pub role_name: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub role_code: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub description: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub enabled: Option<bool>,
+    #[serde(deserialize_with = "non_empty")]
+    pub current: Option<u64>,
+    #[serde(deserialize_with = "non_empty")]
+    pub size: Option<u64>,
+}
+
+impl RoleSearchParams {
+    pub fn matches(&self, role: &RoleListItem) -> bool {
+        self.role_id.is_none_or(|id| id == role.role_id)
+            && contains(&self.role_name, &role.role_name)
+            && contains(&self.role_code, &role.role_code)
+            && contains(&self.description, &role.description)
+            && self.enabled.is_none_or(|enabled| enabled == role.enabled)
+    }
+}
+
+/// The gender of a search, the search form sends `1` or `2` for `男` and
+/// `女`.
+pub(crate) fn gender(search: &str) -> &str {
+    match search {
+        "1" => "男",
+        "2" => "女",
+        gender => gender,
+    }
+}
+
+/// Whether the value contains the searched text, ignoring the case.
+fn contains(search: &Option<String>, value: &str) -> bool {
+    search
+        .as_ref()
+        .is_none_or(|search| value.to_lowercase().contains(&search.to_lowercase()))
+}
+
+/// Deserializes a query parameter, an empty one is `None`.
+fn non_empty<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.trim().is_empty() => {
+            value.trim().parse().map(Some).map_err(D::Error::custom)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// A user to create or to replace. The roles are role codes, the optional
+/// fields default to empty, and the status to `1`. The password is kept when
+/// the form has none, and a user without a password can't log in.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserForm {
+    pub user_name: String,
+    #[serde(default)]
+    pub nick_name: String,
+    #[serde(default)]
+    pub avatar: String,
+    #[serde(default)]
+    pub user_gender: String,
+    #[serde(default)]
+    pub user_phone: String,
+    #[serde(default)]
+    pub user_email: String,
+    #[serde(default = "UserForm::default_status")]
+    pub status: String,
+    #[serde(default)]
+    pub user_roles: Vec<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl UserForm {
+    fn default_status() -> String {
+        "1".to_owned()
+    }
+}
+
+/// The body of `POST /api/auth/refresh` and `POST /api/auth/logout`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshParams {
+    pub refresh_token: String,
+}
+
+/// The body of `POST /api/auth/register`, the register view of the
+/// frontend with the email address to verify.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterParams {
+    pub user_name: String,
+    pub password: String,
+    pub email: String,
+}
+
+/// The body of `POST /api/auth/register/verify`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyParams {
+    pub token: String,
+}
+
+/// The body of `POST /api/auth/password-reset`, the forget-password view of
+/// the frontend, with a user name or an email address.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetParams {
+    pub user_name: String,
+}
+
+/// The body of `POST /api/auth/password-reset/confirm`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordParams {
+    pub token: String,
+    pub password: String,
+}
+
+/// A role to create or to replace, enabled unless told otherwise.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleForm {
+    pub role_name: String,
+    pub role_code: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+/// The menus and the buttons granted to a role, the permission dialog of
+/// the role page.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RolePermissions {
+    #[serde(default)]
+    pub menu_ids: Vec<u64>,
+    #[serde(default)]
+    pub auth_ids: Vec<u64>,
+}
+
+/// A menu to create or to replace. `name` is the name of the route and
+/// `title` the title of the menu, like in `AppRouteRecord`. When `roles` is
+/// given, the menu is granted to exactly these role codes.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuForm {
+    #[serde(default)]
+    pub parent_id: Option<u64>,
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub component: String,
+    pub title: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub sort: i64,
+    #[serde(default = "enabled")]
+    pub is_enable: bool,
+    #[serde(default)]
+    pub keep_alive: bool,
+    #[serde(default)]
+    pub is_hide: bool,
+    #[serde(default)]
+    pub is_hide_tab: bool,
+    #[serde(default)]
+    pub link: String,
+    #[serde(default)]
+    pub is_iframe: bool,
+    #[serde(default)]
+    pub show_badge: bool,
+    #[serde(default)]
+    pub show_text_badge: String,
+    #[serde(default)]
+    pub fixed_tab: bool,
+    #[serde(default)]
+    pub active_path: String,
+    #[serde(default)]
+    pub is_full_page: bool,
+    #[serde(default)]
+    pub roles: Option<Vec<String>>,
+}
+
+/// A button of a menu, whose `authMark` the `v-auth` directive checks.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthForm {
+    pub title: String,
+    pub auth_mark: String,
+    #[serde(default)]
+    pub sort: i64,
+}
+
+/// The `AppRouteRecord` of the frontend in `src/types/router/index.ts`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuRecord {
+    pub id: u64,
+    pub path: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub component: String,
+    pub meta: MenuMeta,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<MenuRecord>,
+}
+
+/// The `RouteMeta` of the frontend, the empty fields are left out.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MenuMeta {
+    pub title: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub icon: String,
+    pub sort: i64,
+    pub is_enable: bool,
+    pub keep_alive: bool,
+    pub is_hide: bool,
+    pub is_hide_tab: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub link: String,
+    pub is_iframe: bool,
+    pub show_badge: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub show_text_badge: String,
+    pub fixed_tab: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub active_path: String,
+    pub is_full_page: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub auth_list: Vec<AuthItem>,
+}
+
+/// An item of the `authList` of `RouteMeta`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthItem {
+    pub id: u64,
+    pub title: String,
+    pub auth_mark: String,
+    pub sort: i64,
+}
+
+fn enabled() -> bool {
+    true
+}
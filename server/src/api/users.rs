@@ -0,0 +1,571 @@
+use crate::api::auth::{AuthUser, DEACTIVATED, blocking, hash_password, revoke_user};
+use crate::api::rbac::{Access, SUPER_ROLE};
+use crate::api::store::Store;
+use crate::api::throttle::LoginThrottle;
+use crate::api::types::{UserForm, UserListItem, UserSearchParams, gender, page};
+use crate::api::{ApiError, Filter, split_codes};
+use crate::api_response::{ApiResponse, PaginatedResponse};
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{Path, Query, State};
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter};
+use std::sync::Arc;
+
+const COLUMNS: &str = "u.id, u.avatar, u.status, u.user_name, u.gender, u.nick_name, u.phone, \
+     u.email, (SELECT group_concat(r.code, ',' ORDER BY r.id) FROM user_roles ur \
+     JOIN roles r ON r.id = ur.role_id WHERE ur.user_id = u.id), u.create_by, u.create_time, \
+     u.update_by, u.update_time";
+
+fn user(row: &Row) -> rusqlite::Result<UserListItem> {
+    Ok(UserListItem {
+        id: row.get(0)?,
+        avatar: row.get(1)?,
+        status: row.get(2)?,
+        user_name: row.get(3)?,
+        user_gender: row.get(4)?,
+        nick_name: row.get(5)?,
+        user_phone: row.get(6)?,
+        user_email: row.get(7)?,
+        user_roles: split_codes(row.get(8)?),
+        create_by: row.get(9)?,
+        create_time: row.get(10)?,
+        update_by: row.get(11)?,
+        update_time: row.get(12)?,
+    })
+}
+
+/// A page of the users matching the search, like
+/// [`UserSearchParams::matches`].
+pub fn list(
+    connection: &Connection,
+    params: &UserSearchParams,
+) -> Result<PaginatedResponse<UserListItem>, ApiError> {
+    let (current, size) = page(params.current, params.size);
+    let mut filter = Filter::default();
+    filter
+        .eq("u.id", params.id.map(|id| id as i64))
+        .contains("u.user_name", params.user_name.as_deref())
+        .eq(
+            "u.gender",
+            params.user_gender.as_deref().map(gender).map(str::to_owned),
+        )
+        .contains("u.phone", params.user_phone.as_deref())
+        .contains("u.email", params.user_email.as_deref())
+        .eq("u.status", params.status.clone());
+    let total = connection.query_row(
+        &format!("SELECT count(*) FROM users u{}", filter.sql()),
+        params_from_iter(filter.values()),
+        |row| row.get(0),
+    )?;
+    let records = connection
+        .prepare(&format!(
+            "SELECT {COLUMNS} FROM users u{} ORDER BY u.id LIMIT {size} OFFSET {}",
+            filter.sql(),
+            (current - 1).saturating_mul(size)
+        ))?
+        .query_map(params_from_iter(filter.values()), user)?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(PaginatedResponse {
+        records,
+        current,
+        size,
+        total,
+    })
+}
+
+pub fn find(connection: &Connection, id: u64) -> Result<UserListItem, ApiError> {
+    Ok(connection.query_row(
+        &format!("SELECT {COLUMNS} FROM users u WHERE u.id = ?1"),
+        [id],
+        user,
+    )?)
+}
+
+/// Creates the user on behalf of the operator, the user logged in, who can
+/// only grant the roles they hold unless they are a super administrator.
+///
+/// The password of the form is hashed by the caller, see [`hash_form_password`].
+pub fn insert(
+    connection: &mut Connection,
+    form: &UserForm,
+    password_hash: Option<&str>,
+    operator: &AuthUser,
+) -> Result<UserListItem, ApiError> {
+    validate(form)?;
+    authorize(connection, operator, &[], &form.user_roles)?;
+    let password_hash = password_hash.unwrap_or_default();
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO users (user_name, nick_name, avatar, gender, phone, email, status, \
+         password_hash, create_by, update_by) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+        params![
+            form.user_name.trim(),
+            form.nick_name,
+            form.avatar,
+            form.user_gender,
+            form.user_phone,
+            form.user_email,
+            form.status,
+            password_hash,
+            operator.name
+        ],
+    )?;
+    let id = transaction.last_insert_rowid() as u64;
+    set_roles(&transaction, id, &form.user_roles)?;
+    transaction.commit()?;
+    find(connection, id)
+}
+
+/// Replaces every field of the user and its roles, and the password when
+/// the form has one. The sessions of the user end when the password changes
+/// or the account is deactivated.
+///
+/// The operator can only grant or revoke the roles they hold, and not update
+/// a super administrator, unless they are one.
+pub fn update(
+    connection: &mut Connection,
+    id: u64,
+    form: &UserForm,
+    password_hash: Option<&str>,
+    operator: &AuthUser,
+) -> Result<UserListItem, ApiError> {
+    validate(form)?;
+    authorize(
+        connection,
+        operator,
+        &find(connection, id)?.user_roles,
+        &form.user_roles,
+    )?;
+    let transaction = connection.transaction()?;
+    let updated = transaction.execute(
+        "UPDATE users SET user_name = ?2, nick_name = ?3, avatar = ?4, gender = ?5, phone = ?6, \
+         email = ?7, status = ?8, password_hash = coalesce(?9, password_hash), update_by = ?10, \
+         update_time = strftime('%Y-%m-%d %H:%M:%S', 'now') WHERE id = ?1",
+        params![
+            id,
+            form.user_name.trim(),
+            form.nick_name,
+            form.avatar,
+            form.user_gender,
+            form.user_phone,
+            form.user_email,
+            form.status,
+            password_hash,
+            operator.name
+        ],
+    )?;
+    if updated == 0 {
+        return Err(ApiError::NotFound);
+    }
+    if password_hash.is_some() || form.status == DEACTIVATED {
+        revoke_user(&transaction, id)?;
+    }
+    set_roles(&transaction, id, &form.user_roles)?;
+    transaction.commit()?;
+    find(connection, id)
+}
+
+/// Deletes the user on behalf of the operator, who can't delete themselves,
+/// nor a super administrator unless they are one.
+pub fn delete(connection: &Connection, id: u64, operator: &AuthUser) -> Result<(), ApiError> {
+    let user = find(connection, id)?;
+    if id == operator.id {
+        return Err(ApiError::Forbidden);
+    }
+    authorize(connection, operator, &user.user_roles, &user.user_roles)?;
+    match connection.execute("DELETE FROM users WHERE id = ?1", [id])? {
+        0 => Err(ApiError::NotFound),
+        _ => Ok(()),
+    }
+}
+
+/// The hash of the password of the form, if any. Argon2 is slow on purpose,
+/// so the password is hashed before the [`Store`] is locked.
+pub async fn hash_form_password(form: &UserForm) -> Option<String> {
+    let password = form
+        .password
+        .clone()
+        .filter(|password| !password.is_empty())?;
+    Some(blocking(move || hash_password(&password)).await)
+}
+
+fn validate(form: &UserForm) -> Result<(), ApiError> {
+    if form.user_name.trim().is_empty() {
+        return Err(ApiError::BadRequest("The user name is empty".to_owned()));
+    }
+    if form.status.is_empty() {
+        return Err(ApiError::BadRequest("The status is empty".to_owned()));
+    }
+    if form.password.as_ref().is_some_and(String::is_empty) {
+        return Err(ApiError::BadRequest("The password is empty".to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks that the operator may replace the roles of a user with others. A
+/// super administrator may, and anyone else only when the user isn't a super
+/// administrator and the roles granted or revoked are among their own.
+fn authorize(
+    connection: &Connection,
+    operator: &AuthUser,
+    roles: &[String],
+    codes: &[String],
+) -> Result<(), ApiError> {
+    let access = Access::load(connection, operator.id)?;
+    if access.is_super() {
+        return Ok(());
+    }
+    let granted = codes.iter().filter(|code| !roles.contains(code));
+    let revoked = roles.iter().filter(|code| !codes.contains(code));
+    if roles.iter().any(|code| code == SUPER_ROLE)
+        || granted
+            .chain(revoked)
+            .any(|code| !access.roles().contains(code))
+    {
+        return Err(ApiError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Grants exactly the roles with these codes.
+fn set_roles(connection: &Connection, user_id: u64, codes: &[String]) -> Result<(), ApiError> {
+    connection.execute("DELETE FROM user_roles WHERE user_id = ?1", [user_id])?;
+    for code in codes {
+        let role_id: u64 = connection
+            .query_row("SELECT id FROM roles WHERE code = ?1", [code], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .ok_or_else(|| ApiError::BadRequest(format!("Unknown role {code}")))?;
+        connection.execute(
+            "INSERT OR IGNORE INTO user_roles (user_id, role_id) VALUES (?1, ?2)",
+            [user_id, role_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// The handler of `GET /api/user/list`.
+pub async fn list_users(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    params: Result<Query<UserSearchParams>, QueryRejection>,
+) -> Result<ApiResponse<PaginatedResponse<UserListItem>>, ApiError> {
+    let Query(params) = params?;
+    let users = store
+        .run(move |connection| list(connection, &params))
+        .await?;
+    Ok(ApiResponse::success(users))
+}
+
+/// The handler of `GET /api/user/{id}`.
+pub async fn get_user(
+    State(store): State<Arc<Store>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<UserListItem>, ApiError> {
+    let Path(id) = id?;
+    let user = store.run(move |connection| find(connection, id)).await?;
+    Ok(ApiResponse::success(user))
+}
+
+/// The handler of `POST /api/user`.
+pub async fn create_user(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    form: Result<Json<UserForm>, JsonRejection>,
+) -> Result<ApiResponse<UserListItem>, ApiError> {
+    let Json(form) = form?;
+    let password_hash = hash_form_password(&form).await;
+    let user = store
+        .run(move |connection| insert(connection, &form, password_hash.as_deref(), &user))
+        .await?;
+    Ok(ApiResponse::success(user))
+}
+
+/// The handler of `PUT /api/user/{id}`.
+pub async fn update_user(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+    form: Result<Json<UserForm>, JsonRejection>,
+) -> Result<ApiResponse<UserListItem>, ApiError> {
+    let (Path(id), Json(form)) = (id?, form?);
+    let password_hash = hash_form_password(&form).await;
+    let user = store
+        .run(move |connection| update(connection, id, &form, password_hash.as_deref(), &user))
+        .await?;
+    Ok(ApiResponse::success(user))
+}
+
+/// The handler of `DELETE /api/user/{id}`.
+pub async fn delete_user(
+    State(store): State<Arc<Store>>,
+    user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Path(id) = id?;
+    store
+        .run(move |connection| delete(connection, id, &user))
+        .await?;
+    Ok(ApiResponse::success(()))
+}
+
+/// The handler of `POST /api/user/{id}/unlock`, which ends the lockout of the
+/// account after too many failed logins.
+pub async fn unlock_user(
+    State(store): State<Arc<Store>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    _user: AuthUser,
+    id: Result<Path<u64>, PathRejection>,
+) -> Result<ApiResponse<()>, ApiError> {
+    let Path(id) = id?;
+    let user = store.run(move |connection| find(connection, id)).await?;
+    throttle.unlock(&user.user_name);
+    Ok(ApiResponse::success(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::auth::verify_password;
+
+    fn form(user_name: &str, roles: &[&str]) -> UserForm {
+        serde_json::from_value(serde_json::json!({
+            "userName": user_name,
+            "userGender": "女",
+            "userEmail": format!("{}@company.com", user_name.to_lowercase()),
+            "userRoles": roles,
+        }))
+        .unwrap()
+    }
+
+    fn operator(id: u64, name: &str) -> AuthUser {
+        AuthUser {
+            id,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn crud() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let user = insert(
+            &mut connection,
+            &form("Emma", &["R_USER", "R_ADMIN"]),
+            None,
+            &operator(1, "Super"),
+        )
+        .unwrap();
+        assert_eq!(user.id, 4);
+        assert_eq!(user.status, "1");
+        assert_eq!(user.create_by, "Super");
+        assert_eq!(user.user_roles, ["R_ADMIN", "R_USER"]);
+        assert_eq!(find(&connection, 4).unwrap(), user);
+
+        let user = update(
+            &mut connection,
+            4,
+            &form("Emma Stone", &[]),
+            None,
+            &operator(1, "Super"),
+        )
+        .unwrap();
+        assert_eq!(user.user_name, "Emma Stone");
+        assert!(user.user_roles.is_empty());
+        assert!(matches!(
+            update(
+                &mut connection,
+                99,
+                &form("Nobody", &[]),
+                None,
+                &operator(1, "Super")
+            ),
+            Err(ApiError::NotFound)
+        ));
+
+        delete(&connection, 4, &operator(1, "Super")).unwrap();
+        assert!(matches!(find(&connection, 4), Err(ApiError::NotFound)));
+        assert!(matches!(
+            delete(&connection, 4, &operator(1, "Super")),
+            Err(ApiError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        assert!(matches!(
+            insert(
+                &mut connection,
+                &form("admin", &[]),
+                None,
+                &operator(1, "Super")
+            ),
+            Err(ApiError::Conflict(_))
+        ));
+        assert!(matches!(
+            insert(
+                &mut connection,
+                &form(" ", &[]),
+                None,
+                &operator(1, "Super")
+            ),
+            Err(ApiError::BadRequest(_))
+        ));
+        // The user isn't created when one of its roles is unknown.
+        assert!(matches!(
+            insert(
+                &mut connection,
+                &form("Emma", &["R_NOBODY"]),
+                None,
+                &operator(1, "Super")
+            ),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(find(&connection, 4), Err(ApiError::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn password() {
+        let mut emma = form("Emma", &[]);
+        emma.password = Some(String::new());
+        assert_eq!(hash_form_password(&emma).await, None);
+        emma.password = Some("secret".to_owned());
+        let secret = hash_form_password(&emma).await.unwrap();
+        assert!(verify_password("secret", &secret));
+
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let hash = |connection: &Connection| -> String {
+            connection
+                .query_row("SELECT password_hash FROM users WHERE id = 4", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        insert(&mut connection, &emma, Some(&secret), &operator(1, "Super")).unwrap();
+        assert_eq!(hash(&connection), secret);
+        emma.password = None;
+        let user = update(&mut connection, 4, &emma, None, &operator(2, "Admin")).unwrap();
+        assert_eq!(user.update_by, "Admin");
+        assert_eq!(hash(&connection), secret);
+        emma.password = Some(String::new());
+        assert!(matches!(
+            update(&mut connection, 4, &emma, None, &operator(2, "Admin")),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn escalation() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        let admin = operator(2, "Admin");
+        let emma = form("Emma", &["R_USER"]);
+        insert(&mut connection, &emma, None, &operator(1, "Super")).unwrap();
+        for roles in [&["R_SUPER"][..], &["R_USER", "R_FINANCE"]] {
+            assert!(matches!(
+                insert(&mut connection, &form("Olivia", roles), None, &admin),
+                Err(ApiError::Forbidden)
+            ));
+            assert!(matches!(
+                update(&mut connection, 4, &form("Emma", roles), None, &admin),
+                Err(ApiError::Forbidden)
+            ));
+        }
+        // The administrator can't promote themselves, nor update a super
+        // administrator, but grants their own role.
+        assert!(matches!(
+            update(
+                &mut connection,
+                2,
+                &form("Admin", &["R_ADMIN", "R_SUPER"]),
+                None,
+                &admin
+            ),
+            Err(ApiError::Forbidden)
+        ));
+        assert!(matches!(
+            update(
+                &mut connection,
+                1,
+                &form("Super", &["R_SUPER"]),
+                None,
+                &admin
+            ),
+            Err(ApiError::Forbidden)
+        ));
+        let user = update(
+            &mut connection,
+            4,
+            &form("Emma", &["R_USER", "R_ADMIN"]),
+            None,
+            &admin,
+        );
+        assert_eq!(user.unwrap().user_roles, ["R_ADMIN", "R_USER"]);
+        // Nor revoke a role they don't hold.
+        assert!(matches!(
+            update(
+                &mut connection,
+                4,
+                &form("Emma", &["R_ADMIN"]),
+                None,
+                &admin
+            ),
+            Err(ApiError::Forbidden)
+        ));
+        // Nor delete a super administrator, or themselves.
+        for id in [1, 2] {
+            assert!(matches!(
+                delete(&connection, id, &admin),
+                Err(ApiError::Forbidden)
+            ));
+        }
+        delete(&connection, 4, &admin).unwrap();
+        let user = update(
+            &mut connection,
+            2,
+            &form("Admin", &["R_SUPER"]),
+            None,
+            &operator(1, "Super"),
+        );
+        assert_eq!(user.unwrap().user_roles, ["R_SUPER"]);
+    }
+
+    #[test]
+    fn search() {
+        let store = Store::open_in_memory().unwrap();
+        let mut connection = store.lock();
+        for name in ["Emma", "Olivia", "Ava"] {
+            insert(
+                &mut connection,
+                &form(name, &["R_USER"]),
+                None,
+                &operator(1, "Super"),
+            )
+            .unwrap();
+        }
+        let page = list(&connection, &params("userName=A&current=1&size=2")).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(
+            page.records
+                .iter()
+                .map(|user| user.user_name.as_str())
+                .collect::<Vec<_>>(),
+            ["Admin", "Emma"]
+        );
+        let page = list(&connection, &params("userGender=2&userPhone=")).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.size, 10);
+        let page = list(&connection, &params("userName=a&current=3&size=2")).unwrap();
+        assert!(page.records.is_empty());
+    }
+
+    fn params(query: &str) -> UserSearchParams {
+        let uri: http::Uri = format!("/api/user/list?{query}").parse().unwrap();
+        Query::try_from_uri(&uri).unwrap().0
+    }
+}
@@ -0,0 +1,139 @@
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use serde::Serialize;
+
+/// The envelope of every JSON response, the `BaseResponse` of the frontend
+/// in `src/types/common/response.ts`.
+///
+/// `code` mirrors the HTTP status, and `msg` is a human-readable message that
+/// the frontend shows as is.
+///
+/// # Examples
+///
+/// ```
+/// use http::StatusCode;
+/// use server::api_response::ApiResponse;
+///
+/// let response = ApiResponse::error(StatusCode::NOT_FOUND);
+/// assert_eq!(
+///     serde_json::to_string(&response).unwrap(),
+///     r#"{"code":404,"msg":"Not Found","data":null}"#
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ApiResponse<T> {
+    pub code: u16,
+    pub msg: String,
+    pub data: T,
+}
+
+impl<T> ApiResponse<T> {
+    /// A `200` response carrying the data.
+    pub fn success(data: T) -> Self {
+        ApiResponse {
+            code: StatusCode::OK.as_u16(),
+            msg: "success".to_owned(),
+            data,
+        }
+    }
+}
+
+impl ApiResponse<()> {
+    /// An error response without data, whose message is the reason phrase of
+    /// the status.
+    pub fn error(status: StatusCode) -> Self {
+        ApiResponse::message(status, status.canonical_reason().unwrap_or_default())
+    }
+
+    /// An error response without data.
+    pub fn message(status: StatusCode, msg: impl Into<String>) -> Self {
+        ApiResponse {
+            code: status.as_u16(),
+            msg: msg.into(),
+            data: (),
+        }
+    }
+}
+
+/// A page of records, the `PaginatedResponse` of the frontend in
+/// `src/types/api/api.d.ts`. Pages are numbered from 1.
+///
+/// # Examples
+///
+/// ```
+/// use server::api_response::PaginatedResponse;
+///
+/// let page = PaginatedResponse::paginate((1..=25).collect::<Vec<_>>(), 3, 10);
+/// assert_eq!(page.records, [21, 22, 23, 24, 25]);
+/// assert_eq!(page.total, 25);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PaginatedResponse<T> {
+    pub records: Vec<T>,
+    pub current: u64,
+    pub size: u64,
+    pub total: u64,
+}
+
+impl<T> PaginatedResponse<T> {
+    /// The page `current` of `size` records, a page past the end is empty.
+    pub fn paginate(records: Vec<T>, current: u64, size: u64) -> Self {
+        let total = records.len() as u64;
+        let skip = current.saturating_sub(1).saturating_mul(size);
+        let records = records
+            .into_iter()
+            .skip(usize::try_from(skip).unwrap_or(usize::MAX))
+            .take(usize::try_from(size).unwrap_or(usize::MAX))
+            .collect();
+        PaginatedResponse {
+            records,
+            current,
+            size,
+            total,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for ApiResponse<T> {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.code).unwrap_or(StatusCode::OK);
+        (status, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn success() {
+        let response = ApiResponse::success(vec![1, 2]);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"code":200,"msg":"success","data":[1,2]}"#
+        );
+    }
+
+    #[test]
+    fn pages() {
+        let page = PaginatedResponse::paginate(vec!["a", "b", "c"], 1, 2);
+        assert_eq!(page.records, ["a", "b"]);
+        let page = PaginatedResponse::paginate(vec!["a", "b", "c"], 3, 2);
+        assert!(page.records.is_empty());
+        assert_eq!(page.total, 3);
+        let page = PaginatedResponse::paginate(vec!["a"], 0, u64::MAX);
+        assert_eq!(page.records, ["a"]);
+    }
+
+    #[test]
+    fn status() {
+        let response =
+            ApiResponse::message(StatusCode::UNAUTHORIZED, "token expired").into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "application/json"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use crate::error_type;
+use std::fmt;
+use std::str::FromStr;
+
+/// The path prefix the application is deployed under, the counterpart of
+/// `VITE_BASE_URL` in the frontend, e.g. `/admin/`.
+///
+/// The textual form is normalized, so `admin`, `/admin` and `/admin/` are
+/// the same base path, and `/` is the root. The segments may only contain
+/// the characters allowed in a URL path, and `.`, `..` and empty segments are
+/// rejected.
+///
+/// # Examples
+///
+/// ```
+/// use server::base_path::BasePath;
+///
+/// let base_path: BasePath = "admin/".parse().unwrap();
+/// assert_eq!(base_path.to_string(), "/admin/");
+/// assert_eq!(base_path.prefix(), "/admin");
+/// assert_eq!(base_path.join("/assets/index.js"), "/admin/assets/index.js");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BasePath {
+    /// Either empty for the root, or a leading slash followed by the segments
+    /// without a trailing slash.
+    prefix: String,
+}
+
+error_type!(InvalidBasePath);
+
+impl BasePath {
+    /// Whether the application is deployed at the root.
+    pub fn is_root(&self) -> bool {
+        self.prefix.is_empty()
+    }
+
+    /// The base path without the trailing slash, empty for the root.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Moves a path and query from the root to the base path.
+    pub fn join(&self, path_and_query: &str) -> String {
+        format!("{}/{}", self.prefix, path_and_query.trim_start_matches('/'))
+    }
+}
+
+impl FromStr for BasePath {
+    type Err = InvalidBasePath;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_path_char = |b: u8| b.is_ascii_alphanumeric() || b"-._~!$&'()+,;=:@%".contains(&b);
+        let mut prefix = String::new();
+        let segments = s.trim().trim_matches('/');
+        if segments.is_empty() {
+            return Ok(BasePath::default());
+        }
+        for segment in segments.split('/') {
+            if matches!(segment, "" | "." | "..") || !segment.bytes().all(is_path_char) {
+                return Err(InvalidBasePath { _inner: () });
+            }
+            prefix.push('/');
+            prefix.push_str(segment);
+        }
+        Ok(BasePath { prefix })
+    }
+}
+
+impl fmt::Display for BasePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/", self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized() {
+        for s in ["admin", "/admin", "/admin/", " admin/ "] {
+            assert_eq!(s.parse::<BasePath>().unwrap().prefix(), "/admin");
+        }
+        let nested: BasePath = "/apps/art-design-pro/".parse().unwrap();
+        assert_eq!(nested.prefix(), "/apps/art-design-pro");
+        assert_eq!(nested.to_string(), "/apps/art-design-pro/");
+    }
+
+    #[test]
+    fn root() {
+        for s in ["", "/", "//"] {
+            let root = s.parse::<BasePath>().unwrap();
+            assert!(root.is_root());
+            assert_eq!(root.to_string(), "/");
+            assert_eq!(root.join("/index.html"), "/index.html");
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "/admin//app",
+            "/./admin",
+            "/admin/..",
+            "/{*path}",
+            "/admin?x=1",
+            "/a#b",
+            "/a b",
+        ] {
+            assert!(s.parse::<BasePath>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn join() {
+        let base_path: BasePath = "/admin".parse().unwrap();
+        assert_eq!(
+            base_path.join("/system/user?id=1"),
+            "/admin/system/user?id=1"
+        );
+    }
+}
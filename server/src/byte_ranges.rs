@@ -0,0 +1,102 @@
+use crate::content_range::ContentRange;
+use bytes::{BufMut, Bytes, BytesMut};
+use http::HeaderValue;
+
+/// A `multipart/byteranges` body, defined in
+/// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-14.6)
+///
+/// When a 206 (Partial Content) response contains several ranges, each body
+/// part carries its own `Content-Type` and `Content-Range` header fields and
+/// the parts are separated by the boundary announced in the `Content-Type`
+/// of the response.
+///
+/// # Examples
+///
+/// ```
+/// use server::byte_ranges::MultipartByteRanges;
+///
+/// let multipart = MultipartByteRanges::new("3d6b6a416f9b5", "text/plain");
+/// let body = multipart.body(b"hello world", &[(0, 1), (6, 7)]);
+/// assert!(body.starts_with(b"--3d6b6a416f9b5\r\n"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultipartByteRanges<'a> {
+    boundary: &'a str,
+    content_type: &'a str,
+}
+
+impl<'a> MultipartByteRanges<'a> {
+    /// Create a builder with the boundary and the content type of the selected
+    /// representation.
+    pub fn new(boundary: &'a str, content_type: &'a str) -> Self {
+        MultipartByteRanges {
+            boundary,
+            content_type,
+        }
+    }
+
+    /// The `Content-Type` of the whole response.
+    pub fn content_type(&self) -> Option<HeaderValue> {
+        HeaderValue::try_from(format!("multipart/byteranges; boundary={}", self.boundary)).ok()
+    }
+
+    /// Assemble the body from the end-inclusive ranges of `content`.
+    ///
+    /// The ranges must be satisfiable, see `Range::satisfiable_ranges`.
+    pub fn body(&self, content: &[u8], ranges: &[(u64, u64)]) -> Bytes {
+        let complete_length = content.len() as u64;
+        let capacity = ranges
+            .iter()
+            .map(|(first, last)| (last - first + 1) as usize + 128 + self.content_type.len())
+            .sum::<usize>();
+        let mut body = BytesMut::with_capacity(capacity);
+        for &(first, last) in ranges {
+            let content_range = ContentRange::bytes(first..=last, complete_length)
+                .expect("satisfiable ranges are valid content ranges");
+            body.put_slice(b"--");
+            body.put_slice(self.boundary.as_bytes());
+            body.put_slice(b"\r\nContent-Type: ");
+            body.put_slice(self.content_type.as_bytes());
+            body.put_slice(b"\r\nContent-Range: ");
+            body.put_slice(content_range.to_string().as_bytes());
+            body.put_slice(b"\r\n\r\n");
+            body.put_slice(&content[first as usize..=last as usize]);
+            body.put_slice(b"\r\n");
+        }
+        body.put_slice(b"--");
+        body.put_slice(self.boundary.as_bytes());
+        body.put_slice(b"--\r\n");
+        body.freeze()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body() {
+        let multipart = MultipartByteRanges::new("THIS_STRING_SEPARATES", "text/html");
+        let body = multipart.body(b"0123456789", &[(0, 2), (8, 9)]);
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"--THIS_STRING_SEPARATES\r\n\
+                Content-Type: text/html\r\n\
+                Content-Range: bytes 0-2/10\r\n\
+                \r\n\
+                012\r\n\
+                --THIS_STRING_SEPARATES\r\n\
+                Content-Type: text/html\r\n\
+                Content-Range: bytes 8-9/10\r\n\
+                \r\n\
+                89\r\n\
+                --THIS_STRING_SEPARATES--\r\n"
+            )
+        );
+        assert_eq!(
+            multipart.content_type().unwrap(),
+            "multipart/byteranges; boundary=THIS_STRING_SEPARATES"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::util::flat_csv::FlatCsv;
+use crate::{derive_header, error_type};
+use http::HeaderValue;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// `Cache-Control` header, defined in
+/// [RFC9111](https://datatracker.ietf.org/doc/html/rfc9111#section-5.2)
+///
+/// The "Cache-Control" header field is used to list directives for caches
+/// along the request/response chain. Such cache directives are
+/// unidirectional in that the presence of a directive in a request does not
+/// imply that the same directive is present in the response, or to be
+/// repeated in it.
+///
+/// # ABNF
+///
+/// ```text
+/// Cache-Control   = #cache-directive
+/// cache-directive = token [ "=" ( token / quoted-string ) ]
+/// ```
+///
+/// # Example values
+///
+/// * `no-cache`
+/// * `private, community="UCI"`
+/// * `public, max-age=31536000, immutable`
+///
+/// # Examples
+///
+/// ```
+/// use server::cache_control::CacheControl;
+/// use std::time::Duration;
+///
+/// let cc: CacheControl = "public, max-age=31536000, immutable".parse().unwrap();
+/// assert_eq!(cc.max_age(), Some(Duration::from_secs(31536000)));
+/// assert!(cc.has_directive("immutable"));
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct CacheControl(FlatCsv);
+
+derive_header! {
+    CacheControl(_),
+    name: CACHE_CONTROL
+}
+
+error_type!(InvalidCacheControl);
+
+impl CacheControl {
+    /// `Cache-Control: no-cache`, the response may be stored but must be
+    /// validated with the origin server before each reuse.
+    pub fn no_cache() -> Self {
+        CacheControl(HeaderValue::from_static("no-cache").into())
+    }
+
+    /// `Cache-Control: no-store`
+    pub fn no_store() -> Self {
+        CacheControl(HeaderValue::from_static("no-store").into())
+    }
+
+    /// `Cache-Control: public, max-age=<max_age>, immutable`, for resources
+    /// whose URL changes whenever their content changes.
+    pub fn immutable(max_age: Duration) -> Self {
+        let value = format!("public, max-age={}, immutable", max_age.as_secs());
+        CacheControl(
+            HeaderValue::try_from(value)
+                .expect("immutable cache control is a valid header value")
+                .into(),
+        )
+    }
+
+    /// Iterate the directives as lowercase names with their optional,
+    /// unquoted arguments.
+    pub fn directives(&self) -> impl Iterator<Item = (String, Option<&str>)> {
+        self.0
+            .iter()
+            .filter(|d| !d.is_empty())
+            .map(|directive| match directive.split_once('=') {
+                Some((name, argument)) => (
+                    name.trim().to_ascii_lowercase(),
+                    Some(argument.trim().trim_matches('"')),
+                ),
+                None => (directive.to_ascii_lowercase(), None),
+            })
+    }
+
+    /// Whether the directive is present, the name is case-insensitive.
+    pub fn has_directive(&self, name: &str) -> bool {
+        self.directives()
+            .any(|(directive, _)| directive.eq_ignore_ascii_case(name))
+    }
+
+    /// The `max-age` directive.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.directives()
+            .find(|(directive, _)| directive == "max-age")
+            .and_then(|(_, argument)| argument?.parse().ok())
+            .map(Duration::from_secs)
+    }
+}
+
+impl FromStr for CacheControl {
+    type Err = InvalidCacheControl;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCacheControl { _inner: () };
+        let value = HeaderValue::try_from(s.trim()).map_err(|_| invalid())?;
+        let cache_control = CacheControl(value.into());
+        let mut directives = 0;
+        for directive in cache_control.0.iter().filter(|d| !d.is_empty()) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (directive, None),
+            };
+            if !is_token(name) {
+                return Err(invalid());
+            }
+            if let Some(argument) = argument
+                && !is_token(argument)
+                && !is_quoted_string(argument)
+            {
+                return Err(invalid());
+            }
+            directives += 1;
+        }
+        if directives == 0 {
+            return Err(invalid());
+        }
+        Ok(cache_control)
+    }
+}
+
+impl fmt::Debug for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0.value.to_str().unwrap_or_default())
+    }
+}
+
+/// `token = 1*tchar`
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c))
+}
+
+fn is_quoted_string(s: &str) -> bool {
+    s.len() >= 2 && s.starts_with('"') && s.ends_with('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+
+    #[test]
+    fn parse() {
+        let cc: CacheControl = "public, max-age=600, community=\"UCI\"".parse().unwrap();
+        assert_eq!(
+            cc.directives().collect::<Vec<_>>(),
+            vec![
+                ("public".to_owned(), None),
+                ("max-age".to_owned(), Some("600")),
+                ("community".to_owned(), Some("UCI")),
+            ]
+        );
+        assert_eq!(cc.max_age(), Some(Duration::from_secs(600)));
+        assert!(cc.has_directive("PUBLIC"));
+        assert!(!cc.has_directive("immutable"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("".parse::<CacheControl>().is_err());
+        assert!(" , ".parse::<CacheControl>().is_err());
+        assert!("max age=1".parse::<CacheControl>().is_err());
+        assert!("max-age=".parse::<CacheControl>().is_err());
+        assert!("no-cache\n".parse::<CacheControl>().is_ok());
+    }
+
+    #[test]
+    fn encode() {
+        let mut map = ::http::HeaderMap::new();
+        map.typed_insert(CacheControl::immutable(Duration::from_secs(31536000)));
+        assert_eq!(map["cache-control"], "public, max-age=31536000, immutable");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::cache_control::CacheControl;
+use crate::error_type;
+use crate::path_pattern::PathPattern;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Vite emits the build output as `assets/[name]-[hash][extname]`, where the
+/// hash is 8 characters of the url-safe base64 alphabet.
+pub const VITE_HASHED_ASSET: &str = r"regex:^assets/.+-[A-Za-z0-9_-]{8}(\.[A-Za-z0-9]+)+$";
+
+/// One year, the longest `max-age` that is meaningful for caches.
+pub const IMMUTABLE_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Assigns `Cache-Control` directives to every path matching a pattern.
+///
+/// The textual form is `<pattern>=<directives>`, where the pattern is a
+/// [`PathPattern`].
+///
+/// # Examples
+///
+/// ```
+/// use server::cache_policy::CacheRule;
+///
+/// let rule: CacheRule = "*.html=no-cache".parse().unwrap();
+/// assert!(rule.cache_control("index.html").is_some());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CacheRule {
+    pattern: PathPattern,
+    cache_control: CacheControl,
+}
+
+error_type!(InvalidCacheRule);
+
+impl CacheRule {
+    pub fn new(pattern: PathPattern, cache_control: CacheControl) -> Self {
+        CacheRule {
+            pattern,
+            cache_control,
+        }
+    }
+
+    /// The directives of this rule if the path matches it.
+    pub fn cache_control(&self, path: &str) -> Option<&CacheControl> {
+        self.pattern.is_match(path).then_some(&self.cache_control)
+    }
+}
+
+impl FromStr for CacheRule {
+    type Err = InvalidCacheRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCacheRule { _inner: () };
+        let (pattern, directives) = s.split_once('=').ok_or_else(invalid)?;
+        Ok(CacheRule {
+            pattern: pattern.trim().parse().map_err(|_| invalid())?,
+            cache_control: directives.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// An ordered list of [`CacheRule`]s, the first matching rule wins.
+///
+/// # Examples
+///
+/// ```
+/// use server::cache_policy::CachePolicy;
+///
+/// let policy = CachePolicy::default();
+/// let immutable = policy.cache_control("assets/index-BfT3xk9a.js").unwrap();
+/// assert!(immutable.has_directive("immutable"));
+/// let no_cache = policy.cache_control("index.html").unwrap();
+/// assert!(no_cache.has_directive("no-cache"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    rules: Vec<CacheRule>,
+}
+
+impl CachePolicy {
+    /// A policy that only consists of the given rules.
+    pub fn new(rules: Vec<CacheRule>) -> Self {
+        CachePolicy { rules }
+    }
+
+    /// The given rules are checked before the default rules.
+    pub fn with_defaults(rules: Vec<CacheRule>) -> Self {
+        let mut policy = CachePolicy::new(rules);
+        policy.rules.extend(CachePolicy::default_rules());
+        policy
+    }
+
+    /// The default rules:
+    ///
+    /// * Vite's content-hashed assets never change under the same URL, so they
+    ///   are cached for a year and marked `immutable`.
+    /// * Everything else, in particular `index.html`, must be revalidated so
+    ///   that a deployment is picked up immediately.
+    pub fn default_rules() -> Vec<CacheRule> {
+        vec![
+            CacheRule::new(
+                VITE_HASHED_ASSET
+                    .parse()
+                    .expect("the vite hashed asset pattern is valid"),
+                CacheControl::immutable(IMMUTABLE_MAX_AGE),
+            ),
+            CacheRule::new(
+                "**".parse().expect("the fallback pattern is valid"),
+                CacheControl::no_cache(),
+            ),
+        ]
+    }
+
+    /// The directives of the first rule matching the path.
+    pub fn cache_control(&self, path: &str) -> Option<&CacheControl> {
+        self.rules.iter().find_map(|rule| rule.cache_control(path))
+    }
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy::new(CachePolicy::default_rules())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directives(policy: &CachePolicy, path: &str) -> Option<String> {
+        policy.cache_control(path).map(ToString::to_string)
+    }
+
+    #[test]
+    fn defaults() {
+        let policy = CachePolicy::default();
+        let immutable = Some("public, max-age=31536000, immutable".to_owned());
+        assert_eq!(directives(&policy, "assets/index-BfT3xk9a.js"), immutable);
+        assert_eq!(
+            directives(&policy, "assets/el-button-C4o8_q-Z.css"),
+            immutable
+        );
+        assert_eq!(
+            directives(&policy, "assets/index-BfT3xk9a.js.gz"),
+            immutable
+        );
+        assert_eq!(
+            directives(&policy, "index.html"),
+            Some("no-cache".to_owned())
+        );
+        assert_eq!(
+            directives(&policy, "favicon.ico"),
+            Some("no-cache".to_owned())
+        );
+        assert_eq!(
+            directives(&policy, "assets/logo.svg"),
+            Some("no-cache".to_owned())
+        );
+        assert_eq!(
+            directives(&policy, "index-BfT3xk9a.js"),
+            Some("no-cache".to_owned())
+        );
+    }
+
+    #[test]
+    fn custom_rules_first() {
+        let policy = CachePolicy::with_defaults(vec![
+            "assets/**=no-store".parse().unwrap(),
+            r"regex:\.json$=public, max-age=60".parse().unwrap(),
+        ]);
+        assert_eq!(
+            directives(&policy, "assets/index-BfT3xk9a.js"),
+            Some("no-store".to_owned())
+        );
+        assert_eq!(
+            directives(&policy, "config/app.json"),
+            Some("public, max-age=60".to_owned())
+        );
+        assert_eq!(
+            directives(&policy, "index.html"),
+            Some("no-cache".to_owned())
+        );
+    }
+
+    #[test]
+    fn without_defaults() {
+        let policy = CachePolicy::new(vec!["*.html=no-cache".parse().unwrap()]);
+        assert_eq!(directives(&policy, "favicon.ico"), None);
+    }
+
+    #[test]
+    fn invalid_rule() {
+        assert!("*.html".parse::<CacheRule>().is_err());
+        assert!("*.html=".parse::<CacheRule>().is_err());
+        assert!("regex:(=no-cache".parse::<CacheRule>().is_err());
+    }
+}
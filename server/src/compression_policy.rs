@@ -0,0 +1,250 @@
+use crate::error_type;
+use std::fmt;
+use std::str::FromStr;
+
+/// Files smaller than this rarely get smaller once the framing of the
+/// compressed formats is added.
+pub const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// The compressed representation has to save at least this many percent of
+/// the original size to be worth the decoding on the client.
+pub const DEFAULT_MIN_SAVINGS: u8 = 10;
+
+/// A media type such as `image/png`, or all subtypes of a type such as
+/// `video/*`, compared case-insensitively and ignoring the parameters of the
+/// `Content-Type`.
+///
+/// # Examples
+///
+/// ```
+/// use server::compression_policy::MediaRange;
+///
+/// let range: MediaRange = "image/*".parse().unwrap();
+/// assert!(range.matches("image/png"));
+/// assert!(!range.matches("text/html; charset=utf-8"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaRange {
+    type_: String,
+    subtype: Option<String>,
+}
+
+error_type!(InvalidMediaRange);
+
+impl MediaRange {
+    /// Whether the `Content-Type` belongs to this range.
+    pub fn matches(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let Some((type_, subtype)) = essence.split_once('/') else {
+            return false;
+        };
+        type_.eq_ignore_ascii_case(&self.type_)
+            && self
+                .subtype
+                .as_ref()
+                .is_none_or(|expected| subtype.eq_ignore_ascii_case(expected))
+    }
+}
+
+impl FromStr for MediaRange {
+    type Err = InvalidMediaRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMediaRange { _inner: () };
+        let is_token = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+        };
+        let (type_, subtype) = s.trim().split_once('/').ok_or_else(invalid)?;
+        if !is_token(type_) {
+            return Err(invalid());
+        }
+        let subtype = match subtype {
+            "*" => None,
+            subtype if is_token(subtype) => Some(subtype.to_ascii_lowercase()),
+            _ => return Err(invalid()),
+        };
+        Ok(MediaRange {
+            type_: type_.to_ascii_lowercase(),
+            subtype,
+        })
+    }
+}
+
+impl fmt::Display for MediaRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.type_,
+            self.subtype.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+/// Decides whether a compressed representation is worth serving instead of
+/// the original content.
+///
+/// Already compressed formats, like most images, fonts and videos, often grow
+/// when they are compressed again, so a compressed representation is only
+/// served when the original isn't excluded by its media type, is at least
+/// `min_size` bytes, and the compressed one is at least `min_savings` percent
+/// smaller.
+///
+/// # Examples
+///
+/// ```
+/// use server::compression_policy::CompressionPolicy;
+///
+/// let policy = CompressionPolicy::default();
+/// assert!(policy.pays_off("text/javascript", 100_000, 30_000));
+/// assert!(!policy.pays_off("text/javascript", 100_000, 95_000));
+/// assert!(!policy.pays_off("image/png", 100_000, 30_000));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionPolicy {
+    min_size: u64,
+    min_savings: u8,
+    excluded: Vec<MediaRange>,
+}
+
+impl CompressionPolicy {
+    /// A policy that only excludes the given media types.
+    ///
+    /// `min_savings` is a percentage and is capped to 100.
+    pub fn new(min_size: u64, min_savings: u8, excluded: Vec<MediaRange>) -> Self {
+        CompressionPolicy {
+            min_size,
+            min_savings: min_savings.min(100),
+            excluded,
+        }
+    }
+
+    /// The given media types are excluded in addition to the default ones.
+    pub fn with_defaults(min_size: u64, min_savings: u8, excluded: Vec<MediaRange>) -> Self {
+        let mut policy = CompressionPolicy::new(min_size, min_savings, excluded);
+        policy
+            .excluded
+            .extend(CompressionPolicy::default_exclusions());
+        policy
+    }
+
+    /// The media types that are already compressed. SVG images and icons are
+    /// text or bitmaps and still benefit from compression.
+    pub fn default_exclusions() -> Vec<MediaRange> {
+        [
+            "image/png",
+            "image/jpeg",
+            "image/gif",
+            "image/webp",
+            "image/avif",
+            "font/woff",
+            "font/woff2",
+            "audio/*",
+            "video/*",
+            "application/gzip",
+            "application/zip",
+            "application/zstd",
+            "application/x-7z-compressed",
+            "application/x-rar-compressed",
+        ]
+        .into_iter()
+        .map(|range| range.parse().expect("the default exclusions are valid"))
+        .collect()
+    }
+
+    /// Whether the content is worth compressing at all, regardless of how
+    /// well the compression went.
+    pub fn is_compressible(&self, content_type: &str, length: u64) -> bool {
+        length >= self.min_size
+            && !self
+                .excluded
+                .iter()
+                .any(|range| range.matches(content_type))
+    }
+
+    /// Whether the compressed representation should be served instead of the
+    /// original content.
+    pub fn pays_off(&self, content_type: &str, length: u64, encoded_length: u64) -> bool {
+        self.is_compressible(content_type, length)
+            && encoded_length < length
+            && encoded_length as u128 * 100 <= length as u128 * (100 - self.min_savings) as u128
+    }
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        CompressionPolicy::with_defaults(DEFAULT_MIN_SIZE, DEFAULT_MIN_SAVINGS, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_range() {
+        let png: MediaRange = "Image/PNG".parse().unwrap();
+        assert_eq!(png.to_string(), "image/png");
+        assert!(png.matches("image/png"));
+        assert!(png.matches("IMAGE/png; q=1"));
+        assert!(!png.matches("image/svg+xml"));
+
+        let video: MediaRange = "video/*".parse().unwrap();
+        assert_eq!(video.to_string(), "video/*");
+        assert!(video.matches("video/mp4"));
+        assert!(!video.matches("audio/mp4"));
+        assert!(!video.matches("video"));
+    }
+
+    #[test]
+    fn invalid_media_range() {
+        assert!("image".parse::<MediaRange>().is_err());
+        assert!("*/*".parse::<MediaRange>().is_err());
+        assert!("image/".parse::<MediaRange>().is_err());
+        assert!("image/png; charset=utf-8".parse::<MediaRange>().is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        let policy = CompressionPolicy::default();
+        assert!(policy.pays_off("text/html", 4096, 1024));
+        assert!(policy.pays_off("image/svg+xml", 4096, 1024));
+        assert!(policy.pays_off("image/x-icon", 4096, 1024));
+        assert!(!policy.pays_off("image/webp", 4096, 1024));
+        assert!(!policy.pays_off("font/woff2", 4096, 1024));
+        assert!(!policy.pays_off("video/mp4", 4096, 1024));
+    }
+
+    #[test]
+    fn min_size() {
+        let policy = CompressionPolicy::default();
+        assert!(!policy.is_compressible("text/css", DEFAULT_MIN_SIZE - 1));
+        assert!(policy.is_compressible("text/css", DEFAULT_MIN_SIZE));
+        assert!(!policy.pays_off("text/css", 512, 100));
+    }
+
+    #[test]
+    fn min_savings() {
+        let policy = CompressionPolicy::new(0, 25, Vec::new());
+        assert!(policy.pays_off("text/css", 1000, 750));
+        assert!(!policy.pays_off("text/css", 1000, 751));
+        assert!(!policy.pays_off("image/png", 1000, 1200));
+
+        // Even without required savings, a representation has to be smaller
+        let policy = CompressionPolicy::new(0, 0, Vec::new());
+        assert!(policy.pays_off("image/png", 1000, 999));
+        assert!(!policy.pays_off("image/png", 1000, 1000));
+
+        let policy = CompressionPolicy::new(0, 200, Vec::new());
+        assert!(!policy.pays_off("text/css", 1000, 1));
+    }
+
+    #[test]
+    fn custom_exclusions() {
+        let policy = CompressionPolicy::new(0, 0, vec!["application/*".parse().unwrap()]);
+        assert!(!policy.pays_off("application/json", 1000, 100));
+        assert!(policy.pays_off("image/png", 1000, 100));
+    }
+}
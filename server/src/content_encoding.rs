@@ -3,7 +3,7 @@ use crate::util::flat_csv::FlatCsv;
 use headers_core::Error;
 use http::HeaderValue;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ContentEncoding(FlatCsv);
 
 impl headers_core::Header for ContentEncoding {
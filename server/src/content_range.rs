@@ -0,0 +1,234 @@
+use crate::error_type;
+use headers_core::Error;
+use http::HeaderValue;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+/// `Content-Range` header, defined in
+/// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-14.4)
+///
+/// The "Content-Range" header field is sent in a single part 206 (Partial
+/// Content) response to indicate the partial range of the selected
+/// representation enclosed as the message content, sent in each part of a
+/// multipart 206 response to indicate the range enclosed within each body
+/// part, and sent in 416 (Range Not Satisfiable) responses to provide
+/// information about the selected representation.
+///
+/// # ABNF
+///
+/// ```text
+/// Content-Range       = range-unit SP
+///                       ( range-resp / unsatisfied-range )
+///
+/// range-resp          = incl-range "/" ( complete-length / "*" )
+/// incl-range          = first-pos "-" last-pos
+/// unsatisfied-range   = "*/" complete-length
+///
+/// complete-length     = 1*DIGIT
+/// ```
+///
+/// # Example values
+///
+/// * `bytes 42-1233/1234`
+/// * `bytes 42-1233/*`
+/// * `bytes */1234`
+///
+/// # Examples
+///
+/// ```
+/// use server::content_range::ContentRange;
+///
+/// // 100 bytes (included byte 199), with a full length of 3,400
+/// let cr = ContentRange::bytes(100..200, 3400).unwrap();
+/// assert_eq!(cr.to_string(), "bytes 100-199/3400");
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentRange {
+    /// First and last bytes of the range, omitted if request could not be
+    /// satisfied
+    range: Option<(u64, u64)>,
+
+    /// Total length of the instance, can be omitted if unknown
+    complete_length: Option<u64>,
+}
+
+error_type!(InvalidContentRange);
+
+impl ContentRange {
+    /// Construct a new `Content-Range: bytes ..` header.
+    pub fn bytes(
+        range: impl RangeBounds<u64>,
+        complete_length: impl Into<Option<u64>>,
+    ) -> Result<ContentRange, InvalidContentRange> {
+        let complete_length = complete_length.into();
+        let invalid = || InvalidContentRange { _inner: () };
+
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.checked_add(1).ok_or_else(invalid)?,
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e,
+            Bound::Excluded(&e) => e.checked_sub(1).ok_or_else(invalid)?,
+            Bound::Unbounded => complete_length
+                .and_then(|max| max.checked_sub(1))
+                .ok_or_else(invalid)?,
+        };
+
+        if end < start || complete_length.is_some_and(|max| end >= max) {
+            return Err(invalid());
+        }
+
+        Ok(ContentRange {
+            range: Some((start, end)),
+            complete_length,
+        })
+    }
+
+    /// Create a new `ContentRange` stating the range could not be satisfied.
+    ///
+    /// The passed argument is the complete length of the entity.
+    pub fn unsatisfied_bytes(complete_length: u64) -> Self {
+        ContentRange {
+            range: None,
+            complete_length: Some(complete_length),
+        }
+    }
+
+    /// Get the byte range if satisfied.
+    ///
+    /// Note that these byte ranges are inclusive on both ends.
+    pub fn bytes_range(&self) -> Option<(u64, u64)> {
+        self.range
+    }
+
+    /// Get the bytes complete length if available.
+    pub fn bytes_len(&self) -> Option<u64> {
+        self.complete_length
+    }
+}
+
+impl fmt::Display for ContentRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("bytes ")?;
+        if let Some((first, last)) = self.range {
+            write!(f, "{first}-{last}")?;
+        } else {
+            f.write_str("*")?;
+        }
+        f.write_str("/")?;
+        if let Some(len) = self.complete_length {
+            write!(f, "{len}")
+        } else {
+            f.write_str("*")
+        }
+    }
+}
+
+impl headers_core::Header for ContentRange {
+    fn name() -> &'static ::http::header::HeaderName {
+        &::http::header::CONTENT_RANGE
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        values
+            .next()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.split_once(' '))
+            .and_then(|(unit, spec)| {
+                if unit != "bytes" {
+                    return None;
+                }
+
+                let (range, complete_length) = spec.split_once('/')?;
+
+                let complete_length = if complete_length == "*" {
+                    None
+                } else {
+                    Some(complete_length.parse().ok()?)
+                };
+
+                let range = if range == "*" {
+                    None
+                } else {
+                    let (first_byte, last_byte) = range.split_once('-')?;
+                    let first_byte = first_byte.parse().ok()?;
+                    let last_byte = last_byte.parse().ok()?;
+                    if last_byte < first_byte {
+                        return None;
+                    }
+                    Some((first_byte, last_byte))
+                };
+
+                Some(ContentRange {
+                    range,
+                    complete_length,
+                })
+            })
+            .ok_or_else(Error::invalid)
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        let value = HeaderValue::try_from(self.to_string())
+            .expect("Content-Range is always a valid header value");
+        values.extend(std::iter::once(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+
+    fn test_decode(value: &'static str) -> Option<ContentRange> {
+        let mut map = ::http::HeaderMap::new();
+        map.insert(
+            ::http::header::CONTENT_RANGE,
+            HeaderValue::from_static(value),
+        );
+        map.typed_get()
+    }
+
+    #[test]
+    fn decode() {
+        let cr = test_decode("bytes 0-499/1234").unwrap();
+        assert_eq!(cr.bytes_range(), Some((0, 499)));
+        assert_eq!(cr.bytes_len(), Some(1234));
+
+        let cr = test_decode("bytes */1234").unwrap();
+        assert_eq!(cr.bytes_range(), None);
+        assert_eq!(cr.bytes_len(), Some(1234));
+
+        let cr = test_decode("bytes 0-499/*").unwrap();
+        assert_eq!(cr.bytes_len(), None);
+
+        assert!(test_decode("bytes 500-499/1234").is_none());
+        assert!(test_decode("items 0-1/2").is_none());
+    }
+
+    #[test]
+    fn encode() {
+        let mut map = ::http::HeaderMap::new();
+        map.typed_insert(ContentRange::bytes(0..=499, 1234).unwrap());
+        assert_eq!(map["content-range"], "bytes 0-499/1234");
+
+        map.typed_insert(ContentRange::unsatisfied_bytes(1234));
+        assert_eq!(map["content-range"], "bytes */1234");
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(
+            ContentRange::bytes(10.., 20).unwrap().bytes_range(),
+            Some((10, 19))
+        );
+        assert!(ContentRange::bytes(10.., None).is_err());
+        assert!(ContentRange::bytes(0..=20, 20).is_err());
+        assert!(ContentRange::bytes(5..5, 20).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+use crate::accept::Accept;
+use crate::api_response::ApiResponse;
+use crate::error_type;
+use crate::util::quality::Quality;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use headers::HeaderMapExt;
+use http::{HeaderValue, StatusCode};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const TEXT_HTML: &str = "text/html";
+const APPLICATION_JSON: &str = "application/json";
+
+/// A status code such as `404`, or a class of status codes such as `5xx`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusPattern {
+    Code(StatusCode),
+    /// The first digit of the status codes.
+    Class(u8),
+}
+
+error_type!(InvalidStatusPattern);
+
+impl StatusPattern {
+    pub fn matches(&self, status: StatusCode) -> bool {
+        match self {
+            StatusPattern::Code(code) => *code == status,
+            StatusPattern::Class(class) => status.as_u16() / 100 == *class as u16,
+        }
+    }
+}
+
+impl FromStr for StatusPattern {
+    type Err = InvalidStatusPattern;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidStatusPattern { _inner: () };
+        let s = s.trim();
+        if let Some(class) = s.strip_suffix("xx").or_else(|| s.strip_suffix("XX")) {
+            return match class {
+                "4" => Ok(StatusPattern::Class(4)),
+                "5" => Ok(StatusPattern::Class(5)),
+                _ => Err(invalid()),
+            };
+        }
+        let code = s.parse::<u16>().map_err(|_| invalid())?;
+        match StatusCode::from_u16(code) {
+            Ok(status) if status.is_client_error() || status.is_server_error() => {
+                Ok(StatusPattern::Code(status))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for StatusPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusPattern::Code(status) => write!(f, "{}", status.as_u16()),
+            StatusPattern::Class(class) => write!(f, "{class}xx"),
+        }
+    }
+}
+
+/// Assigns an HTML document to the error responses matching a status pattern.
+///
+/// The textual form is `<status>=<document>`, e.g. `404=404.html` or
+/// `5xx=50x.html`, only client and server errors can have a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorPageRule {
+    status: StatusPattern,
+    document: String,
+}
+
+error_type!(InvalidErrorPageRule);
+
+impl ErrorPageRule {
+    pub fn new(status: StatusPattern, document: impl Into<String>) -> Self {
+        ErrorPageRule {
+            status,
+            document: document.into(),
+        }
+    }
+
+    /// The document of this rule if the status matches it.
+    pub fn document(&self, status: StatusCode) -> Option<&str> {
+        self.status
+            .matches(status)
+            .then_some(self.document.as_str())
+    }
+}
+
+impl FromStr for ErrorPageRule {
+    type Err = InvalidErrorPageRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidErrorPageRule { _inner: () };
+        let (status, document) = s.split_once('=').ok_or_else(invalid)?;
+        let document = document.trim().trim_start_matches('/');
+        if document.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ErrorPageRule {
+            status: status.parse().map_err(|_| invalid())?,
+            document: document.to_owned(),
+        })
+    }
+}
+
+/// The bodies of the error responses.
+///
+/// Browsers get the HTML document of the first rule matching the status,
+/// while API clients, which prefer `application/json` to `text/html` in
+/// `Accept`, get an [`ApiResponse`] in the shape the frontend expects. The
+/// documents are loaded once with [`ErrorPages::load`], a rule whose document
+/// can't be loaded is skipped.
+///
+/// # Examples
+///
+/// ```
+/// use http::StatusCode;
+/// use server::error_page::ErrorPages;
+///
+/// let pages = ErrorPages::default().load(|document| {
+///     (document == "404.html").then(|| "<h1>404</h1>".into())
+/// });
+/// assert!(pages.html(StatusCode::NOT_FOUND).is_some());
+/// assert!(pages.html(StatusCode::INTERNAL_SERVER_ERROR).is_none());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ErrorPages {
+    rules: Vec<ErrorPageRule>,
+    documents: HashMap<String, Bytes>,
+}
+
+impl ErrorPages {
+    /// Error pages that only consist of the given rules.
+    pub fn new(rules: Vec<ErrorPageRule>) -> Self {
+        ErrorPages {
+            rules,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// The given rules are checked before the default rules.
+    pub fn with_defaults(rules: Vec<ErrorPageRule>) -> Self {
+        let mut pages = ErrorPages::new(rules);
+        pages.rules.extend(ErrorPages::default_rules());
+        pages
+    }
+
+    /// `404.html` for `404 Not Found`, and `50x.html` for the server errors.
+    pub fn default_rules() -> Vec<ErrorPageRule> {
+        vec![
+            ErrorPageRule::new(StatusPattern::Code(StatusCode::NOT_FOUND), "404.html"),
+            ErrorPageRule::new(StatusPattern::Class(5), "50x.html"),
+        ]
+    }
+
+    /// Loads the documents of the rules, `read` returns `None` when the
+    /// document doesn't exist.
+    pub fn load(mut self, mut read: impl FnMut(&str) -> Option<Bytes>) -> Self {
+        for rule in &self.rules {
+            if !self.documents.contains_key(&rule.document)
+                && let Some(content) = read(&rule.document)
+            {
+                self.documents.insert(rule.document.clone(), content);
+            }
+        }
+        self
+    }
+
+    /// The HTML document of the first rule matching the status whose document
+    /// is loaded.
+    pub fn html(&self, status: StatusCode) -> Option<&Bytes> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.document(status))
+            .find_map(|document| self.documents.get(document))
+    }
+
+    /// The body of an error response with the status, `None` if the client
+    /// wants HTML and there is no document for the status.
+    pub fn response(&self, status: StatusCode, accept: Option<&Accept>) -> Option<Response> {
+        if prefers_json(accept) {
+            return Some(ApiResponse::error(status).into_response());
+        }
+        let html = self.html(status)?;
+        let mut response = (status, Body::from(html.clone())).into_response();
+        response.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        Some(response)
+    }
+}
+
+impl Default for ErrorPages {
+    fn default() -> Self {
+        ErrorPages::with_defaults(Vec::new())
+    }
+}
+
+/// Whether the client prefers JSON to HTML, when both have the same quality
+/// the one listed explicitly wins, and HTML wins otherwise.
+fn prefers_json(accept: Option<&Accept>) -> bool {
+    let Some(accept) = accept else {
+        return false;
+    };
+    let preference = |media_type| {
+        (
+            accept.quality_of(media_type).unwrap_or(Quality::ZERO),
+            accept.lists(media_type),
+        )
+    };
+    preference(APPLICATION_JSON) > preference(TEXT_HTML)
+}
+
+/// A middleware that fills the empty bodies of the error responses with the
+/// [`ErrorPages`], the headers of the original response are kept.
+pub async fn error_pages(
+    State(pages): State<Arc<ErrorPages>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accept = request.headers().typed_get::<Accept>();
+    let response = next.run(request).await;
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error())
+        || response.body().size_hint().exact() != Some(0)
+    {
+        return response;
+    }
+    let Some(error_page) = pages.response(status, accept.as_ref()) else {
+        return response;
+    };
+    let (mut parts, _) = response.into_parts();
+    let (error_parts, body) = error_page.into_parts();
+    for name in [http::header::CONTENT_TYPE, http::header::CONTENT_LENGTH] {
+        parts.headers.remove(&name);
+        if let Some(value) = error_parts.headers.get(&name) {
+            parts.headers.insert(name, value.clone());
+        }
+    }
+    parts
+        .headers
+        .append(http::header::VARY, HeaderValue::from_static("accept"));
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use axum::routing::get;
+    use tower::{Layer, ServiceExt};
+
+    #[test]
+    fn status_pattern() {
+        let not_found: StatusPattern = "404".parse().unwrap();
+        assert!(not_found.matches(StatusCode::NOT_FOUND));
+        assert!(!not_found.matches(StatusCode::FORBIDDEN));
+        let server_error: StatusPattern = "5xx".parse().unwrap();
+        assert_eq!(server_error.to_string(), "5xx");
+        assert!(server_error.matches(StatusCode::BAD_GATEWAY));
+        assert!(!server_error.matches(StatusCode::NOT_FOUND));
+        for s in ["200", "301", "3xx", "45xx", "x", "600", "4x"] {
+            assert!(s.parse::<StatusPattern>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let rule: ErrorPageRule = "403=/errors/403.html".parse().unwrap();
+        assert_eq!(
+            rule.document(StatusCode::FORBIDDEN),
+            Some("errors/403.html")
+        );
+        assert_eq!(rule.document(StatusCode::NOT_FOUND), None);
+        assert!("403".parse::<ErrorPageRule>().is_err());
+        assert!("403=".parse::<ErrorPageRule>().is_err());
+        assert!("200=ok.html".parse::<ErrorPageRule>().is_err());
+    }
+
+    fn pages() -> ErrorPages {
+        ErrorPages::with_defaults(vec!["502=502.html".parse().unwrap()]).load(|document| {
+            matches!(document, "404.html" | "50x.html").then(|| Bytes::from(document.to_owned()))
+        })
+    }
+
+    #[test]
+    fn first_loaded_document() {
+        let pages = pages();
+        assert_eq!(pages.html(StatusCode::NOT_FOUND).unwrap(), "404.html");
+        // 502.html isn't loaded, so the class rule is used
+        assert_eq!(pages.html(StatusCode::BAD_GATEWAY).unwrap(), "50x.html");
+        assert_eq!(pages.html(StatusCode::FORBIDDEN), None);
+    }
+
+    #[test]
+    fn negotiation() {
+        let accept = |value: &str| {
+            let mut map = http::HeaderMap::new();
+            map.insert(http::header::ACCEPT, value.parse().unwrap());
+            map.typed_get::<Accept>()
+        };
+        assert!(prefers_json(
+            accept("application/json, text/plain, */*").as_ref()
+        ));
+        assert!(prefers_json(accept("application/json").as_ref()));
+        assert!(!prefers_json(
+            accept("text/html,application/xhtml+xml,*/*;q=0.8").as_ref()
+        ));
+        assert!(!prefers_json(accept("*/*").as_ref()));
+        assert!(!prefers_json(
+            accept("text/html, application/json").as_ref()
+        ));
+        assert!(!prefers_json(None));
+    }
+
+    async fn request(status: StatusCode, accept: &str) -> Response {
+        let router = axum::Router::new()
+            .route("/", get(move || async move { status }))
+            .route("/body", get(|| async { (StatusCode::NOT_FOUND, "custom") }));
+        let app =
+            axum::middleware::from_fn_with_state(Arc::new(pages()), error_pages).layer(router);
+        let uri = if accept.is_empty() { "/body" } else { "/" };
+        let request = Request::builder()
+            .uri(uri)
+            .header(http::header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> String {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        let response = request(StatusCode::NOT_FOUND, "text/html").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(response.headers()[http::header::VARY], "accept");
+        assert_eq!(body(response).await, "404.html");
+
+        let response = request(StatusCode::SERVICE_UNAVAILABLE, "application/json").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body(response).await,
+            r#"{"code":503,"msg":"Service Unavailable","data":null}"#
+        );
+
+        // Without a document the response is left untouched
+        let response = request(StatusCode::FORBIDDEN, "text/html").await;
+        assert_eq!(body(response).await, "");
+
+        let response = request(StatusCode::NO_CONTENT, "application/json").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(body(response).await, "");
+
+        // A response with a body is never replaced
+        let response = request(StatusCode::NOT_FOUND, "").await;
+        assert_eq!(body(response).await, "custom");
+    }
+}
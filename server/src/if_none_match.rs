@@ -52,10 +52,24 @@ impl IfNoneMatch {
         IfNoneMatch(EntityTagRange::Any)
     }
 
+    /// Returns whether this is `If-None-Match: *`, matching any entity tag.
+    pub fn is_any(&self) -> bool {
+        matches!(self.0, EntityTagRange::Any)
+    }
+
     /// Checks whether the ETag passes this precondition.
     pub fn precondition_passes(&self, etag: &ETag) -> bool {
         !self.0.matches_weak(&etag.0)
     }
+
+    /// Returns the first of the given entity-tags that this header matches.
+    ///
+    /// A cache that stored several representations of one resource, e.g. one
+    /// per content-coding, lists all their entity-tags, and the one matched
+    /// tells which stored representation can be reused.
+    pub fn first_match<'a>(&self, etags: &'a [ETag]) -> Option<&'a ETag> {
+        etags.iter().find(|etag| self.0.matches_weak(&etag.0))
+    }
 }
 
 impl From<ETag> for IfNoneMatch {
@@ -63,3 +77,24 @@ impl From<ETag> for IfNoneMatch {
         IfNoneMatch(EntityTagRange::Tags(HeaderValue::from(etag.0).into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_match() {
+        let identity = ETag::from_static("\"foo\"");
+        let brotli = ETag::from_static("\"foo-br\"");
+        let variants = [identity.clone(), brotli.clone()];
+
+        let if_none_match = IfNoneMatch::from(brotli.clone());
+        assert!(if_none_match.precondition_passes(&identity));
+        assert_eq!(if_none_match.first_match(&variants), Some(&brotli));
+
+        let if_none_match = IfNoneMatch::from(ETag::from_static("\"bar\""));
+        assert_eq!(if_none_match.first_match(&variants), None);
+
+        assert_eq!(IfNoneMatch::any().first_match(&variants), Some(&identity));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::derive_header;
+use crate::etag::ETag;
+use crate::util::TryFromValues;
+use crate::util::entity::EntityTag;
+use crate::util::http_date::HttpDate;
+use headers_core::Error;
+use http::HeaderValue;
+use std::time::SystemTime;
+
+/// `If-Range` header, defined in [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-13.1.5)
+///
+/// If a client has a partial copy of a representation and wishes to have
+/// an up-to-date copy of the entire representation, it could use the
+/// Range header field with a conditional GET (using either or both of
+/// If-Unmodified-Since and If-Match.)  However, if the precondition
+/// fails because the representation has been modified, the client would
+/// then have to make a second request to obtain the entire current
+/// representation.
+///
+/// The `If-Range` header field allows a client to "short-circuit" the
+/// second request.  Informally, its meaning is as follows: if the
+/// representation is unchanged, send me the part(s) that I am requesting
+/// in Range; otherwise, send me the entire representation.
+///
+/// # ABNF
+///
+/// ```text
+/// If-Range = entity-tag / HTTP-date
+/// ```
+///
+/// # Example values
+///
+/// * `Sat, 29 Oct 1994 19:43:31 GMT`
+/// * `"xyzzy"`
+///
+/// # Examples
+///
+/// ```
+/// use server::if_range::IfRange;
+/// use std::time::{SystemTime, Duration};
+///
+/// let fetched = SystemTime::now() - Duration::from_secs(60 * 60 * 24);
+/// let if_range = IfRange::date(fetched);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct IfRange(IfRangeValue);
+
+derive_header! {
+    IfRange(_),
+    name: IF_RANGE
+}
+
+impl IfRange {
+    /// Create an `IfRange` header with an entity tag.
+    pub fn etag(tag: ETag) -> IfRange {
+        IfRange(IfRangeValue::EntityTag(tag.0))
+    }
+
+    /// Create an `IfRange` header with a date value.
+    pub fn date(time: SystemTime) -> IfRange {
+        IfRange(IfRangeValue::Date(time.into()))
+    }
+
+    /// Checks if the resource has been modified, or if the range request
+    /// can be served.
+    ///
+    /// An entity-tag is compared with the strong comparison function and a
+    /// date must exactly match the `Last-Modified` of the representation.
+    pub fn is_modified(&self, etag: Option<&ETag>, last_modified: Option<SystemTime>) -> bool {
+        match self.0 {
+            IfRangeValue::Date(since) => last_modified
+                .map(|time| since != HttpDate::from(time))
+                .unwrap_or(true),
+            IfRangeValue::EntityTag(ref entity) => {
+                etag.map(|etag| !etag.0.strong_eq(entity)).unwrap_or(true)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum IfRangeValue {
+    /// The entity-tag the client has of the resource
+    EntityTag(EntityTag),
+    /// The date when the client retrieved the resource
+    Date(HttpDate),
+}
+
+impl TryFromValues for IfRangeValue {
+    fn try_from_values<'i, I>(values: &mut I) -> Result<Self, Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        values
+            .next()
+            .and_then(|val| {
+                if let Some(tag) = EntityTag::from_val(val) {
+                    return Some(IfRangeValue::EntityTag(tag));
+                }
+
+                let date = HttpDate::from_val(val)?;
+                Some(IfRangeValue::Date(date))
+            })
+            .ok_or_else(Error::invalid)
+    }
+}
+
+impl<'a> From<&'a IfRangeValue> for HeaderValue {
+    fn from(if_range: &'a IfRangeValue) -> HeaderValue {
+        match *if_range {
+            IfRangeValue::EntityTag(ref tag) => tag.into(),
+            IfRangeValue::Date(ref date) => date.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn is_modified_etag() {
+        let etag = ETag::from_static("\"xyzzy\"");
+        let if_range = IfRange::etag(etag.clone());
+
+        assert!(!if_range.is_modified(Some(&etag), None));
+
+        let etag = ETag::from_static("W/\"xyzzy\"");
+        assert!(if_range.is_modified(Some(&etag), None));
+        assert!(if_range.is_modified(None, None));
+    }
+
+    #[test]
+    fn is_modified_date() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(784198117);
+        let if_range = IfRange::date(modified);
+
+        assert!(!if_range.is_modified(None, Some(modified)));
+        assert!(if_range.is_modified(None, Some(modified + Duration::from_secs(1))));
+        assert!(if_range.is_modified(None, None));
+    }
+}
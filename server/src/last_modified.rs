@@ -0,0 +1,51 @@
+use crate::derive_header;
+use crate::util::http_date::HttpDate;
+use std::time::SystemTime;
+
+/// `Last-Modified` header, defined in
+/// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-8.8.2)
+///
+/// The `Last-Modified` header field in a response provides a timestamp
+/// indicating the date and time at which the origin server believes the
+/// selected representation was last modified, as determined at the
+/// conclusion of handling the request.
+///
+/// # ABNF
+///
+/// ```text
+/// Last-Modified = HTTP-date
+/// ```
+///
+/// # Example values
+///
+/// * `Sat, 29 Oct 1994 19:43:31 GMT`
+///
+/// # Example
+///
+/// ```
+/// use server::last_modified::LastModified;
+/// use std::time::{Duration, SystemTime};
+///
+/// let modified = LastModified::from(
+///     SystemTime::now() - Duration::from_secs(60 * 60 * 24)
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy)]
+pub struct LastModified(pub(super) HttpDate);
+
+derive_header! {
+    LastModified(_),
+    name: LAST_MODIFIED
+}
+
+impl From<SystemTime> for LastModified {
+    fn from(time: SystemTime) -> LastModified {
+        LastModified(time.into())
+    }
+}
+
+impl From<LastModified> for SystemTime {
+    fn from(date: LastModified) -> SystemTime {
+        date.0.into()
+    }
+}
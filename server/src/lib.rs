@@ -1,7 +1,36 @@
+pub mod accept;
 pub mod accept_encoding;
+pub mod accept_ranges;
+pub mod api;
+pub mod api_response;
+pub mod base_path;
+pub mod byte_ranges;
+pub mod cache_control;
+pub mod cache_policy;
+pub mod compression_policy;
 pub mod content_encoding;
+pub mod content_range;
+pub mod csp_nonce;
+pub mod error_page;
 pub mod etag;
+pub mod if_match;
+pub mod if_modified_since;
 pub mod if_none_match;
+pub mod if_range;
+pub mod if_unmodified_since;
+pub mod last_modified;
+pub mod mime_types;
+pub mod mock_api;
+pub mod path_pattern;
+pub mod precondition;
+pub mod prepared_file;
+pub mod proxy;
+pub mod range;
+pub mod request_path;
+pub mod runtime_config;
+pub mod security_headers;
+pub mod spa_fallback;
+pub mod websocket_proxy;
 #[macro_use]
 mod util;
 
@@ -0,0 +1,212 @@
+use crate::error_type;
+use http::HeaderValue;
+use std::str::FromStr;
+
+/// Assigns a `Content-Type` to every file with an extension, overriding the
+/// type guessed from the built-in table.
+///
+/// The textual form is `<extension>=<content type>`. The extension is
+/// compared case-insensitively and may start with a dot. A textual type
+/// without parameters is declared as `charset=utf-8`, which is what Vite
+/// emits.
+///
+/// # Examples
+///
+/// ```
+/// use server::mime_types::MimeRule;
+///
+/// let rule: MimeRule = ".webmanifest=application/manifest+json".parse().unwrap();
+/// assert_eq!(
+///     rule.content_type("manifest.webmanifest"),
+///     Some("application/manifest+json; charset=utf-8")
+/// );
+/// assert_eq!(rule.content_type("index.html"), None);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MimeRule {
+    extension: String,
+    content_type: String,
+}
+
+error_type!(InvalidMimeRule);
+
+impl MimeRule {
+    /// The `Content-Type` of this rule if the path has its extension.
+    pub fn content_type(&self, path: &str) -> Option<&str> {
+        let name = path.rsplit('/').next().unwrap_or_default();
+        let (_, extension) = name.rsplit_once('.')?;
+        extension
+            .eq_ignore_ascii_case(&self.extension)
+            .then_some(self.content_type.as_str())
+    }
+}
+
+impl FromStr for MimeRule {
+    type Err = InvalidMimeRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMimeRule { _inner: () };
+        let is_token = |s: &str| {
+            !s.is_empty()
+                && s.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+        };
+        let (extension, content_type) = s.split_once('=').ok_or_else(invalid)?;
+        let extension = extension.trim();
+        let extension = extension.strip_prefix('.').unwrap_or(extension);
+        if !is_token(extension) || extension.contains('.') {
+            return Err(invalid());
+        }
+        let content_type = content_type.trim();
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let (type_, subtype) = essence.split_once('/').ok_or_else(invalid)?;
+        if !is_token(type_) || !is_token(subtype) || HeaderValue::from_str(content_type).is_err() {
+            return Err(invalid());
+        }
+        Ok(MimeRule {
+            extension: extension.to_ascii_lowercase(),
+            content_type: dist::mime::with_charset(content_type),
+        })
+    }
+}
+
+/// An ordered list of [`MimeRule`]s, the first matching rule wins. The paths
+/// matching none of them keep the guessed type.
+///
+/// # Examples
+///
+/// ```
+/// use server::mime_types::MimeTypes;
+///
+/// let types = MimeTypes::default();
+/// assert_eq!(
+///     types.content_type("assets/worker.mjs"),
+///     Some("text/javascript; charset=utf-8")
+/// );
+/// assert_eq!(types.content_type("assets/app.wasm"), Some("application/wasm"));
+/// assert_eq!(types.content_type("index.html"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MimeTypes {
+    rules: Vec<MimeRule>,
+}
+
+impl MimeTypes {
+    /// A table that only consists of the given rules.
+    pub fn new(rules: Vec<MimeRule>) -> Self {
+        MimeTypes { rules }
+    }
+
+    /// The given rules are checked before the default rules.
+    pub fn with_defaults(rules: Vec<MimeRule>) -> Self {
+        let mut types = MimeTypes::new(rules);
+        types.rules.extend(MimeTypes::default_rules());
+        types
+    }
+
+    /// The types that browsers check strictly, which the guess table either
+    /// lacks or has an outdated entry for. Module scripts and workers are
+    /// rejected unless they are served as JavaScript, and
+    /// `WebAssembly.instantiateStreaming` requires `application/wasm`.
+    pub fn default_rules() -> Vec<MimeRule> {
+        [
+            "js=text/javascript",
+            "mjs=text/javascript",
+            "map=application/json",
+            "wasm=application/wasm",
+            "webmanifest=application/manifest+json",
+            "avif=image/avif",
+        ]
+        .into_iter()
+        .map(|rule| rule.parse().expect("the default mime rules are valid"))
+        .collect()
+    }
+
+    /// Reads the rules from a file with one `<extension>=<content type>` per
+    /// line, ignoring the blank lines and the comments starting with `#`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use server::mime_types::MimeTypes;
+    ///
+    /// let rules = MimeTypes::parse_rules("# fonts\nttf=font/ttf\n\notf=font/otf\n").unwrap();
+    /// assert_eq!(rules.len(), 2);
+    /// ```
+    pub fn parse_rules(content: &str) -> Result<Vec<MimeRule>, InvalidMimeRule> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::parse)
+            .collect()
+    }
+
+    /// The `Content-Type` of the first rule matching the path.
+    pub fn content_type(&self, path: &str) -> Option<&str> {
+        self.rules.iter().find_map(|rule| rule.content_type(path))
+    }
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        MimeTypes::new(MimeTypes::default_rules())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charset() {
+        let types = MimeTypes::new(vec![
+            "txt=text/plain; charset=iso-8859-1".parse().unwrap(),
+            "md=text/markdown".parse().unwrap(),
+            "bin=application/octet-stream".parse().unwrap(),
+        ]);
+        assert_eq!(
+            types.content_type("LEGACY.TXT"),
+            Some("text/plain; charset=iso-8859-1")
+        );
+        assert_eq!(
+            types.content_type("docs/README.md"),
+            Some("text/markdown; charset=utf-8")
+        );
+        assert_eq!(
+            types.content_type("model.bin"),
+            Some("application/octet-stream")
+        );
+    }
+
+    #[test]
+    fn custom_rules_first() {
+        let types = MimeTypes::with_defaults(vec!["js=application/javascript".parse().unwrap()]);
+        assert_eq!(
+            types.content_type("assets/index-BfT3xk9a.js"),
+            Some("application/javascript; charset=utf-8")
+        );
+        assert_eq!(
+            types.content_type("manifest.webmanifest"),
+            Some("application/manifest+json; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn extension_only() {
+        let rule: MimeRule = "map=application/json".parse().unwrap();
+        assert_eq!(rule.content_type("map"), None);
+        assert_eq!(rule.content_type("assets.map/index.js"), None);
+        assert!(rule.content_type("assets/index.js.map").is_some());
+    }
+
+    #[test]
+    fn invalid_rule() {
+        assert!("js".parse::<MimeRule>().is_err());
+        assert!("=text/javascript".parse::<MimeRule>().is_err());
+        assert!("js=javascript".parse::<MimeRule>().is_err());
+        assert!("tar.gz=application/gzip".parse::<MimeRule>().is_err());
+        assert!("js=text/java script".parse::<MimeRule>().is_err());
+        assert!(MimeTypes::parse_rules("ttf=font/ttf\nbroken\n").is_err());
+    }
+}
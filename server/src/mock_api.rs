@@ -0,0 +1,364 @@
+use crate::api::auth::authorization_token;
+use crate::api::types::{
+    LoginParams, LoginResponse, RoleListItem, RoleSearchParams, UserInfo, UserListItem,
+    UserSearchParams, page,
+};
+use crate::api_response::{ApiResponse, PaginatedResponse};
+use crate::error_type;
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Response};
+use http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// The fixture files, which can be overridden one by one.
+pub const FIXTURES: [&str; 4] = ["accounts.json", "users.json", "roles.json", "menus.json"];
+
+/// An account that can log in, along with the `UserInfo` of the frontend.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub user_id: u64,
+    pub user_name: String,
+    pub password: String,
+    pub email: String,
+    #[serde(default)]
+    pub avatar: Option<String>,
+    pub roles: Vec<String>,
+    pub buttons: Vec<String>,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+error_type!(InvalidFixture);
+
+/// An offline stand-in for the backend of the frontend, answering the
+/// requests of `src/api` from JSON fixtures in the `{code, msg, data}`
+/// envelope.
+///
+/// The fixtures are embedded, and each of [`FIXTURES`] can be replaced by a
+/// file of the same shape. The accounts log in with their password and get
+/// their fixed token, which `/api/user/info` expects in `Authorization`, with
+/// or without the `Bearer` scheme.
+///
+/// # Examples
+///
+/// ```
+/// use server::api::types::UserSearchParams;
+/// use server::mock_api::MockApi;
+///
+/// let mock_api = MockApi::load(|_| None).unwrap();
+/// let params = UserSearchParams {
+///     user_name: Some("MORGAN".to_owned()),
+///     ..UserSearchParams::default()
+/// };
+/// let page = mock_api.users(&params);
+/// assert_eq!(page.records[0].user_name, "alexmorgan");
+/// assert_eq!(page.total, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockApi {
+    accounts: Vec<Account>,
+    users: Vec<UserListItem>,
+    roles: Vec<RoleListItem>,
+    menus: serde_json::Value,
+}
+
+impl MockApi {
+    /// Loads the fixtures, `read` returns the content of a fixture that
+    /// replaces the embedded one.
+    pub fn load(mut read: impl FnMut(&str) -> Option<String>) -> Result<Self, InvalidFixture> {
+        fn parse<T: DeserializeOwned>(
+            read: &mut impl FnMut(&str) -> Option<String>,
+            name: &str,
+        ) -> Result<T, InvalidFixture> {
+            let content = read(name);
+            let content = content
+                .as_deref()
+                .unwrap_or_else(|| MockApi::embedded(name));
+            serde_json::from_str(content).map_err(|_| InvalidFixture { _inner: () })
+        }
+        Ok(MockApi {
+            accounts: parse(&mut read, "accounts.json")?,
+            users: parse(&mut read, "users.json")?,
+            roles: parse(&mut read, "roles.json")?,
+            menus: parse(&mut read, "menus.json")?,
+        })
+    }
+
+    /// The embedded content of one of the [`FIXTURES`].
+    pub fn embedded(name: &str) -> &'static str {
+        match name {
+            "accounts.json" => include_str!("../fixtures/mock-api/accounts.json"),
+            "users.json" => include_str!("../fixtures/mock-api/users.json"),
+            "roles.json" => include_str!("../fixtures/mock-api/roles.json"),
+            "menus.json" => include_str!("../fixtures/mock-api/menus.json"),
+            _ => "null",
+        }
+    }
+
+    /// The account with the user name and the password.
+    pub fn login(&self, params: &LoginParams) -> Option<&Account> {
+        self.accounts.iter().find(|account| {
+            account.user_name == params.user_name && account.password == params.password
+        })
+    }
+
+    /// The account of the token.
+    pub fn account(&self, token: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.token == token)
+    }
+
+    /// The page of the users matching the search.
+    pub fn users(&self, params: &UserSearchParams) -> PaginatedResponse<UserListItem> {
+        let users = self
+            .users
+            .iter()
+            .filter(|user| params.matches(user))
+            .cloned()
+            .collect();
+        paginate(users, params.current, params.size)
+    }
+
+    /// The page of the roles matching the search.
+    pub fn roles(&self, params: &RoleSearchParams) -> PaginatedResponse<RoleListItem> {
+        let roles = self
+            .roles
+            .iter()
+            .filter(|role| params.matches(role))
+            .cloned()
+            .collect();
+        paginate(roles, params.current, params.size)
+    }
+
+    /// The menu tree, the `AppRouteRecord[]` of the frontend.
+    pub fn menus(&self) -> &serde_json::Value {
+        &self.menus
+    }
+}
+
+fn paginate<T>(records: Vec<T>, current: Option<u64>, size: Option<u64>) -> PaginatedResponse<T> {
+    let (current, size) = page(current, size);
+    PaginatedResponse::paginate(records, current, size)
+}
+
+/// A `400` response with the reason of a rejected request.
+fn bad_request(rejection: impl Display) -> Response {
+    ApiResponse::message(StatusCode::BAD_REQUEST, rejection.to_string()).into_response()
+}
+
+/// `POST /api/auth/login`
+pub async fn login(
+    State(mock_api): State<Arc<MockApi>>,
+    params: Result<Json<LoginParams>, JsonRejection>,
+) -> Response {
+    let Json(params) = match params {
+        Ok(params) => params,
+        Err(rejection) => return bad_request(rejection.body_text()),
+    };
+    match mock_api.login(&params) {
+        Some(account) => ApiResponse::success(LoginResponse {
+            token: account.token.clone(),
+            refresh_token: account.refresh_token.clone(),
+        })
+        .into_response(),
+        None => ApiResponse::message(StatusCode::BAD_REQUEST, "Wrong user name or password")
+            .into_response(),
+    }
+}
+
+/// `GET /api/user/info`
+pub async fn user_info(State(mock_api): State<Arc<MockApi>>, headers: HeaderMap) -> Response {
+    let Some(account) = authorization_token(&headers).and_then(|token| mock_api.account(token))
+    else {
+        return ApiResponse::error(StatusCode::UNAUTHORIZED).into_response();
+    };
+    ApiResponse::success(UserInfo {
+        buttons: account.buttons.clone(),
+        roles: account.roles.clone(),
+        user_id: account.user_id,
+        user_name: account.user_name.clone(),
+        email: account.email.clone(),
+        avatar: account.avatar.clone(),
+    })
+    .into_response()
+}
+
+/// `GET /api/user/list`
+pub async fn user_list(
+    State(mock_api): State<Arc<MockApi>>,
+    params: Result<Query<UserSearchParams>, QueryRejection>,
+) -> Response {
+    match params {
+        Ok(Query(params)) => ApiResponse::success(mock_api.users(&params)).into_response(),
+        Err(rejection) => bad_request(rejection.body_text()),
+    }
+}
+
+/// `GET /api/role/list`
+pub async fn role_list(
+    State(mock_api): State<Arc<MockApi>>,
+    params: Result<Query<RoleSearchParams>, QueryRejection>,
+) -> Response {
+    match params {
+        Ok(Query(params)) => ApiResponse::success(mock_api.roles(&params)).into_response(),
+        Err(rejection) => bad_request(rejection.body_text()),
+    }
+}
+
+/// `GET /api/v3/system/menus/simple`
+pub async fn menus(State(mock_api): State<Arc<MockApi>>) -> Response {
+    ApiResponse::success(mock_api.menus()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::{Body, to_bytes};
+    use axum::extract::Request;
+    use axum::routing::{get, post};
+    use http::{Method, header};
+    use tower::ServiceExt;
+
+    fn mock_api() -> Arc<MockApi> {
+        Arc::new(MockApi::load(|_| None).unwrap())
+    }
+
+    async fn call(request: Request) -> (StatusCode, serde_json::Value) {
+        let router = Router::new()
+            .route("/api/auth/login", post(login))
+            .route("/api/user/info", get(user_info))
+            .route("/api/user/list", get(user_list))
+            .route("/api/role/list", get(role_list))
+            .route("/api/v3/system/menus/simple", get(menus))
+            .with_state(mock_api());
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn get_request(uri: &str) -> Request {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn embedded_fixtures() {
+        let mock_api = mock_api();
+        assert!(
+            FIXTURES
+                .iter()
+                .all(|name| MockApi::embedded(name) != "null")
+        );
+        assert_eq!(mock_api.accounts.len(), 3);
+        assert!(mock_api.menus().is_array());
+    }
+
+    #[test]
+    fn override_fixture() {
+        let mock_api = MockApi::load(|name| {
+            (name == "roles.json").then(|| {
+                r#"[{"roleId":9,"roleName":"Auditor","roleCode":"R_AUDIT","description":"","enabled":true,"createTime":""}]"#.to_owned()
+            })
+        })
+        .unwrap();
+        let page = mock_api.roles(&RoleSearchParams::default());
+        assert_eq!(page.total, 1);
+        assert_eq!(page.records[0].role_code, "R_AUDIT");
+        assert!(MockApi::load(|name| (name == "users.json").then(|| "{}".to_owned())).is_err());
+    }
+
+    #[test]
+    fn search_users() {
+        let mock_api = mock_api();
+        let params = UserSearchParams {
+            user_gender: Some("2".to_owned()),
+            status: Some("1".to_owned()),
+            ..UserSearchParams::default()
+        };
+        let page = mock_api.users(&params);
+        assert!(page.total > 0);
+        assert!(
+            page.records
+                .iter()
+                .all(|user| user.user_gender == "女" && user.status == "1")
+        );
+        let params = UserSearchParams {
+            user_email: Some("@COMPANY.com".to_owned()),
+            current: Some(3),
+            size: Some(10),
+            ..UserSearchParams::default()
+        };
+        let page = mock_api.users(&params);
+        assert_eq!((page.total, page.records.len()), (25, 5));
+        assert_eq!(page.records[0].id, 21);
+    }
+
+    #[tokio::test]
+    async fn login_and_user_info() {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"userName":"Admin","password":"123456"}"#))
+            .unwrap();
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["code"], 200);
+        let token = body["data"]["token"].as_str().unwrap().to_owned();
+        let request = Request::builder()
+            .uri("/api/user/info")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        let (_, body) = call(request).await;
+        assert_eq!(body["data"]["userName"], "Admin");
+        assert_eq!(body["data"]["roles"][0], "R_ADMIN");
+        let (status, body) = call(get_request("/api/user/info")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], 401);
+    }
+
+    #[tokio::test]
+    async fn wrong_password() {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"userName":"Admin","password":"654321"}"#))
+            .unwrap();
+        let (status, body) = call(request).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["msg"], "Wrong user name or password");
+    }
+
+    #[tokio::test]
+    async fn lists() {
+        let (_, body) = call(get_request(
+            "/api/user/list?current=2&size=20&userName=&userPhone=&status=",
+        ))
+        .await;
+        assert_eq!(body["data"]["current"], 2);
+        assert_eq!(body["data"]["total"], 25);
+        assert_eq!(body["data"]["records"].as_array().unwrap().len(), 5);
+        let (_, body) = call(get_request("/api/role/list?enabled=false")).await;
+        assert_eq!(body["data"]["size"], crate::api::types::DEFAULT_PAGE_SIZE);
+        assert!(
+            body["data"]["records"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .all(|role| role["enabled"] == false)
+        );
+        let (status, body) = call(get_request("/api/user/list?current=first")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], 400);
+        let (_, body) = call(get_request("/api/v3/system/menus/simple")).await;
+        assert_eq!(body["data"][0]["name"], "Dashboard");
+    }
+}
@@ -0,0 +1,114 @@
+use crate::error_type;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+/// A pattern matched against the path of a request, relative to the root of
+/// the embedded assets and without the leading `/`.
+///
+/// A pattern is a glob by default, in which `*` doesn't cross a `/` and `**`
+/// matches any number of directories. Prefixing the pattern with `regex:`
+/// makes it a regular expression instead.
+///
+/// # Examples
+///
+/// ```
+/// use server::path_pattern::PathPattern;
+///
+/// let glob: PathPattern = "assets/**/*.js".parse().unwrap();
+/// assert!(glob.is_match("assets/chunks/index.js"));
+/// assert!(!glob.is_match("index.js"));
+///
+/// let regex: PathPattern = r"regex:^api(/|$)".parse().unwrap();
+/// assert!(regex.is_match("api/user/info"));
+/// ```
+#[derive(Clone)]
+pub enum PathPattern {
+    Glob(String, GlobMatcher),
+    Regex(Regex),
+}
+
+error_type!(InvalidPathPattern);
+
+const REGEX_PREFIX: &str = "regex:";
+
+impl PathPattern {
+    /// Checks whether the path matches the pattern.
+    pub fn is_match(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        match self {
+            PathPattern::Glob(_, glob) => glob.is_match(path),
+            PathPattern::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = InvalidPathPattern;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(regex) = s.strip_prefix(REGEX_PREFIX) {
+            return Regex::new(regex)
+                .map(PathPattern::Regex)
+                .map_err(|_| InvalidPathPattern { _inner: () });
+        }
+        let glob = s.trim_start_matches('/');
+        GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map(|matcher| PathPattern::Glob(glob.to_owned(), matcher.compile_matcher()))
+            .map_err(|_| InvalidPathPattern { _inner: () })
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathPattern::Glob(glob, _) => f.write_str(glob),
+            PathPattern::Regex(regex) => write!(f, "{REGEX_PREFIX}{}", regex.as_str()),
+        }
+    }
+}
+
+impl fmt::Debug for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(s: &str) -> PathPattern {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn glob() {
+        assert!(pattern("*.html").is_match("index.html"));
+        assert!(!pattern("*.html").is_match("docs/index.html"));
+        assert!(pattern("**/*.html").is_match("docs/index.html"));
+        assert!(pattern("/assets/**").is_match("/assets/fonts/a.woff2"));
+        assert!(pattern("assets/*.{js,css}").is_match("assets/index.css"));
+    }
+
+    #[test]
+    fn regex() {
+        assert!(pattern(r"regex:\.js$").is_match("assets/index.js"));
+        assert!(!pattern(r"regex:^index").is_match("assets/index.js"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!("regex:(".parse::<PathPattern>().is_err());
+        assert!("assets/[".parse::<PathPattern>().is_err());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(pattern("/assets/**").to_string(), "assets/**");
+        assert_eq!(pattern(r"regex:\.js$").to_string(), r"regex:\.js$");
+    }
+}
@@ -0,0 +1,299 @@
+use crate::etag::ETag;
+use crate::if_match::IfMatch;
+use crate::if_modified_since::IfModifiedSince;
+use crate::if_none_match::IfNoneMatch;
+use crate::if_unmodified_since::IfUnmodifiedSince;
+use headers::HeaderMapExt;
+use http::{HeaderMap, Method};
+use std::time::SystemTime;
+
+/// The outcome of evaluating the conditional request header fields against
+/// the selected representation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// Every precondition holds, the method should be performed. `Range` and
+    /// `If-Range` are evaluated afterwards by the caller.
+    Passed,
+    /// The client already has the current representation, respond with
+    /// `304 Not Modified`.
+    NotModified,
+    /// A precondition does not hold, respond with `412 Precondition Failed`.
+    Failed,
+}
+
+/// The conditional request header fields of a request, defined in
+/// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-13.1)
+///
+/// Header fields that can't be parsed are ignored, as the specification
+/// requires for the date based conditions.
+///
+/// # Examples
+///
+/// ```
+/// use http::{HeaderMap, HeaderValue, Method};
+/// use server::precondition::{Precondition, Preconditions};
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(http::header::IF_MATCH, HeaderValue::from_static("\"xyzzy\""));
+/// let preconditions = Preconditions::from_headers(&headers);
+///
+/// let etag = "\"r2d2\"".parse().unwrap();
+/// let outcome = preconditions.evaluate(&Method::GET, Some(&etag), None);
+/// assert_eq!(outcome, Precondition::Failed);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preconditions {
+    pub if_match: Option<IfMatch>,
+    pub if_unmodified_since: Option<IfUnmodifiedSince>,
+    pub if_none_match: Option<IfNoneMatch>,
+    pub if_modified_since: Option<IfModifiedSince>,
+}
+
+impl Preconditions {
+    /// Collect the conditional header fields from a request.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Preconditions {
+            if_match: headers.typed_get(),
+            if_unmodified_since: headers.typed_get(),
+            if_none_match: headers.typed_get(),
+            if_modified_since: headers.typed_get(),
+        }
+    }
+
+    /// Whether the request carries no conditional header field at all.
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none()
+            && self.if_unmodified_since.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+    }
+
+    /// Evaluate the preconditions for a current representation with the
+    /// given validators, following the order defined in
+    /// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-13.2.2):
+    ///
+    /// 1. `If-Match`, with the strong comparison, otherwise `If-Unmodified-Since`
+    /// 2. `If-None-Match`, with the weak comparison, otherwise
+    ///    `If-Modified-Since` for `GET` and `HEAD`
+    pub fn evaluate(
+        &self,
+        method: &Method,
+        etag: Option<&ETag>,
+        last_modified: Option<SystemTime>,
+    ) -> Precondition {
+        let is_get_or_head = method == Method::GET || method == Method::HEAD;
+
+        if let Some(if_match) = &self.if_match {
+            let passes = match etag {
+                Some(etag) => if_match.precondition_passes(etag),
+                None => if_match.is_any(),
+            };
+            if !passes {
+                return Precondition::Failed;
+            }
+        } else if let Some(if_unmodified_since) = &self.if_unmodified_since
+            && let Some(last_modified) = last_modified
+            && !if_unmodified_since.precondition_passes(last_modified)
+        {
+            return Precondition::Failed;
+        }
+
+        if let Some(if_none_match) = &self.if_none_match {
+            let passes = match etag {
+                Some(etag) => if_none_match.precondition_passes(etag),
+                None => !if_none_match.is_any(),
+            };
+            if !passes {
+                return if is_get_or_head {
+                    Precondition::NotModified
+                } else {
+                    Precondition::Failed
+                };
+            }
+        } else if is_get_or_head
+            && let Some(if_modified_since) = &self.if_modified_since
+            && let Some(last_modified) = last_modified
+            && !if_modified_since.is_modified(last_modified)
+        {
+            return Precondition::NotModified;
+        }
+
+        Precondition::Passed
+    }
+
+    /// When the selected representation passed the preconditions, finds
+    /// another representation of the same resource whose entity-tag is listed
+    /// in `If-None-Match`, so that a `304 Not Modified` carrying that
+    /// entity-tag lets the cache reuse its stored copy.
+    pub fn not_modified_variant<'a>(
+        &self,
+        method: &Method,
+        variants: &'a [ETag],
+    ) -> Option<&'a ETag> {
+        if method != Method::GET && method != Method::HEAD {
+            return None;
+        }
+        self.if_none_match.as_ref()?.first_match(variants)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn preconditions(values: &[(&'static str, &'static str)]) -> Preconditions {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.append(*name, value.parse().unwrap());
+        }
+        Preconditions::from_headers(&headers)
+    }
+
+    fn modified() -> SystemTime {
+        // Mon, 07 Nov 1994 08:48:37 GMT
+        SystemTime::UNIX_EPOCH + Duration::from_secs(784198117)
+    }
+
+    #[test]
+    fn empty() {
+        let etag = ETag::from_static("\"foo\"");
+        let preconditions = preconditions(&[]);
+        assert!(preconditions.is_empty());
+        assert_eq!(
+            preconditions.evaluate(&Method::GET, Some(&etag), Some(modified())),
+            Precondition::Passed
+        );
+    }
+
+    #[test]
+    fn if_match() {
+        let etag = ETag::from_static("\"foo\"");
+        let get =
+            |value| preconditions(&[("if-match", value)]).evaluate(&Method::GET, Some(&etag), None);
+        assert_eq!(get("\"foo\""), Precondition::Passed);
+        assert_eq!(get("\"bar\", \"foo\""), Precondition::Passed);
+        assert_eq!(get("*"), Precondition::Passed);
+        assert_eq!(get("\"bar\""), Precondition::Failed);
+        // If-Match uses the strong comparison
+        assert_eq!(get("W/\"foo\""), Precondition::Failed);
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        let etag = ETag::from_static("\"foo\"");
+        let preconditions = preconditions(&[
+            ("if-match", "\"foo\""),
+            ("if-unmodified-since", "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]);
+        assert_eq!(
+            preconditions.evaluate(&Method::GET, Some(&etag), Some(modified())),
+            Precondition::Passed
+        );
+    }
+
+    #[test]
+    fn if_unmodified_since() {
+        let get = |value| {
+            preconditions(&[("if-unmodified-since", value)]).evaluate(
+                &Method::GET,
+                None,
+                Some(modified()),
+            )
+        };
+        assert_eq!(get("Mon, 07 Nov 1994 08:48:37 GMT"), Precondition::Passed);
+        assert_eq!(get("Tue, 08 Nov 1994 08:48:37 GMT"), Precondition::Passed);
+        assert_eq!(get("Sun, 06 Nov 1994 08:49:37 GMT"), Precondition::Failed);
+        // Invalid dates are ignored
+        assert_eq!(get("yesterday"), Precondition::Passed);
+    }
+
+    #[test]
+    fn if_none_match() {
+        let etag = ETag::from_static("\"foo\"");
+        let evaluate = |method, value| {
+            preconditions(&[("if-none-match", value)]).evaluate(&method, Some(&etag), None)
+        };
+        assert_eq!(evaluate(Method::GET, "\"bar\""), Precondition::Passed);
+        assert_eq!(evaluate(Method::GET, "\"foo\""), Precondition::NotModified);
+        // If-None-Match uses the weak comparison
+        assert_eq!(
+            evaluate(Method::HEAD, "W/\"foo\""),
+            Precondition::NotModified
+        );
+        assert_eq!(evaluate(Method::GET, "*"), Precondition::NotModified);
+        assert_eq!(evaluate(Method::PUT, "\"foo\""), Precondition::Failed);
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let etag = ETag::from_static("\"foo\"");
+        let preconditions = preconditions(&[
+            ("if-none-match", "\"bar\""),
+            ("if-modified-since", "Mon, 07 Nov 1994 08:48:37 GMT"),
+        ]);
+        assert_eq!(
+            preconditions.evaluate(&Method::GET, Some(&etag), Some(modified())),
+            Precondition::Passed
+        );
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let evaluate = |method, value| {
+            preconditions(&[("if-modified-since", value)]).evaluate(&method, None, Some(modified()))
+        };
+        assert_eq!(
+            evaluate(Method::GET, "Mon, 07 Nov 1994 08:48:37 GMT"),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            evaluate(Method::GET, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            Precondition::Passed
+        );
+        // Only GET and HEAD are conditional on If-Modified-Since
+        assert_eq!(
+            evaluate(Method::POST, "Mon, 07 Nov 1994 08:48:37 GMT"),
+            Precondition::Passed
+        );
+        // Without a last modified date there is nothing to compare
+        assert_eq!(
+            preconditions(&[("if-modified-since", "Mon, 07 Nov 1994 08:48:37 GMT")]).evaluate(
+                &Method::GET,
+                None,
+                None
+            ),
+            Precondition::Passed
+        );
+    }
+
+    #[test]
+    fn not_modified_variant() {
+        let identity = ETag::from_static("\"foo\"");
+        let zstd = ETag::from_static("\"foo-zstd\"");
+        let variants = [identity.clone(), zstd.clone()];
+        let preconditions = preconditions(&[("if-none-match", "\"foo-zstd\"")]);
+        assert_eq!(
+            preconditions.evaluate(&Method::GET, Some(&identity), None),
+            Precondition::Passed
+        );
+        assert_eq!(
+            preconditions.not_modified_variant(&Method::GET, &variants),
+            Some(&zstd)
+        );
+        assert_eq!(
+            preconditions.not_modified_variant(&Method::PUT, &variants),
+            None
+        );
+    }
+
+    #[test]
+    fn if_match_fails_before_if_none_match() {
+        let etag = ETag::from_static("\"foo\"");
+        let preconditions = preconditions(&[("if-match", "\"bar\""), ("if-none-match", "\"foo\"")]);
+        assert_eq!(
+            preconditions.evaluate(&Method::GET, Some(&etag), None),
+            Precondition::Failed
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use crate::accept_encoding::AcceptEncoding;
+use crate::accept_ranges::AcceptRanges;
+use crate::byte_ranges::MultipartByteRanges;
+use crate::cache_control::CacheControl;
+use crate::cache_policy::CachePolicy;
+use crate::compression_policy::CompressionPolicy;
+use crate::content_encoding::ContentEncoding;
+use crate::content_range::ContentRange;
+use crate::csp_nonce::{CspNonce, NonceTemplate};
+use crate::etag::ETag;
+use crate::if_range::IfRange;
+use crate::last_modified::LastModified;
+use crate::mime_types::MimeTypes;
+use crate::precondition::{Precondition, Preconditions};
+use crate::range::Range;
+use crate::{Encoding, IntoQuality, QualityValue};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use dist::{Dist, ETagHeaderValue, Record};
+use embed_it::Entry;
+use headers::HeaderMapExt;
+use http::{HeaderMap, HeaderValue, Method, StatusCode};
+use std::time::SystemTime;
+use tracing::{debug, error, info};
+
+/// An encoded representation of a file.
+struct Representation {
+    content_encoding: ContentEncoding,
+    encoding: Encoding,
+    content: Bytes,
+    etag: ETag,
+}
+
+/// The headers and the contents of the response of a file, prepared at
+/// startup, so that a request only looks them up and copies them.
+pub struct PreparedFile {
+    path: &'static str,
+    content_type: String,
+    content_type_value: HeaderValue,
+    /// The original content first, then the compressed ones worth serving.
+    representations: Vec<Representation>,
+    /// The etags of the representations of every encoding, a cache may hold
+    /// any of them.
+    variants: Vec<ETag>,
+    last_modified: Option<SystemTime>,
+    cache_control: Option<CacheControl>,
+    /// The boundary of the `multipart/byteranges` of several ranges.
+    boundary: String,
+    /// With CSP nonces, the HTML document split at its `<script>` elements,
+    /// so that a response only joins the parts.
+    nonce_template: Option<NonceTemplate>,
+    supported_accept_encoding: AcceptEncoding,
+    /// Whether a compressed representation is worth serving, the response
+    /// doesn't need an `Accept-Encoding` otherwise.
+    compressible: bool,
+}
+
+impl PreparedFile {
+    /// Prepares the response of an embedded file. The type is guessed and the
+    /// etag computed at build time, this parses the etags, picks the encodings
+    /// by their compressed lengths and looks up the cache policy, once per
+    /// file at startup.
+    pub fn new(
+        record: &'static Record,
+        mime_types: &MimeTypes,
+        cache_policy: &CachePolicy,
+        compression_policy: &CompressionPolicy,
+        csp_nonce: bool,
+    ) -> Option<Self> {
+        let Some(Entry::File(file)) = Dist.get(record.path) else {
+            return None;
+        };
+        let path = record.path;
+        // A configured type takes precedence over the one guessed at build
+        // time.
+        let content_type = mime_types
+            .content_type(path)
+            .unwrap_or(record.content_type)
+            .to_owned();
+        let Ok(content_type_value) = HeaderValue::try_from(content_type.as_str()) else {
+            error!("The content-type of {path} couldn't to header value");
+            return None;
+        };
+        let etag_value = ETagHeaderValue {
+            value: record.etag.to_owned(),
+        };
+        let parse = |etag: String| match etag.parse::<ETag>() {
+            Ok(etag) => Some(etag),
+            Err(_) => {
+                error!("The etag {etag} of {path} is invalid");
+                None
+            }
+        };
+        // Each encoded representation has its own etag.
+        let variants = [
+            (Encoding::Identity, etag_value.value.clone()),
+            (Encoding::Brotli, etag_value.with_suffix("br")),
+            (Encoding::Zstd, etag_value.with_suffix("zstd")),
+            (Encoding::Gzip, etag_value.with_suffix("gzip")),
+            (Encoding::Deflate, etag_value.with_suffix("deflate")),
+        ]
+        .into_iter()
+        .map(|(encoding, etag)| Some((encoding, parse(etag)?)))
+        .collect::<Option<Vec<_>>>()?;
+        let etag_of = |encoding: &Encoding| {
+            variants
+                .iter()
+                .find(|(variant, _)| variant == encoding)
+                .map(|(_, etag)| etag.clone())
+        };
+        let mut representations = vec![Representation {
+            content_encoding: ContentEncoding::from(Encoding::Identity),
+            encoding: Encoding::Identity,
+            content: Bytes::from_static(file.content()),
+            etag: etag_of(&Encoding::Identity)?,
+        }];
+        // Of zstd, brotli, gzip and deflate, the better compressed zstd and
+        // brotli come first, gzip and deflate are for the clients and proxies
+        // without them. A representation which isn't clearly smaller isn't
+        // served, e.g. of an already compressed png or woff2, whose clients
+        // get the original content.
+        let length = file.encoded_length();
+        let mut supported = Vec::new();
+        for (encoding, encoded_length, quality) in [
+            (Encoding::Zstd, Some(length.zstd), 1000_u16),
+            (Encoding::Brotli, Some(length.brotli), 800),
+            (Encoding::Gzip, Some(length.gzip), 600),
+            (Encoding::Deflate, length.deflate, 400),
+        ] {
+            let Some(encoded_length) = encoded_length else {
+                continue;
+            };
+            if !compression_policy.pays_off(&content_type, length.identity, encoded_length) {
+                continue;
+            }
+            let content = match encoding {
+                Encoding::Zstd => file.zstd_content(),
+                Encoding::Brotli => file.brotli_content(),
+                Encoding::Gzip => file.gzip_content(),
+                _ => match &file.deflate_content().value {
+                    Some(deflate) => deflate.as_slice(),
+                    None => continue,
+                },
+            };
+            representations.push(Representation {
+                content_encoding: ContentEncoding::from(encoding.clone()),
+                etag: etag_of(&encoding)?,
+                encoding: encoding.clone(),
+                content: Bytes::from_static(content),
+            });
+            supported.push(QualityValue::new(encoding, quality.into_quality()));
+        }
+        let compressible = !supported.is_empty();
+        Some(Self {
+            path,
+            nonce_template: nonce_template(
+                &content_type,
+                Bytes::from_static(file.content()),
+                csp_nonce,
+            ),
+            content_type,
+            content_type_value,
+            representations,
+            variants: variants.into_iter().map(|(_, etag)| etag).collect(),
+            last_modified: file.last_modified().value,
+            cache_control: cache_policy.cache_control(path).cloned(),
+            boundary: record.etag.trim_matches('"').to_owned(),
+            supported_accept_encoding: supported.into_iter().collect(),
+            compressible,
+        })
+    }
+
+    /// Prepares the response of a content generated at startup, e.g. the
+    /// runtime config, or the `index.html` it is injected into. It has no
+    /// compressed representation and no modification time, and its etag is
+    /// computed from the content.
+    pub fn generated(
+        path: &'static str,
+        content: Bytes,
+        content_type: &str,
+        cache_policy: &CachePolicy,
+        csp_nonce: bool,
+    ) -> Option<Self> {
+        let Ok(content_type_value) = HeaderValue::try_from(content_type) else {
+            error!("The content-type of {path} couldn't to header value");
+            return None;
+        };
+        let hash = blake3::hash(&content).to_hex();
+        let Ok(etag) = format!("\"{hash}\"").parse::<ETag>() else {
+            error!("The etag of {path} is invalid");
+            return None;
+        };
+        Some(Self {
+            path,
+            content_type: content_type.to_owned(),
+            content_type_value,
+            nonce_template: nonce_template(content_type, content.clone(), csp_nonce),
+            representations: vec![Representation {
+                content_encoding: ContentEncoding::from(Encoding::Identity),
+                encoding: Encoding::Identity,
+                content,
+                etag: etag.clone(),
+            }],
+            variants: vec![etag],
+            last_modified: None,
+            cache_control: cache_policy.cache_control(path).cloned(),
+            boundary: hash.to_string(),
+            supported_accept_encoding: std::iter::empty().collect(),
+            compressible: false,
+        })
+    }
+
+    /// The path relative to `dist`.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The original content.
+    pub fn content(&self) -> &Bytes {
+        &self.representations[0].content
+    }
+
+    /// The representation of the negotiated encoding, or the original
+    /// content.
+    fn representation(&self, encoding: &Encoding) -> &Representation {
+        self.representations
+            .iter()
+            .find(|representation| representation.encoding == *encoding)
+            .unwrap_or(&self.representations[0])
+    }
+
+    /// The response to the request of the file, with the negotiation of the
+    /// encoding, the conditional requests and the range requests.
+    pub fn serve(
+        &self,
+        nonce: Option<&CspNonce>,
+        method: &Method,
+        headers: &HeaderMap,
+    ) -> Response {
+        let mut base_header = HeaderMap::new();
+        let path = self.path;
+        debug!("The content type is {}", self.content_type);
+        // A document with a nonce injected differs in each response, so it is
+        // neither compressed nor cached or revalidated, or the nonce of the
+        // cache wouldn't match the new CSP.
+        if let (Some(nonce), Some(template)) = (nonce, &self.nonce_template) {
+            debug!("Inject the nonce into the scripts of {path}");
+            base_header.insert(http::header::CONTENT_TYPE, self.content_type_value.clone());
+            base_header.typed_insert(CacheControl::no_store());
+            base_header.typed_insert(ContentEncoding::from(Encoding::Identity));
+            return (base_header, template.render(nonce)).into_response();
+        }
+        // A range applies to the original content, and only to a GET request.
+        let mut range = if method == Method::GET {
+            headers.typed_get::<Range>()
+        } else {
+            None
+        };
+        // The encoding is negotiated by RFC 9110: q=0 refuses an encoding, `*`
+        // matches the ones not listed, the highest weight of the client wins
+        // and the preference of the server breaks ties. Without
+        // `Accept-Encoding` the original content is served, and when the
+        // client refuses it and accepts none of the encodings, the answer is
+        // `406 Not Acceptable`.
+        let encoding = match headers.typed_get::<AcceptEncoding>() {
+            // A range request of a client accepting the original content uses
+            // it.
+            Some(accept_encoding)
+                if range.is_some() && accept_encoding.accepts(&Encoding::Identity) =>
+            {
+                Encoding::Identity
+            }
+            Some(accept_encoding) => {
+                match accept_encoding.negotiate(&self.supported_accept_encoding) {
+                    Some(encoding) => encoding,
+                    None => {
+                        info!("None of the encodings of {path} is acceptable");
+                        base_header.insert(
+                            http::header::VARY,
+                            HeaderValue::from_static("accept-encoding"),
+                        );
+                        if self.compressible {
+                            base_header.typed_insert(self.supported_accept_encoding.clone());
+                        }
+                        return (StatusCode::NOT_ACCEPTABLE, base_header).into_response();
+                    }
+                }
+            }
+            None => Encoding::Identity,
+        };
+        // A client refusing the original content gets the whole compressed
+        // content, the range is ignored.
+        if encoding != Encoding::Identity {
+            range = None;
+        }
+        let representation = self.representation(&encoding);
+        let etag = &representation.etag;
+        let last_modified = self.last_modified;
+        // A `304` carries the ETag, Last-Modified, Cache-Control and Vary too.
+        base_header.typed_insert(etag.clone());
+        if let Some(last_modified) = last_modified {
+            base_header.typed_insert(LastModified::from(last_modified));
+        }
+        if let Some(cache_control) = &self.cache_control {
+            debug!("The cache control of {path} is {cache_control}");
+            base_header.typed_insert(cache_control.clone());
+        }
+        base_header.insert(
+            http::header::VARY,
+            HeaderValue::from_static("accept-encoding"),
+        );
+        // If-Match, If-Unmodified-Since, If-None-Match and If-Modified-Since
+        // are evaluated in the order of RFC 9110.
+        let preconditions = Preconditions::from_headers(headers);
+        match preconditions.evaluate(method, Some(etag), last_modified) {
+            Precondition::NotModified => {
+                info!("The {path} has not been modified");
+                return (StatusCode::NOT_MODIFIED, base_header).into_response();
+            }
+            Precondition::Failed => {
+                info!("The precondition of {path} has failed");
+                return (StatusCode::PRECONDITION_FAILED, base_header).into_response();
+            }
+            Precondition::Passed => {}
+        }
+        // A cache may hold the representation of another encoding, when
+        // If-None-Match matches any of them, the `304` tells which one.
+        if let Some(variant) = preconditions.not_modified_variant(method, &self.variants) {
+            info!("The {path} has not been modified, the cached representation is {variant:?}");
+            base_header.typed_insert(variant.clone());
+            return (StatusCode::NOT_MODIFIED, base_header).into_response();
+        }
+        base_header.insert(http::header::CONTENT_TYPE, self.content_type_value.clone());
+        base_header.typed_insert(AcceptRanges::bytes());
+        base_header.typed_insert(representation.content_encoding.clone());
+        // The whole content is served when the etag of If-Range differs, the
+        // content of the client is stale, or when the range is malformed.
+        let if_range_passes = match headers.typed_get::<IfRange>() {
+            Some(if_range) => !if_range.is_modified(Some(etag), last_modified),
+            None => !headers.contains_key(http::header::IF_RANGE),
+        };
+        if if_range_passes
+            && let Some(range) = range
+            && let Ok(ranges) = range.satisfiable_ranges(representation.content.len() as u64)
+        {
+            return partial_content(
+                base_header,
+                representation.content.clone(),
+                &ranges,
+                &self.content_type,
+                &self.boundary,
+            );
+        }
+        if self.compressible {
+            base_header.typed_insert(self.supported_accept_encoding.clone());
+        }
+        (base_header, representation.content.clone()).into_response()
+    }
+}
+
+/// With CSP nonces, the HTML document split at its `<script>` elements.
+fn nonce_template(content_type: &str, content: Bytes, csp_nonce: bool) -> Option<NonceTemplate> {
+    let is_html = content_type
+        .split(';')
+        .next()
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case("text/html"));
+    if csp_nonce && is_html {
+        NonceTemplate::new(content)
+    } else {
+        None
+    }
+}
+
+/// A `206 Partial Content` of the satisfiable ranges, or a `416 Range Not
+/// Satisfiable` when there is none.
+fn partial_content(
+    mut base_header: HeaderMap,
+    content: Bytes,
+    ranges: &[(u64, u64)],
+    content_type: &str,
+    boundary: &str,
+) -> Response {
+    let complete_length = content.len() as u64;
+    match ranges {
+        [] => {
+            info!("The range is not satisfiable");
+            base_header.remove(http::header::CONTENT_TYPE);
+            base_header.typed_insert(ContentRange::unsatisfied_bytes(complete_length));
+            (base_header, StatusCode::RANGE_NOT_SATISFIABLE).into_response()
+        }
+        [(first, last)] => {
+            let Ok(content_range) = ContentRange::bytes(*first..=*last, complete_length) else {
+                error!("The range {first}-{last} couldn't to content range");
+                return (base_header, StatusCode::INTERNAL_SERVER_ERROR).into_response();
+            };
+            base_header.typed_insert(content_range);
+            let body = content.slice(*first as usize..=*last as usize);
+            (StatusCode::PARTIAL_CONTENT, base_header, body).into_response()
+        }
+        ranges => {
+            // Several ranges are answered with a `multipart/byteranges`, each
+            // part with its own Content-Type and Content-Range.
+            let multipart = MultipartByteRanges::new(boundary, content_type);
+            let Some(multipart_content_type) = multipart.content_type() else {
+                error!("The boundary {boundary} couldn't to header value");
+                return (base_header, StatusCode::INTERNAL_SERVER_ERROR).into_response();
+            };
+            base_header.insert(http::header::CONTENT_TYPE, multipart_content_type);
+            let body = multipart.body(&content, ranges);
+            (StatusCode::PARTIAL_CONTENT, base_header, body).into_response()
+        }
+    }
+}
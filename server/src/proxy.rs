@@ -0,0 +1,668 @@
+use crate::error_type;
+use axum::body::{Body, HttpBody};
+use axum::extract::{ConnectInfo, Request};
+use axum::response::{IntoResponse, Response};
+use http::header::{self, HeaderName};
+use http::uri::{Authority, PathAndQuery, Scheme};
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, Version};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the response headers of the upstream.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many times an idempotent request is retried on another attempt.
+pub const DEFAULT_RETRIES: u32 = 2;
+
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// The hop-by-hop headers of
+/// [RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-7.6.1),
+/// which are meaningful for a single connection and never forwarded.
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Forwards the requests below a path prefix to an upstream server.
+///
+/// The textual form is `<prefix>=<url>`, like the `server.proxy` option of
+/// Vite. The path of the request is kept and appended to the path of the
+/// url, only `http` upstreams are supported.
+///
+/// # Examples
+///
+/// ```
+/// use server::proxy::ProxyRule;
+///
+/// let rule: ProxyRule = "/api=http://backend:9000".parse().unwrap();
+/// assert_eq!(rule.prefix(), "/api");
+/// assert!("/api=https://backend".parse::<ProxyRule>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyRule {
+    prefix: String,
+    upstream: Uri,
+}
+
+error_type!(InvalidProxyRule);
+
+impl ProxyRule {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+}
+
+impl FromStr for ProxyRule {
+    type Err = InvalidProxyRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidProxyRule { _inner: () };
+        let (prefix, upstream) = s.split_once('=').ok_or_else(invalid)?;
+        let prefix = prefix.trim().trim_end_matches('/');
+        if !prefix.starts_with('/') || prefix.contains(['?', '#', '*', '{', '}']) {
+            return Err(invalid());
+        }
+        let upstream = upstream.trim().parse::<Uri>().map_err(|_| invalid())?;
+        if upstream.scheme() != Some(&Scheme::HTTP)
+            || upstream.authority().is_none()
+            || upstream.query().is_some()
+        {
+            return Err(invalid());
+        }
+        Ok(ProxyRule {
+            prefix: prefix.to_owned(),
+            upstream,
+        })
+    }
+}
+
+/// An upstream server and whether the last health check passed.
+#[derive(Debug)]
+pub struct Upstream {
+    authority: Authority,
+    path: String,
+    healthy: AtomicBool,
+}
+
+impl Upstream {
+    fn new(uri: &Uri) -> Self {
+        Upstream {
+            authority: uri
+                .authority()
+                .expect("the upstream has an authority")
+                .clone(),
+            path: uri.path().trim_end_matches('/').to_owned(),
+            healthy: AtomicBool::new(true),
+        }
+    }
+
+    pub fn authority(&self) -> &Authority {
+        &self.authority
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// The url of the request on this upstream.
+    fn uri(&self, path_and_query: &str) -> Option<Uri> {
+        let path_and_query = format!("{}{path_and_query}", self.path);
+        Uri::builder()
+            .scheme(Scheme::HTTP)
+            .authority(self.authority.clone())
+            .path_and_query(PathAndQuery::from_str(&path_and_query).ok()?)
+            .build()
+            .ok()
+    }
+}
+
+/// The upstreams of a path prefix, which are picked in turn.
+#[derive(Debug)]
+pub struct ProxyRoute {
+    prefix: String,
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+}
+
+impl ProxyRoute {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn upstreams(&self) -> &[Upstream] {
+        &self.upstreams
+    }
+
+    /// Whether the path is the prefix or below it.
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// The healthy upstreams in turn, starting from the next one.
+    fn healthy_upstreams(&self) -> Vec<&Upstream> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.upstreams.len();
+        (0..count)
+            .map(|i| &self.upstreams[(start + i) % count])
+            .filter(|upstream| upstream.is_healthy())
+            .collect()
+    }
+}
+
+/// Requests `GET <upstream><path>` periodically, an upstream that doesn't
+/// answer with a success or a redirection in time gets no requests until
+/// it recovers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub path: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyOptions {
+    pub connect_timeout: Duration,
+    pub timeout: Duration,
+    pub retries: u32,
+    /// Sends the authority of the upstream as `Host`, like the
+    /// `changeOrigin` option of Vite. Otherwise the `Host` of the client is
+    /// kept.
+    pub change_origin: bool,
+    pub health_check: Option<HealthCheck>,
+}
+
+impl Default for ProxyOptions {
+    fn default() -> Self {
+        ProxyOptions {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            change_origin: true,
+            health_check: None,
+        }
+    }
+}
+
+/// A reverse proxy streaming the requests and the responses between the
+/// clients and the upstreams.
+///
+/// The proxy removes the hop-by-hop headers, appends the address of the
+/// client to `X-Forwarded-For`, and sets `X-Forwarded-Host` and
+/// `X-Forwarded-Proto` unless a proxy in front has set them. A request whose
+/// response headers don't arrive in time gets `504 Gateway Timeout`, and an
+/// upstream that can't be reached `502 Bad Gateway`. Only the requests that
+/// can safely be sent again, the ones with an idempotent method and without a
+/// body, are retried on the next upstream.
+#[derive(Debug)]
+pub struct Proxy {
+    routes: Vec<ProxyRoute>,
+    options: ProxyOptions,
+    client: Client<HttpConnector, Body>,
+}
+
+impl Proxy {
+    /// The rules with the same prefix share their upstreams.
+    pub fn new(rules: Vec<ProxyRule>, options: ProxyOptions) -> Self {
+        let mut routes: Vec<ProxyRoute> = Vec::new();
+        for rule in rules {
+            let upstream = Upstream::new(&rule.upstream);
+            match routes.iter_mut().find(|route| route.prefix == rule.prefix) {
+                Some(route) => route.upstreams.push(upstream),
+                None => routes.push(ProxyRoute {
+                    prefix: rule.prefix,
+                    upstreams: vec![upstream],
+                    next: AtomicUsize::new(0),
+                }),
+            }
+        }
+        // The longest prefix is matched first
+        routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(options.connect_timeout));
+        let client = Client::builder(TokioExecutor::new()).build(connector);
+        Proxy {
+            routes,
+            options,
+            client,
+        }
+    }
+
+    pub fn routes(&self) -> &[ProxyRoute] {
+        &self.routes
+    }
+
+    pub fn options(&self) -> &ProxyOptions {
+        &self.options
+    }
+
+    /// The route of the longest prefix matching the path.
+    pub fn route(&self, path: &str) -> Option<&ProxyRoute> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+
+    /// Forwards the request to an upstream of its route.
+    pub async fn forward(&self, request: Request) -> Response {
+        let Some(route) = self.route(request.uri().path()) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let upstreams = route.healthy_upstreams();
+        if upstreams.is_empty() {
+            warn!("None of the upstreams of {} is healthy", route.prefix);
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+        let (parts, body) = request.into_parts();
+        let path_and_query = parts
+            .uri
+            .path_and_query()
+            .map_or("/", PathAndQuery::as_str)
+            .to_owned();
+        let client = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let mut headers = parts.headers;
+        let host = headers.get(header::HOST).cloned();
+        remove_hop_by_hop(&mut headers);
+        forwarded_headers(&mut headers, client, host.as_ref());
+        let retryable = is_idempotent(&parts.method) && body.size_hint().exact() == Some(0);
+        let attempts = if retryable {
+            self.options.retries as usize + 1
+        } else {
+            1
+        };
+        let mut body = Some(body);
+        let mut status = StatusCode::BAD_GATEWAY;
+        for upstream in upstreams.iter().cycle().take(attempts) {
+            let Some(uri) = upstream.uri(&path_and_query) else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+            let mut request = Request::new(body.take().unwrap_or_default());
+            *request.method_mut() = parts.method.clone();
+            *request.uri_mut() = uri;
+            *request.version_mut() = Version::HTTP_11;
+            *request.headers_mut() = headers.clone();
+            if self.options.change_origin
+                && let Ok(authority) = HeaderValue::try_from(upstream.authority.as_str())
+            {
+                request.headers_mut().insert(header::HOST, authority);
+            }
+            debug!(
+                "Forward {} {} to {}",
+                parts.method, path_and_query, upstream.authority
+            );
+            match tokio::time::timeout(self.options.timeout, self.client.request(request)).await {
+                Ok(Ok(response)) => {
+                    let (mut parts, body) = response.into_parts();
+                    remove_hop_by_hop(&mut parts.headers);
+                    // The version of the connection to the client is kept
+                    parts.version = Version::HTTP_11;
+                    return Response::from_parts(parts, Body::new(body));
+                }
+                Ok(Err(error)) => {
+                    warn!("The upstream {} failed: {error}", upstream.authority);
+                    status = StatusCode::BAD_GATEWAY;
+                }
+                Err(_) => {
+                    warn!("The upstream {} timed out", upstream.authority);
+                    status = StatusCode::GATEWAY_TIMEOUT;
+                }
+            }
+        }
+        status.into_response()
+    }
+
+    /// Checks every upstream once, it is a no-op without a [`HealthCheck`].
+    pub async fn check_health(&self) {
+        let Some(health_check) = &self.options.health_check else {
+            return;
+        };
+        for upstream in self.routes.iter().flat_map(|route| &route.upstreams) {
+            let healthy = match upstream.uri(&health_check.path) {
+                Some(uri) => {
+                    let mut request = Request::new(Body::empty());
+                    *request.uri_mut() = uri;
+                    matches!(
+                        tokio::time::timeout(health_check.timeout, self.client.request(request)).await,
+                        Ok(Ok(response))
+                            if response.status().is_success() || response.status().is_redirection()
+                    )
+                }
+                None => false,
+            };
+            if upstream.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                if healthy {
+                    info!("The upstream {} is healthy again", upstream.authority);
+                } else {
+                    warn!("The upstream {} is unhealthy", upstream.authority);
+                }
+            }
+        }
+    }
+
+    /// Checks the health of the upstreams in the background, `None` without a
+    /// [`HealthCheck`].
+    pub fn spawn_health_checks(self: &Arc<Self>) -> Option<JoinHandle<()>> {
+        let interval = self.options.health_check.as_ref()?.interval;
+        let proxy = Arc::clone(self);
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                proxy.check_health().await;
+            }
+        }))
+    }
+}
+
+/// The idempotent methods of
+/// [RFC 9110](https://datatracker.ietf.org/doc/html/rfc9110#section-9.2.2).
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// Removes the hop-by-hop headers, including the ones listed in `Connection`.
+pub(crate) fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_str(name.trim()).ok())
+        .collect::<Vec<_>>();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
+
+pub(crate) fn forwarded_headers(
+    headers: &mut HeaderMap,
+    client: Option<std::net::IpAddr>,
+    host: Option<&HeaderValue>,
+) {
+    if let Some(client) = client {
+        let forwarded_for = match headers
+            .get(X_FORWARDED_FOR)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(forwarded_for) => format!("{forwarded_for}, {client}"),
+            None => client.to_string(),
+        };
+        if let Ok(value) = HeaderValue::try_from(forwarded_for) {
+            headers.insert(X_FORWARDED_FOR, value);
+        }
+    }
+    if let Some(host) = host
+        && !headers.contains_key(X_FORWARDED_HOST)
+    {
+        headers.insert(X_FORWARDED_HOST, host.clone());
+    }
+    if !headers.contains_key(X_FORWARDED_PROTO) {
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static("http"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::to_bytes;
+    use axum::routing::{any, get};
+    use std::net::Ipv4Addr;
+    use tokio::net::TcpListener;
+
+    /// A stub upstream echoing the request.
+    async fn stub() -> SocketAddr {
+        let router = Router::new()
+            .route(
+                "/{*path}",
+                any(|request: Request| async move {
+                    let headers = request.headers();
+                    let header = |name: &str| {
+                        headers
+                            .get(name)
+                            .and_then(|value| value.to_str().ok())
+                            .unwrap_or("-")
+                            .to_owned()
+                    };
+                    let line = format!(
+                        "{} {} host={} xff={} xfh={} proto={} connection={}\n",
+                        request.method(),
+                        request.uri(),
+                        header("host"),
+                        header("x-forwarded-for"),
+                        header("x-forwarded-host"),
+                        header("x-forwarded-proto"),
+                        header("x-hop"),
+                    );
+                    let body = to_bytes(request.into_body(), usize::MAX).await.unwrap();
+                    let mut response = (line + std::str::from_utf8(&body).unwrap()).into_response();
+                    response
+                        .headers_mut()
+                        .insert(header::CONNECTION, HeaderValue::from_static("x-internal"));
+                    response
+                        .headers_mut()
+                        .insert("x-internal", HeaderValue::from_static("secret"));
+                    response
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "slow"
+                }),
+            )
+            .route("/health", get(|| async { "ok" }));
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    /// An address nothing listens on.
+    async fn closed() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn rule(prefix: &str, addr: SocketAddr) -> ProxyRule {
+        format!("{prefix}=http://{addr}").parse().unwrap()
+    }
+
+    fn request(method: Method, uri: &str, body: &'static str) -> Request {
+        let mut request = Request::new(Body::from(body));
+        *request.method_mut() = method;
+        *request.uri_mut() = uri.parse().unwrap();
+        request
+            .headers_mut()
+            .insert(header::HOST, HeaderValue::from_static("admin.example.com"));
+        request
+            .headers_mut()
+            .insert(header::CONNECTION, HeaderValue::from_static("x-hop"));
+        request
+            .headers_mut()
+            .insert("x-hop", HeaderValue::from_static("dropped"));
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([192, 168, 1, 7], 50000))));
+        request
+    }
+
+    async fn text(response: Response) -> String {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn rules() {
+        let rule: ProxyRule = "/api/=http://backend:9000/v1/".parse().unwrap();
+        assert_eq!(rule.prefix(), "/api");
+        let upstream = Upstream::new(rule.upstream());
+        assert_eq!(
+            upstream.uri("/api/user/list?current=1").unwrap(),
+            "http://backend:9000/v1/api/user/list?current=1"
+        );
+        assert!("api=http://backend".parse::<ProxyRule>().is_err());
+        assert!("/api=backend:9000".parse::<ProxyRule>().is_err());
+        assert!("/api=http://backend?x=1".parse::<ProxyRule>().is_err());
+        assert!("/api".parse::<ProxyRule>().is_err());
+    }
+
+    #[test]
+    fn longest_prefix() {
+        let proxy = Proxy::new(
+            vec![
+                "/api=http://a".parse().unwrap(),
+                "/api/v3=http://b".parse().unwrap(),
+            ],
+            ProxyOptions::default(),
+        );
+        assert_eq!(proxy.route("/api/v3/menus").unwrap().prefix(), "/api/v3");
+        assert_eq!(proxy.route("/api/v30").unwrap().prefix(), "/api");
+        assert_eq!(proxy.route("/api").unwrap().prefix(), "/api");
+        assert!(proxy.route("/apis").is_none());
+    }
+
+    #[tokio::test]
+    async fn forward() {
+        let addr = stub().await;
+        let proxy = Proxy::new(vec![rule("/api", addr)], ProxyOptions::default());
+        let response = proxy
+            .forward(request(
+                Method::POST,
+                "/api/auth/login?from=ui",
+                "{\"userName\":\"Super\"}",
+            ))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key("x-internal"));
+        assert!(!response.headers().contains_key(header::CONNECTION));
+        assert_eq!(
+            text(response).await,
+            format!(
+                "POST /api/auth/login?from=ui host={addr} xff=192.168.1.7 xfh=admin.example.com proto=http connection=-\n{{\"userName\":\"Super\"}}"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn preserve_host() {
+        let addr = stub().await;
+        let options = ProxyOptions {
+            change_origin: false,
+            ..ProxyOptions::default()
+        };
+        let proxy = Proxy::new(vec![rule("/api", addr)], options);
+        let mut request = request(Method::GET, "/api/user/info", "");
+        request
+            .headers_mut()
+            .insert(X_FORWARDED_FOR, HeaderValue::from_static("203.0.113.9"));
+        let response = proxy.forward(request).await;
+        assert!(
+            text(response).await.starts_with(
+                "GET /api/user/info host=admin.example.com xff=203.0.113.9, 192.168.1.7"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let addr = stub().await;
+        let options = ProxyOptions {
+            timeout: Duration::from_millis(100),
+            retries: 0,
+            ..ProxyOptions::default()
+        };
+        let proxy = Proxy::new(vec![rule("/slow", addr)], options);
+        let response = proxy.forward(request(Method::GET, "/slow", "")).await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn retry_idempotent() {
+        let (down, up) = (closed().await, stub().await);
+        let proxy = Arc::new(Proxy::new(
+            vec![rule("/api", down), rule("/api", up)],
+            ProxyOptions::default(),
+        ));
+        assert_eq!(proxy.routes()[0].upstreams().len(), 2);
+        // The first attempt goes to the closed port
+        let response = proxy
+            .forward(request(Method::GET, "/api/role/list", ""))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // A request with a body is never sent twice
+        proxy.routes()[0].next.store(0, Ordering::Relaxed);
+        let response = proxy
+            .forward(request(Method::POST, "/api/role", "{}"))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn health_check() {
+        let (down, up) = (closed().await, stub().await);
+        let options = ProxyOptions {
+            retries: 0,
+            health_check: Some(HealthCheck {
+                path: "/health".to_owned(),
+                interval: Duration::from_secs(60),
+                timeout: Duration::from_secs(1),
+            }),
+            ..ProxyOptions::default()
+        };
+        let proxy = Proxy::new(
+            vec![rule("/api", down), rule("/api", up), rule("/down", down)],
+            options,
+        );
+        proxy.check_health().await;
+        let healthy = proxy.routes()[1]
+            .upstreams()
+            .iter()
+            .map(Upstream::is_healthy)
+            .collect::<Vec<_>>();
+        assert_eq!(healthy, [false, true]);
+        // Only the healthy upstream gets the requests, even without retries
+        for _ in 0..2 {
+            let response = proxy
+                .forward(request(Method::POST, "/api/user", "{}"))
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = proxy.forward(request(Method::GET, "/down", "")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
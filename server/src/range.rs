@@ -0,0 +1,284 @@
+use crate::{derive_header, error_type};
+use http::HeaderValue;
+use std::ops::{Bound, RangeBounds};
+
+/// `Range` header, defined in [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-14.2)
+///
+/// The "Range" header field on a GET request modifies the method
+/// semantics to request transfer of only one or more subranges of the
+/// selected representation data, rather than the entire selected
+/// representation data.
+///
+/// A server MUST ignore a Range header field received with a request
+/// method that is unrecognized or for which range handling is not
+/// defined, and it MAY ignore a Range header field whose syntax is
+/// invalid. Because of that, decoding never fails: the syntax is checked
+/// when the ranges are resolved against a representation.
+///
+/// # ABNF
+///
+/// ```text
+/// Range = ranges-specifier
+/// ranges-specifier = range-unit "=" range-set
+/// range-set = 1#range-spec
+/// range-spec = int-range / suffix-range / other-range
+///
+/// int-range = first-pos "-" [ last-pos ]
+/// suffix-range = "-" suffix-length
+/// ```
+///
+/// # Example values
+///
+/// * `bytes=1000-`
+/// * `bytes=-2000`
+/// * `bytes=0-1,30-40`
+/// * `bytes=0-10,20-90,-100`
+///
+/// # Examples
+///
+/// ```
+/// use server::range::Range;
+///
+/// let range = Range::bytes(0..1234).unwrap();
+/// assert_eq!(range.satisfiable_ranges(2000).unwrap(), vec![(0, 1233)]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range(HeaderValue);
+
+derive_header! {
+    Range(_),
+    name: RANGE
+}
+
+error_type!(InvalidRange);
+
+/// A single `range-spec` of a `bytes` range set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRangeSpec {
+    /// `first-pos "-" last-pos`
+    FromTo(u64, u64),
+    /// `first-pos "-"`
+    AllFrom(u64),
+    /// `"-" suffix-length`
+    Last(u64),
+}
+
+impl Range {
+    /// Creates a `Range` header from bounds.
+    pub fn bytes(bounds: impl RangeBounds<u64>) -> Result<Self, InvalidRange> {
+        let v = match (bounds.start_bound(), bounds.end_bound()) {
+            (Bound::Included(start), Bound::Included(end)) => format!("bytes={start}-{end}"),
+            (Bound::Included(start), Bound::Excluded(&end)) if end > 0 => {
+                format!("bytes={start}-{}", end - 1)
+            }
+            (Bound::Included(start), Bound::Unbounded) => format!("bytes={start}-"),
+            _ => return Err(InvalidRange { _inner: () }),
+        };
+
+        HeaderValue::try_from(v)
+            .map(Range)
+            .map_err(|_| InvalidRange { _inner: () })
+    }
+
+    /// Parses the `bytes` range set.
+    ///
+    /// Returns an error if the unit is not `bytes` or if any of the
+    /// range specs is syntactically invalid, in which case the whole header
+    /// should be ignored.
+    pub fn byte_range_specs(&self) -> Result<Vec<ByteRangeSpec>, InvalidRange> {
+        let invalid = || InvalidRange { _inner: () };
+        let s = self.0.to_str().map_err(|_| invalid())?;
+        let (unit, set) = s.split_once('=').ok_or_else(invalid)?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return Err(invalid());
+        }
+        let specs = set
+            .split(',')
+            .map(str::trim)
+            // A recipient MUST accept empty list elements
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| spec.parse::<ByteRangeSpec>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if specs.is_empty() {
+            return Err(invalid());
+        }
+        Ok(specs)
+    }
+
+    /// Resolves the range set against a representation of `complete_length`
+    /// bytes and returns the satisfiable end-inclusive `(first, last)` ranges.
+    ///
+    /// Overlapping or adjacent ranges are coalesced and returned in ascending
+    /// order, so the total amount of data never exceeds the representation.
+    /// An empty vector means the set is unsatisfiable and the server should
+    /// answer `416 Range Not Satisfiable`.
+    pub fn satisfiable_ranges(
+        &self,
+        complete_length: u64,
+    ) -> Result<Vec<(u64, u64)>, InvalidRange> {
+        let mut ranges = self
+            .byte_range_specs()?
+            .iter()
+            .filter_map(|spec| spec.to_satisfiable_range(complete_length))
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        let mut coalesced: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for (first, last) in ranges {
+            match coalesced.last_mut() {
+                Some((_, prev_last)) if first <= prev_last.saturating_add(1) => {
+                    *prev_last = (*prev_last).max(last);
+                }
+                _ => coalesced.push((first, last)),
+            }
+        }
+        Ok(coalesced)
+    }
+}
+
+impl ByteRangeSpec {
+    /// Given the full length of the representation, normalizes the byte range
+    /// into a satisfiable end-inclusive `(first, last)` range.
+    ///
+    /// The resulting range is guaranteed to be within the bounds of
+    /// `0 <= first <= last < full_length`. `None` is returned when the range is
+    /// unsatisfiable, following
+    /// [RFC9110](https://datatracker.ietf.org/doc/html/rfc9110#section-14.1.1).
+    pub fn to_satisfiable_range(&self, full_length: u64) -> Option<(u64, u64)> {
+        if full_length == 0 {
+            return None;
+        }
+        match *self {
+            ByteRangeSpec::FromTo(first, last) if first < full_length => {
+                Some((first, last.min(full_length - 1)))
+            }
+            ByteRangeSpec::AllFrom(first) if first < full_length => Some((first, full_length - 1)),
+            ByteRangeSpec::Last(suffix) if suffix > 0 => {
+                Some((full_length.saturating_sub(suffix), full_length - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for ByteRangeSpec {
+    type Err = InvalidRange;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidRange { _inner: () };
+        let (first, last) = spec.split_once('-').ok_or_else(invalid)?;
+        let parse = |pos: &str| -> Result<u64, InvalidRange> {
+            if pos.is_empty() || !pos.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            pos.parse().map_err(|_| invalid())
+        };
+        match (first.trim(), last.trim()) {
+            ("", suffix) => parse(suffix).map(ByteRangeSpec::Last),
+            (first, "") => parse(first).map(ByteRangeSpec::AllFrom),
+            (first, last) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if last < first {
+                    return Err(invalid());
+                }
+                Ok(ByteRangeSpec::FromTo(first, last))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+
+    fn range(value: &'static str) -> Range {
+        let mut map = ::http::HeaderMap::new();
+        map.insert(::http::header::RANGE, HeaderValue::from_static(value));
+        map.typed_get().unwrap()
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(
+            range("bytes=0-10, 20-, -30").byte_range_specs().unwrap(),
+            vec![
+                ByteRangeSpec::FromTo(0, 10),
+                ByteRangeSpec::AllFrom(20),
+                ByteRangeSpec::Last(30)
+            ]
+        );
+        assert_eq!(
+            range("Bytes = 5-6,,").byte_range_specs().unwrap(),
+            vec![ByteRangeSpec::FromTo(5, 6)]
+        );
+    }
+
+    #[test]
+    fn invalid_specs() {
+        assert!(range("items=0-10").byte_range_specs().is_err());
+        assert!(range("bytes=").byte_range_specs().is_err());
+        assert!(range("bytes=10-5").byte_range_specs().is_err());
+        assert!(range("bytes=-").byte_range_specs().is_err());
+        assert!(range("bytes=a-b").byte_range_specs().is_err());
+        assert!(range("bytes=+1-2").byte_range_specs().is_err());
+        assert!(range("bytes=0-10").byte_range_specs().is_ok());
+    }
+
+    #[test]
+    fn satisfiable() {
+        assert_eq!(
+            range("bytes=0-99").satisfiable_ranges(50).unwrap(),
+            vec![(0, 49)]
+        );
+        assert_eq!(
+            range("bytes=-10").satisfiable_ranges(50).unwrap(),
+            vec![(40, 49)]
+        );
+        assert_eq!(
+            range("bytes=-100").satisfiable_ranges(50).unwrap(),
+            vec![(0, 49)]
+        );
+        assert_eq!(
+            range("bytes=45-").satisfiable_ranges(50).unwrap(),
+            vec![(45, 49)]
+        );
+        assert_eq!(
+            range("bytes=40-45, 0-4").satisfiable_ranges(50).unwrap(),
+            vec![(0, 4), (40, 45)]
+        );
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert!(
+            range("bytes=50-")
+                .satisfiable_ranges(50)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(range("bytes=-0").satisfiable_ranges(50).unwrap().is_empty());
+        assert!(range("bytes=0-0").satisfiable_ranges(0).unwrap().is_empty());
+        assert_eq!(
+            range("bytes=60-70, 10-20").satisfiable_ranges(50).unwrap(),
+            vec![(10, 20)]
+        );
+    }
+
+    #[test]
+    fn coalesce() {
+        assert_eq!(
+            range("bytes=0-10, 5-20, 21-30, 40-")
+                .satisfiable_ranges(50)
+                .unwrap(),
+            vec![(0, 30), (40, 49)]
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(Range::bytes(0..=9).unwrap(), range("bytes=0-9"));
+        assert_eq!(Range::bytes(0..10).unwrap(), range("bytes=0-9"));
+        assert_eq!(Range::bytes(5..).unwrap(), range("bytes=5-"));
+        assert!(Range::bytes(..5).is_err());
+    }
+}
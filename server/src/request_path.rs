@@ -0,0 +1,426 @@
+use crate::error_type;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::uri::PathAndQuery;
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use std::fmt;
+use std::str::FromStr;
+
+/// The characters that are percent-encoded in a canonical path segment,
+/// everything except the unreserved characters, the sub-delimiters, `:` and
+/// `@`, see [RFC3986](https://datatracker.ietf.org/doc/html/rfc3986#section-3.3).
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// The path of a request, decoded and normalized.
+///
+/// The raw path is normalized the way a browser resolves a URL, except that
+/// the rules are applied after the percent-decoding, so that an encoded
+/// separator or dot can't hide a traversal:
+///
+/// * `%XX` sequences are decoded, and the result has to be valid UTF-8,
+/// * `\` is a separator like `/`, and empty segments from `//` are removed,
+/// * `.` segments are removed and `..` removes the previous segment,
+/// * a `..` that would leave the root is a traversal attempt and rejected,
+/// * malformed `%` sequences and control characters, including NUL, are
+///   rejected,
+/// * a trailing slash is kept, since it tells a directory apart from a file.
+///
+/// # Examples
+///
+/// ```
+/// use server::request_path::RequestPath;
+///
+/// let path: RequestPath = "/docs//guide/./%E6%8C%87%E5%8D%97/".parse().unwrap();
+/// assert_eq!(path.as_str(), "docs/guide/指南/");
+/// assert_eq!(path.to_uri_path(), "/docs/guide/%E6%8C%87%E5%8D%97/");
+/// assert!("/assets/..%2F..%2Fetc/passwd".parse::<RequestPath>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestPath {
+    segments: Vec<String>,
+    trailing_slash: bool,
+    /// The decoded path without the leading slash.
+    path: String,
+}
+
+error_type!(InvalidRequestPath);
+
+impl RequestPath {
+    /// The decoded path relative to the root, without the leading slash.
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The decoded segments of the path.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().map(String::as_str)
+    }
+
+    /// Whether the path ends with a slash, the root always does.
+    pub fn has_trailing_slash(&self) -> bool {
+        self.trailing_slash
+    }
+
+    /// The canonical form of the path in a URL, with the leading slash.
+    pub fn to_uri_path(&self) -> String {
+        let mut uri_path = String::from("/");
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                uri_path.push('/');
+            }
+            uri_path.extend(utf8_percent_encode(segment, SEGMENT));
+        }
+        if self.trailing_slash && !self.segments.is_empty() {
+            uri_path.push('/');
+        }
+        uri_path
+    }
+
+    /// Whether the raw path is already the canonical form of this path.
+    pub fn is_canonical(&self, raw: &str) -> bool {
+        self.to_uri_path() == raw
+    }
+}
+
+impl FromStr for RequestPath {
+    type Err = InvalidRequestPath;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidRequestPath { _inner: () };
+        let bytes = raw.as_bytes();
+        for (i, b) in bytes.iter().enumerate() {
+            if *b == b'%'
+                && !(bytes.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+                    && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit))
+            {
+                return Err(invalid());
+            }
+        }
+        let decoded = percent_decode_str(raw)
+            .decode_utf8()
+            .map_err(|_| invalid())?;
+        if decoded.chars().any(char::is_control) {
+            return Err(invalid());
+        }
+        let mut segments: Vec<String> = Vec::new();
+        // The path is a directory when the last segment is empty, `.` or `..`
+        let mut trailing_slash = true;
+        for segment in decoded.split(['/', '\\']) {
+            trailing_slash = matches!(segment, "" | "." | "..");
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop().ok_or_else(invalid)?;
+                }
+                segment => segments.push(segment.to_owned()),
+            }
+        }
+        let mut path = segments.join("/");
+        if trailing_slash && !segments.is_empty() {
+            path.push('/');
+        }
+        Ok(RequestPath {
+            segments,
+            trailing_slash,
+            path,
+        })
+    }
+}
+
+impl fmt::Display for RequestPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_uri_path())
+    }
+}
+
+/// What to do with a request whose path isn't canonical.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonCanonicalPath {
+    /// Route the request as if the canonical path had been requested.
+    #[default]
+    Rewrite,
+    /// Redirect the client to the canonical path.
+    Redirect,
+}
+
+/// A middleware that normalizes the path of every request with
+/// [`RequestPath`] before it is routed, answering `400 Bad Request` when the
+/// path is invalid or tries to leave the root.
+///
+/// It has to wrap the whole router, since a middleware added with
+/// `Router::layer` runs after the routing:
+///
+/// ```
+/// use axum::{Router, ServiceExt, middleware};
+/// use server::request_path::{NonCanonicalPath, normalize_path};
+/// use tower::Layer;
+///
+/// let router: Router = Router::new();
+/// let app = middleware::from_fn_with_state(NonCanonicalPath::Rewrite, normalize_path)
+///     .layer(router)
+///     .into_make_service();
+/// ```
+pub async fn normalize_path(
+    State(non_canonical): State<NonCanonicalPath>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let uri = request.uri();
+    let Ok(path) = uri.path().parse::<RequestPath>() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    if path.is_canonical(uri.path()) {
+        return next.run(request).await;
+    }
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_uri_path(),
+    };
+    if non_canonical == NonCanonicalPath::Redirect {
+        // 308 keeps the method and the body of the other requests
+        let status = if matches!(*request.method(), Method::GET | Method::HEAD) {
+            StatusCode::MOVED_PERMANENTLY
+        } else {
+            StatusCode::PERMANENT_REDIRECT
+        };
+        let mut headers = HeaderMap::new();
+        if let Ok(location) = HeaderValue::try_from(path_and_query) {
+            headers.insert(http::header::LOCATION, location);
+            return (status, headers).into_response();
+        }
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let mut parts = uri.clone().into_parts();
+    let Ok(path_and_query) = PathAndQuery::try_from(path_and_query) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    parts.path_and_query = Some(path_and_query);
+    let Ok(uri) = Uri::from_parts(parts) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    *request.uri_mut() = uri;
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(raw: &str) -> Option<String> {
+        raw.parse::<RequestPath>()
+            .ok()
+            .map(|path| path.as_str().to_owned())
+    }
+
+    fn canonical(raw: &str) -> String {
+        raw.parse::<RequestPath>().unwrap().to_uri_path()
+    }
+
+    #[test]
+    fn already_normalized() {
+        for raw in [
+            "/",
+            "/index.html",
+            "/assets/index-BfT3xk9a.js",
+            "/docs/guide/",
+        ] {
+            let path: RequestPath = raw.parse().unwrap();
+            assert!(path.is_canonical(raw), "{raw}");
+            assert_eq!(path.as_str(), raw.trim_start_matches('/'));
+        }
+        assert_eq!(normalize(""), Some(String::new()));
+    }
+
+    #[test]
+    fn root() {
+        let root: RequestPath = "/".parse().unwrap();
+        assert!(root.has_trailing_slash());
+        assert_eq!(root.segments().count(), 0);
+        assert_eq!(root.to_uri_path(), "/");
+        assert_eq!(canonical("//"), "/");
+        assert_eq!(canonical("/./"), "/");
+        assert_eq!(canonical("/a/.."), "/");
+    }
+
+    #[test]
+    fn empty_and_dot_segments() {
+        assert_eq!(
+            normalize("//assets///index.js"),
+            Some("assets/index.js".to_owned())
+        );
+        assert_eq!(
+            normalize("/./assets/./index.js"),
+            Some("assets/index.js".to_owned())
+        );
+        assert_eq!(normalize("/docs/guide/."), Some("docs/guide/".to_owned()));
+        assert_eq!(normalize("/docs/guide/.."), Some("docs/".to_owned()));
+        assert_eq!(
+            normalize("/docs/guide/../api/index.html"),
+            Some("docs/api/index.html".to_owned())
+        );
+        assert_eq!(
+            normalize("/.../index.html"),
+            Some(".../index.html".to_owned())
+        );
+        assert_eq!(
+            normalize("/.well-known/a"),
+            Some(".well-known/a".to_owned())
+        );
+    }
+
+    #[test]
+    fn traversal() {
+        for raw in [
+            "/..",
+            "/../etc/passwd",
+            "/assets/../../etc/passwd",
+            "/%2e%2e/etc/passwd",
+            "/%2E%2E%2Fetc%2Fpasswd",
+            "/assets/..%2f..%2fetc/passwd",
+            "/..\\etc\\passwd",
+            "/assets/..%5c..%5cetc",
+            "/a/./../..",
+        ] {
+            assert_eq!(normalize(raw), None, "{raw}");
+        }
+        // The path is decoded only once, so doubly encoded dots are a file name
+        assert_eq!(normalize("/%252e%252e/etc"), Some("%2e%2e/etc".to_owned()));
+        assert_eq!(canonical("/%252e%252e/etc"), "/%252e%252e/etc");
+    }
+
+    #[test]
+    fn backslash() {
+        assert_eq!(
+            normalize("/assets\\index.js"),
+            Some("assets/index.js".to_owned())
+        );
+        assert_eq!(
+            normalize("/assets%5Cindex.js"),
+            Some("assets/index.js".to_owned())
+        );
+        assert_eq!(canonical("\\docs\\guide\\"), "/docs/guide/");
+    }
+
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(
+            normalize("/%61ssets/a%20b.js"),
+            Some("assets/a b.js".to_owned())
+        );
+        assert_eq!(canonical("/%61ssets/a%20b.js"), "/assets/a%20b.js");
+        assert_eq!(normalize("/100%25.html"), Some("100%.html".to_owned()));
+        assert_eq!(canonical("/100%25.html"), "/100%25.html");
+        assert_eq!(canonical("/a%2fb"), "/a/b");
+        assert_eq!(canonical("/a%3Fb%23c"), "/a%3Fb%23c");
+        assert_eq!(canonical("/@scope/pkg:1(2)"), "/@scope/pkg:1(2)");
+        assert_eq!(canonical("/%e6%8c%87%e5%8d%97"), "/%E6%8C%87%E5%8D%97");
+        assert!(
+            !"/%e6%8c%87"
+                .parse::<RequestPath>()
+                .unwrap()
+                .is_canonical("/%e6%8c%87")
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for raw in [
+            "/index.html%00.js",
+            "/index.html%00",
+            "/a\0b",
+            "/a%0Ab",
+            "/a%7F",
+            "/%",
+            "/%4",
+            "/%zz",
+            "/%FF%FE",
+            "/%C0%AE%C0%AE/etc",
+        ] {
+            assert_eq!(normalize(raw), None, "{raw}");
+        }
+    }
+
+    #[test]
+    fn trailing_slash() {
+        let file: RequestPath = "/docs/guide".parse().unwrap();
+        assert!(!file.has_trailing_slash());
+        let dir: RequestPath = "/docs/guide//".parse().unwrap();
+        assert!(dir.has_trailing_slash());
+        assert_eq!(dir.to_string(), "/docs/guide/");
+    }
+
+    async fn request(non_canonical: NonCanonicalPath, uri: &str) -> (StatusCode, String) {
+        use axum::body::{Body, to_bytes};
+        use axum::routing::get;
+        use tower::{Layer, ServiceExt};
+
+        let router =
+            axum::Router::new().route("/{*path}", get(|uri: Uri| async move { uri.to_string() }));
+        let app = axum::middleware::from_fn_with_state(non_canonical, normalize_path).layer(router);
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let location = response.headers().get(http::header::LOCATION).cloned();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        match location {
+            Some(location) => (status, location.to_str().unwrap().to_owned()),
+            None => (status, String::from_utf8(body.to_vec()).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn rewrite() {
+        let rewrite = NonCanonicalPath::Rewrite;
+        assert_eq!(
+            request(rewrite, "/assets/index.js?v=1").await,
+            (StatusCode::OK, "/assets/index.js?v=1".to_owned())
+        );
+        assert_eq!(
+            request(rewrite, "//assets/./x/../index.js?v=1").await,
+            (StatusCode::OK, "/assets/index.js?v=1".to_owned())
+        );
+        assert_eq!(
+            request(rewrite, "/%2e%2e/etc/passwd").await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            request(rewrite, "/index.html%00").await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect() {
+        let redirect = NonCanonicalPath::Redirect;
+        assert_eq!(
+            request(redirect, "/docs/guide/").await,
+            (StatusCode::OK, "/docs/guide/".to_owned())
+        );
+        assert_eq!(
+            request(redirect, "/docs//guide/.?q=1").await,
+            (StatusCode::MOVED_PERMANENTLY, "/docs/guide/?q=1".to_owned())
+        );
+        assert_eq!(
+            request(redirect, "/../docs").await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
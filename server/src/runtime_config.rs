@@ -0,0 +1,192 @@
+use crate::error_type;
+use std::collections::BTreeMap;
+
+/// The environment variables with this prefix are exposed to the frontend.
+pub const ENV_PREFIX: &str = "APP_";
+
+/// The prefix of the variables that Vite bakes into the build.
+pub const VITE_PREFIX: &str = "VITE_";
+
+/// The global variable holding the configuration in the browser.
+pub const GLOBAL: &str = "window.__APP_CONFIG__";
+
+/// The configuration of the frontend read when the server starts, so that
+/// the same build can be promoted from one environment to another.
+///
+/// The keys are the names of the Vite variables, e.g. `VITE_API_URL`, so the
+/// frontend can fall back to `import.meta.env` for the keys that aren't set.
+/// It is served as `config.json`, as a script assigning [`GLOBAL`], or
+/// injected into the HTML document as that script.
+///
+/// # Examples
+///
+/// ```
+/// use server::runtime_config::RuntimeConfig;
+///
+/// let mut config = RuntimeConfig::parse("VITE_ACCESS_MODE = frontend").unwrap();
+/// config.merge(RuntimeConfig::from_env([(
+///     "APP_API_URL".to_owned(),
+///     "https://api.example.com".to_owned(),
+/// )]));
+/// assert_eq!(
+///     config.to_json(),
+///     r#"{"VITE_ACCESS_MODE":"frontend","VITE_API_URL":"https://api.example.com"}"#
+/// );
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    values: BTreeMap<String, String>,
+}
+
+error_type!(InvalidRuntimeConfig);
+
+impl RuntimeConfig {
+    /// The variables starting with [`ENV_PREFIX`], renamed to the Vite
+    /// variables, e.g. `APP_API_URL` becomes `VITE_API_URL`.
+    pub fn from_env(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let values = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let name = name.strip_prefix(ENV_PREFIX)?;
+                (!name.is_empty()).then(|| (format!("{VITE_PREFIX}{name}"), value))
+            })
+            .collect();
+        RuntimeConfig { values }
+    }
+
+    /// Parses a file in the format of the `.env` files of Vite, one
+    /// `KEY = VALUE` per line, ignoring the blank lines and the comments
+    /// starting with `#`. The value may be quoted.
+    pub fn parse(content: &str) -> Result<Self, InvalidRuntimeConfig> {
+        let invalid = || InvalidRuntimeConfig { _inner: () };
+        let mut values = BTreeMap::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let key = key.trim();
+            if key.is_empty() || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                return Err(invalid());
+            }
+            let value = value.trim();
+            let value = ['"', '\'']
+                .into_iter()
+                .find_map(|quote| {
+                    value
+                        .strip_prefix(quote)
+                        .and_then(|value| value.strip_suffix(quote))
+                })
+                .unwrap_or(value);
+            values.insert(key.to_owned(), value.to_owned());
+        }
+        Ok(RuntimeConfig { values })
+    }
+
+    /// The values of the other configuration win.
+    pub fn merge(&mut self, other: RuntimeConfig) {
+        self.values.extend(other.values);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// The configuration as a JSON object, which is also safe to embed in a
+    /// `<script>` element: `<`, `>` and `&` are escaped so a value can't
+    /// close the element.
+    pub fn to_json(&self) -> String {
+        let json = serde_json::to_string(&self.values).expect("a map of strings is serializable");
+        json.replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+    }
+
+    /// A script assigning the configuration to [`GLOBAL`].
+    pub fn to_script(&self) -> String {
+        format!("{GLOBAL} = {};\n", self.to_json())
+    }
+
+    /// Inserts the script at the end of `<head>`, before the module scripts
+    /// of Vite run. `None` if the document has no `</head>`.
+    pub fn inject(&self, html: &[u8]) -> Option<Vec<u8>> {
+        const HEAD_END: &[u8] = b"</head>";
+        let position = html
+            .windows(HEAD_END.len())
+            .position(|window| window.eq_ignore_ascii_case(HEAD_END))?;
+        let script = format!("<script>{}</script>\n  ", self.to_script().trim_end());
+        let mut document = Vec::with_capacity(html.len() + script.len());
+        document.extend_from_slice(&html[..position]);
+        document.extend_from_slice(script.as_bytes());
+        document.extend_from_slice(&html[position..]);
+        Some(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env() {
+        let config = RuntimeConfig::from_env([
+            ("APP_API_URL".to_owned(), "/api".to_owned()),
+            ("APP_".to_owned(), "empty".to_owned()),
+            ("PATH".to_owned(), "/usr/bin".to_owned()),
+        ]);
+        assert_eq!(config.get("VITE_API_URL"), Some("/api"));
+        assert_eq!(config.values.len(), 1);
+    }
+
+    #[test]
+    fn env_file() {
+        let config = RuntimeConfig::parse(
+            "# 【生产】环境变量\n\nVITE_API_URL = https://api.example.com\nVITE_LOCK_ENCRYPT_KEY='a=b'\nVITE_EMPTY =\n",
+        )
+        .unwrap();
+        assert_eq!(config.get("VITE_API_URL"), Some("https://api.example.com"));
+        assert_eq!(config.get("VITE_LOCK_ENCRYPT_KEY"), Some("a=b"));
+        assert_eq!(config.get("VITE_EMPTY"), Some(""));
+        assert!(RuntimeConfig::parse("VITE_API_URL").is_err());
+        assert!(RuntimeConfig::parse("VITE API = 1").is_err());
+        assert!(RuntimeConfig::parse("= 1").is_err());
+    }
+
+    #[test]
+    fn env_wins() {
+        let mut config = RuntimeConfig::parse("VITE_API_URL = /file").unwrap();
+        config.merge(RuntimeConfig::from_env([(
+            "APP_API_URL".to_owned(),
+            "/env".to_owned(),
+        )]));
+        assert_eq!(config.get("VITE_API_URL"), Some("/env"));
+    }
+
+    #[test]
+    fn script_safe() {
+        let config = RuntimeConfig::from_env([(
+            "APP_TITLE".to_owned(),
+            "</script><script>alert(1)</script>".to_owned(),
+        )]);
+        assert!(!config.to_script().contains("</script>"));
+        assert_eq!(
+            config.to_script(),
+            "window.__APP_CONFIG__ = {\"VITE_TITLE\":\"\\u003c/script\\u003e\\u003cscript\\u003ealert(1)\\u003c/script\\u003e\"};\n"
+        );
+    }
+
+    #[test]
+    fn inject() {
+        let config = RuntimeConfig::from_env([("APP_API_URL".to_owned(), "/api".to_owned())]);
+        let html = b"<html><head><title>Art Design Pro</title></HEAD><body></body></html>";
+        assert_eq!(
+            String::from_utf8(config.inject(html).unwrap()).unwrap(),
+            "<html><head><title>Art Design Pro</title><script>window.__APP_CONFIG__ = {\"VITE_API_URL\":\"/api\"};</script>\n  </HEAD><body></body></html>"
+        );
+        assert!(config.inject(b"<p>fragment</p>").is_none());
+    }
+}
@@ -0,0 +1,388 @@
+use crate::csp_nonce::CspNonce;
+use crate::error_type;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::{self, HeaderName};
+use http::{HeaderMap, HeaderValue};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A response header set on every response, or removed from the defaults.
+///
+/// The textual form is `<name>=<value>`, an empty value removes the default
+/// header of the same name.
+///
+/// # Examples
+///
+/// ```
+/// use server::security_headers::SecurityHeader;
+///
+/// let header: SecurityHeader = "X-Frame-Options=SAMEORIGIN".parse().unwrap();
+/// assert_eq!(header.name(), "x-frame-options");
+/// assert!("Strict-Transport-Security=".parse::<SecurityHeader>().is_ok());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecurityHeader {
+    name: HeaderName,
+    value: Option<HeaderValue>,
+}
+
+error_type!(InvalidSecurityHeader);
+
+impl SecurityHeader {
+    pub fn new(name: HeaderName, value: HeaderValue) -> Self {
+        SecurityHeader {
+            name,
+            value: Some(value),
+        }
+    }
+
+    pub fn name(&self) -> &HeaderName {
+        &self.name
+    }
+}
+
+impl FromStr for SecurityHeader {
+    type Err = InvalidSecurityHeader;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidSecurityHeader { _inner: () };
+        let (name, value) = s.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim();
+        Ok(SecurityHeader {
+            name: name.trim().parse().map_err(|_| invalid())?,
+            value: if value.is_empty() {
+                None
+            } else {
+                Some(value.parse().map_err(|_| invalid())?)
+            },
+        })
+    }
+}
+
+/// The security headers of the admin UI, set on the responses that don't
+/// have them yet.
+///
+/// With nonces enabled, a [`CspNonce`] is generated for every request and
+/// put into the request extensions, so that the handler can inject it into
+/// the HTML documents. The same nonce is added to the `script-src` of the
+/// `Content-Security-Policy`, in place of `'unsafe-inline'`.
+///
+/// # Examples
+///
+/// ```
+/// use http::HeaderMap;
+/// use server::csp_nonce::CspNonce;
+/// use server::security_headers::SecurityHeaders;
+///
+/// let csp = SecurityHeaders::content_security_policy(&["https://api.example.com"]);
+/// let headers = SecurityHeaders::with_defaults(vec![csp]).with_nonce(true);
+/// let nonce = CspNonce::generate();
+/// let mut map = HeaderMap::new();
+/// headers.apply(&mut map, Some(&nonce));
+/// let csp = map["content-security-policy"].to_str().unwrap();
+/// assert!(csp.contains(&nonce.source()));
+/// assert!(!csp.contains("'unsafe-inline' 'nonce"));
+/// assert!(csp.contains("connect-src 'self' https://api.example.com;"));
+/// assert_eq!(map["x-frame-options"], "DENY");
+/// ```
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    nonce: bool,
+}
+
+impl SecurityHeaders {
+    /// Only the given headers, the ones without a value are ignored.
+    pub fn new(headers: Vec<SecurityHeader>) -> Self {
+        let mut security_headers = SecurityHeaders {
+            headers: Vec::new(),
+            nonce: false,
+        };
+        security_headers.extend(headers);
+        security_headers
+    }
+
+    /// The given headers replace or remove the default headers of the same
+    /// name.
+    pub fn with_defaults(headers: Vec<SecurityHeader>) -> Self {
+        let mut security_headers = SecurityHeaders::new(SecurityHeaders::default_headers());
+        security_headers.extend(headers);
+        security_headers
+    }
+
+    /// The defaults suit a single-page admin UI:
+    ///
+    /// * `Strict-Transport-Security` keeps browsers on HTTPS for a year, it is
+    ///   ignored on plain HTTP. The subdomains are left alone, they may be
+    ///   other sites.
+    /// * `X-Frame-Options` forbids framing.
+    /// * `Referrer-Policy` only sends the origin to other sites.
+    /// * `Permissions-Policy` disables the powerful features the UI never
+    ///   uses.
+    ///
+    /// The [`content_security_policy`](Self::content_security_policy) isn't
+    /// one of them, since it has to allow the origin of the API.
+    pub fn default_headers() -> Vec<SecurityHeader> {
+        [
+            (header::STRICT_TRANSPORT_SECURITY, "max-age=31536000"),
+            (header::X_FRAME_OPTIONS, "DENY"),
+            (header::REFERRER_POLICY, "strict-origin-when-cross-origin"),
+            (
+                HeaderName::from_static("permissions-policy"),
+                "camera=(), microphone=(), geolocation=(), payment=(), usb=()",
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| SecurityHeader::new(name, HeaderValue::from_static(value)))
+        .collect()
+    }
+
+    /// A `Content-Security-Policy` that only allows the resources of the
+    /// origin, and the connections to the given sources too, e.g. the origin
+    /// of the API when it is on another one. Element Plus sets inline styles,
+    /// and the theme script in `index.html` is inline, so both are allowed
+    /// unless nonces are used. `frame-ancestors` forbids framing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use server::security_headers::SecurityHeaders;
+    ///
+    /// let header = SecurityHeaders::content_security_policy(&["https://api.example.com"]);
+    /// assert_eq!(header.name(), "content-security-policy");
+    /// ```
+    pub fn content_security_policy(connect_sources: &[impl AsRef<str>]) -> SecurityHeader {
+        let connect_src = std::iter::once("'self'")
+            .chain(connect_sources.iter().map(AsRef::as_ref))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let policy = format!(
+            "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; img-src 'self' data: blob: https:; font-src 'self' data:; connect-src {connect_src}; object-src 'none'; base-uri 'self'; form-action 'self'; frame-ancestors 'none'"
+        );
+        SecurityHeader {
+            name: header::CONTENT_SECURITY_POLICY,
+            value: HeaderValue::try_from(policy).ok(),
+        }
+    }
+
+    /// Whether a [`CspNonce`] is generated for every request.
+    pub fn with_nonce(mut self, nonce: bool) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn uses_nonce(&self) -> bool {
+        self.nonce
+    }
+
+    fn extend(&mut self, headers: Vec<SecurityHeader>) {
+        for SecurityHeader { name, value } in headers {
+            self.headers.retain(|(existing, _)| *existing != name);
+            if let Some(value) = value {
+                self.headers.push((name, value));
+            }
+        }
+    }
+
+    /// Sets the headers that the response doesn't have yet, a handler or a
+    /// proxied backend may set its own policy.
+    pub fn apply(&self, headers: &mut HeaderMap, nonce: Option<&CspNonce>) {
+        for (name, value) in &self.headers {
+            if headers.contains_key(name) {
+                continue;
+            }
+            let value = match nonce {
+                Some(nonce) if *name == header::CONTENT_SECURITY_POLICY => value
+                    .to_str()
+                    .ok()
+                    .and_then(|policy| HeaderValue::try_from(with_nonce(policy, nonce)).ok())
+                    .unwrap_or_else(|| value.clone()),
+                _ => value.clone(),
+            };
+            headers.insert(name.clone(), value);
+        }
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders::with_defaults(Vec::new())
+    }
+}
+
+/// Adds the nonce to `script-src` and drops `'unsafe-inline'`, which the
+/// nonce makes browsers ignore anyway. Without `script-src` the sources are
+/// copied from `default-src`, which `script-src` would fall back to.
+fn with_nonce(policy: &str, nonce: &CspNonce) -> String {
+    let nonce = nonce.source();
+    let mut directives = policy
+        .split(';')
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .map(|directive| {
+            let mut tokens = directive.split_ascii_whitespace();
+            let name = tokens.next().unwrap_or_default().to_ascii_lowercase();
+            (name, tokens.collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    let position = match directives.iter().position(|(name, _)| name == "script-src") {
+        Some(position) => position,
+        None => {
+            let sources = directives
+                .iter()
+                .find(|(name, _)| name == "default-src")
+                .map(|(_, sources)| sources.clone())
+                .unwrap_or_default();
+            directives.push(("script-src".to_owned(), sources));
+            directives.len() - 1
+        }
+    };
+    let sources = &mut directives[position].1;
+    sources.retain(|source| !source.eq_ignore_ascii_case("'unsafe-inline'"));
+    sources.push(&nonce);
+    directives
+        .iter()
+        .map(|(name, sources)| {
+            if sources.is_empty() {
+                name.clone()
+            } else {
+                format!("{name} {}", sources.join(" "))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A middleware that sets the [`SecurityHeaders`] on every response, and
+/// generates the [`CspNonce`] of the request when nonces are enabled.
+pub async fn security_headers(
+    State(security_headers): State<Arc<SecurityHeaders>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let nonce = security_headers.nonce.then(CspNonce::generate);
+    if let Some(nonce) = &nonce {
+        request.extensions_mut().insert(nonce.clone());
+    }
+    let mut response = next.run(request).await;
+    security_headers.apply(response.headers_mut(), nonce.as_ref());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn parse() {
+        let header: SecurityHeader = "Referrer-Policy = no-referrer".parse().unwrap();
+        assert_eq!(header.value.unwrap(), "no-referrer");
+        assert!("Referrer-Policy".parse::<SecurityHeader>().is_err());
+        assert!("Bad Name=value".parse::<SecurityHeader>().is_err());
+        assert!("X-Test=bad\nvalue".parse::<SecurityHeader>().is_err());
+    }
+
+    #[test]
+    fn replace_and_remove_defaults() {
+        let headers = SecurityHeaders::with_defaults(vec![
+            "X-Frame-Options=SAMEORIGIN".parse().unwrap(),
+            "Strict-Transport-Security=".parse().unwrap(),
+            "Cross-Origin-Opener-Policy=same-origin".parse().unwrap(),
+        ]);
+        let mut map = HeaderMap::new();
+        map.insert(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        );
+        headers.apply(&mut map, None);
+        assert_eq!(map[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+        assert!(!map.contains_key(header::STRICT_TRANSPORT_SECURITY));
+        assert_eq!(map["cross-origin-opener-policy"], "same-origin");
+        assert_eq!(map[header::REFERRER_POLICY], "no-referrer");
+        assert!(!map.contains_key(header::CONTENT_SECURITY_POLICY));
+        let mut map = HeaderMap::new();
+        SecurityHeaders::default().apply(&mut map, None);
+        assert_eq!(map[header::STRICT_TRANSPORT_SECURITY], "max-age=31536000");
+    }
+
+    #[test]
+    fn content_security_policy() {
+        let header = SecurityHeaders::content_security_policy(&[] as &[&str]);
+        let policy = header.value.unwrap();
+        let policy = policy.to_str().unwrap();
+        assert!(policy.contains("script-src 'self' 'unsafe-inline';"));
+        assert!(policy.contains("connect-src 'self';"));
+        let header = SecurityHeaders::content_security_policy(&[
+            "https://m1.apifoxmock.com",
+            "wss://ws.example.com",
+        ]);
+        assert!(
+            header
+                .value
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .contains("connect-src 'self' https://m1.apifoxmock.com wss://ws.example.com;")
+        );
+    }
+
+    #[test]
+    fn nonce_source() {
+        let nonce = CspNonce::from_static("abc");
+        assert_eq!(
+            with_nonce(
+                "default-src 'self'; script-src 'self' 'unsafe-inline'; object-src 'none'",
+                &nonce
+            ),
+            "default-src 'self'; script-src 'self' 'nonce-abc'; object-src 'none'"
+        );
+        assert_eq!(
+            with_nonce("default-src 'self' https://cdn.example.com;", &nonce),
+            "default-src 'self' https://cdn.example.com; script-src 'self' https://cdn.example.com 'nonce-abc'"
+        );
+        assert_eq!(
+            with_nonce("frame-ancestors 'none'", &nonce),
+            "frame-ancestors 'none'; script-src 'nonce-abc'"
+        );
+        assert_eq!(
+            with_nonce("script-src-elem 'self'; script-src 'self'", &nonce),
+            "script-src-elem 'self'; script-src 'self' 'nonce-abc'"
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        let router = Router::new().route(
+            "/",
+            get(|nonce: Option<Extension<CspNonce>>| async move {
+                nonce
+                    .map(|Extension(nonce)| nonce.to_string())
+                    .unwrap_or_default()
+            }),
+        );
+        let csp = SecurityHeaders::content_security_policy(&[] as &[&str]);
+        let headers = Arc::new(SecurityHeaders::new(vec![csp]).with_nonce(true));
+        let app = router.layer(axum::middleware::from_fn_with_state(
+            headers,
+            security_headers,
+        ));
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let csp = response.headers()[header::CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let nonce = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!nonce.is_empty());
+        assert!(csp.contains(&format!("'nonce-{nonce}'")));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::accept::Accept;
+
+/// The media type browsers list in `Accept` when they navigate to a page.
+const TEXT_HTML: &str = "text/html";
+
+/// The history mode of a single page application.
+///
+/// The router of the frontend updates the URL with the History API, so a
+/// reload or a bookmark requests a path that only exists on the client. Such
+/// requests are answered with the application document instead of `404`.
+///
+/// A request is a client route when:
+///
+/// * its last path segment has no file extension, so missing assets and
+///   source maps still answer `404`,
+/// * it isn't below one of the excluded prefixes, e.g. `/api`,
+/// * the client lists `text/html` in `Accept`, like browsers do when they
+///   navigate, while `fetch` and `XMLHttpRequest` accept `*/*`.
+///
+/// # Examples
+///
+/// ```
+/// use server::spa_fallback::SpaFallback;
+///
+/// let fallback = SpaFallback::new("index.html", vec!["/api".to_owned()]);
+/// assert!(fallback.is_client_route("system/user"));
+/// assert!(!fallback.is_client_route("assets/index-missing.js"));
+/// assert!(!fallback.is_client_route("api/user/list"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpaFallback {
+    document: String,
+    excluded: Vec<String>,
+}
+
+impl SpaFallback {
+    /// Falls back to `document` outside of the `excluded` path prefixes.
+    pub fn new(document: impl Into<String>, excluded: Vec<String>) -> Self {
+        let excluded = excluded
+            .into_iter()
+            .map(|prefix| prefix.trim_matches('/').to_owned())
+            .collect();
+        SpaFallback {
+            document: document.into(),
+            excluded,
+        }
+    }
+
+    /// The document that is served for the client routes.
+    pub fn document(&self) -> &str {
+        &self.document
+    }
+
+    /// Whether the path, relative to the root of the application, looks like
+    /// a route of the frontend rather than a file.
+    pub fn is_client_route(&self, path: &str) -> bool {
+        let path = path.trim_matches('/');
+        let last_segment = path.rsplit('/').next().unwrap_or_default();
+        !last_segment.contains('.')
+            && !self.excluded.iter().any(|prefix| {
+                prefix.is_empty()
+                    || path
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+    }
+
+    /// Whether the request comes from a browser navigating to the path.
+    pub fn is_navigation(accept: Option<&Accept>) -> bool {
+        accept.is_some_and(|accept| accept.lists(TEXT_HTML))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use headers::HeaderMapExt;
+
+    #[test]
+    fn client_routes() {
+        let fallback = SpaFallback::new("index.html", Vec::new());
+        assert!(fallback.is_client_route("dashboard/console"));
+        assert!(fallback.is_client_route("/system/user/"));
+        assert!(fallback.is_client_route("api/user"));
+        assert!(!fallback.is_client_route("favicon.ico"));
+        assert!(!fallback.is_client_route("assets/index-BfT3xk9a.js.map"));
+        assert!(fallback.is_client_route("v1.2/release"));
+    }
+
+    #[test]
+    fn excluded_prefixes() {
+        let fallback = SpaFallback::new(
+            "index.html",
+            vec!["/api".to_owned(), "auth/callback/".to_owned()],
+        );
+        assert!(!fallback.is_client_route("api"));
+        assert!(!fallback.is_client_route("api/user/list"));
+        assert!(fallback.is_client_route("apidoc"));
+        assert!(!fallback.is_client_route("auth/callback/github"));
+        assert!(fallback.is_client_route("auth/login"));
+
+        let fallback = SpaFallback::new("index.html", vec!["/".to_owned()]);
+        assert!(!fallback.is_client_route("dashboard"));
+    }
+
+    #[test]
+    fn navigation() {
+        let accept = |value: &str| {
+            let mut map = http::HeaderMap::new();
+            map.insert(http::header::ACCEPT, value.parse().unwrap());
+            map.typed_get::<Accept>()
+        };
+        assert!(SpaFallback::is_navigation(
+            accept("text/html,application/xhtml+xml,*/*;q=0.8").as_ref()
+        ));
+        assert!(!SpaFallback::is_navigation(
+            accept("application/json, text/plain, */*").as_ref()
+        ));
+        assert!(!SpaFallback::is_navigation(accept("*/*").as_ref()));
+        assert!(!SpaFallback::is_navigation(None));
+    }
+}
@@ -47,12 +47,14 @@ impl str::FromStr for Encoding {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
+        // Codings are case-insensitive, and x-gzip and x-compress are aliases of
+        // gzip and compress, see RFC9110 section 8.4.1
+        match s.to_ascii_lowercase().as_str() {
             "chunked" => Ok(Chunked),
             "br" => Ok(Brotli),
             "deflate" => Ok(Deflate),
-            "gzip" => Ok(Gzip),
-            "compress" => Ok(Compress),
+            "gzip" | "x-gzip" => Ok(Gzip),
+            "compress" | "x-compress" => Ok(Compress),
             "identity" => Ok(Identity),
             "trailers" => Ok(Trailers),
             "zstd" => Ok(Zstd),
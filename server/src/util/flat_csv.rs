@@ -28,6 +28,7 @@ impl Separator for Comma {
     const CHAR: char = ',';
 }
 
+#[allow(dead_code)]
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) enum SemiColon {}
 
@@ -4,6 +4,7 @@ use http::HeaderValue;
 pub mod encoding;
 pub mod entity;
 pub mod flat_csv;
+pub mod http_date;
 pub mod iter;
 pub mod quality;
 
@@ -43,7 +44,7 @@ macro_rules! derive_header {
             where
                 I: Iterator<Item = &'i ::http::header::HeaderValue>,
             {
-                crate::util::TryFromValues::try_from_values(values).map($type)
+                $crate::util::TryFromValues::try_from_values(values).map($type)
             }
 
             fn encode<E: Extend<http::header::HeaderValue>>(&self, values: &mut E) {
@@ -18,6 +18,11 @@ use std::{cmp, fmt, str};
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Quality(u16);
 
+impl Quality {
+    /// `q=0`, the value is not acceptable.
+    pub const ZERO: Quality = Quality(0);
+}
+
 impl Default for Quality {
     fn default() -> Quality {
         Quality(1000)
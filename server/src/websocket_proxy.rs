@@ -0,0 +1,694 @@
+use crate::error_type;
+use crate::proxy::{forwarded_headers, remove_hop_by_hop};
+use axum::extract::ws::{self, CloseFrame, Message, Utf8Bytes, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Request};
+use axum::response::{IntoResponse, Response};
+use futures_util::{SinkExt, StreamExt};
+use http::header::{self, HeaderName};
+use http::uri::{PathAndQuery, Scheme};
+use http::{HeaderMap, StatusCode, Uri};
+use std::future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::{self as ts, Error};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a connection may go without a text or a binary message.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often the client is pinged, a client that hasn't answered the previous
+/// ping is considered gone.
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for the close handshake of both sides to finish.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The close code of
+/// [the IANA registry](https://www.iana.org/assignments/websocket/websocket.xhtml#close-code-number)
+/// sent to the client when the upstream drops the connection without
+/// closing it.
+const BAD_GATEWAY: u16 = 1014;
+
+/// The headers of the opening handshake of
+/// [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455#section-4.1),
+/// which belong to the connection of the client and are generated again for
+/// the upstream. `Sec-WebSocket-Protocol` is forwarded, so that the upstream
+/// chooses the subprotocol.
+const HANDSHAKE: [HeaderName; 4] = [
+    header::HOST,
+    header::SEC_WEBSOCKET_KEY,
+    header::SEC_WEBSOCKET_VERSION,
+    header::SEC_WEBSOCKET_EXTENSIONS,
+];
+
+type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Tunnels the WebSocket connections below a path prefix to an upstream
+/// server.
+///
+/// The textual form is `<prefix>=<url>`. The url is a `ws` url, or an `http`
+/// url like the proxy of the Vite dev server with `ws: true`. The path of the
+/// request is kept and appended to the path of the url.
+///
+/// # Examples
+///
+/// ```
+/// use server::websocket_proxy::WebSocketRule;
+///
+/// let rule: WebSocketRule = "/ws=ws://backend:9000".parse().unwrap();
+/// assert_eq!(rule.prefix(), "/ws");
+/// assert_eq!(
+///     "/socket=http://backend:9000".parse::<WebSocketRule>().unwrap().upstream(),
+///     "ws://backend:9000/"
+/// );
+/// assert!("/ws=wss://backend".parse::<WebSocketRule>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketRule {
+    prefix: String,
+    upstream: Uri,
+}
+
+error_type!(InvalidWebSocketRule);
+
+impl WebSocketRule {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+
+    /// Whether the path is the prefix or below it.
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// The url of the request on the upstream.
+    fn uri(&self, path_and_query: &str) -> Option<Uri> {
+        let path = self.upstream.path().trim_end_matches('/');
+        Uri::builder()
+            .scheme("ws")
+            .authority(self.upstream.authority()?.clone())
+            .path_and_query(PathAndQuery::from_str(&format!("{path}{path_and_query}")).ok()?)
+            .build()
+            .ok()
+    }
+}
+
+impl FromStr for WebSocketRule {
+    type Err = InvalidWebSocketRule;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidWebSocketRule { _inner: () };
+        let (prefix, upstream) = s.split_once('=').ok_or_else(invalid)?;
+        let prefix = prefix.trim().trim_end_matches('/');
+        if !prefix.starts_with('/') || prefix.contains(['?', '#', '*', '{', '}']) {
+            return Err(invalid());
+        }
+        let upstream = upstream.trim().parse::<Uri>().map_err(|_| invalid())?;
+        let scheme = upstream.scheme_str().ok_or_else(invalid)?;
+        if !(scheme == "ws" || scheme == Scheme::HTTP.as_str()) || upstream.query().is_some() {
+            return Err(invalid());
+        }
+        let authority = upstream.authority().ok_or_else(invalid)?.clone();
+        let upstream = Uri::builder()
+            .scheme("ws")
+            .authority(authority)
+            .path_and_query(upstream.path())
+            .build()
+            .map_err(|_| invalid())?;
+        Ok(WebSocketRule {
+            prefix: prefix.to_owned(),
+            upstream,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocketOptions {
+    /// The timeout of the opening handshake with the upstream.
+    pub connect_timeout: Duration,
+    /// Closes the connections without a text or a binary message in either
+    /// direction for this long, with `1001 Going Away`.
+    pub idle_timeout: Option<Duration>,
+    /// Pings the client at this interval. When the pong of the previous ping
+    /// hasn't arrived, the client is gone, and the upstream is closed with
+    /// `1001 Going Away`.
+    pub ping_interval: Option<Duration>,
+    /// Sends the authority of the upstream as `Host`, otherwise the `Host` of
+    /// the client is kept.
+    pub change_origin: bool,
+}
+
+impl Default for WebSocketOptions {
+    fn default() -> Self {
+        WebSocketOptions {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            ping_interval: Some(DEFAULT_PING_INTERVAL),
+            change_origin: true,
+        }
+    }
+}
+
+/// A WebSocket proxy tunneling the messages between the clients and the
+/// upstreams.
+///
+/// The upstream is connected before the connection of the client is
+/// upgraded, so a failed handshake is answered with an HTTP status: the
+/// status of the upstream when it refuses the upgrade, `504 Gateway Timeout`
+/// when it doesn't answer in time, and `502 Bad Gateway` otherwise. The
+/// subprotocol is the one chosen by the upstream.
+///
+/// Text and binary messages are forwarded as they are. The close frame of
+/// one side is forwarded to the other, with its code and its reason, and a
+/// side that drops the connection without closing it is reported to the
+/// other as `1001 Going Away` or `1014 Bad Gateway`. Pings and pongs belong
+/// to each connection and aren't forwarded.
+#[derive(Debug)]
+pub struct WebSocketProxy {
+    rules: Vec<WebSocketRule>,
+    options: WebSocketOptions,
+}
+
+impl WebSocketProxy {
+    pub fn new(mut rules: Vec<WebSocketRule>, options: WebSocketOptions) -> Self {
+        // The longest prefix is matched first
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+        WebSocketProxy { rules, options }
+    }
+
+    pub fn rules(&self) -> &[WebSocketRule] {
+        &self.rules
+    }
+
+    pub fn options(&self) -> &WebSocketOptions {
+        &self.options
+    }
+
+    /// The rule of the longest prefix matching the path.
+    pub fn rule(&self, path: &str) -> Option<&WebSocketRule> {
+        self.rules.iter().find(|rule| rule.matches(path))
+    }
+
+    /// Connects to the upstream of the request, and tunnels the upgraded
+    /// connection of the client to it.
+    pub async fn upgrade(&self, ws: WebSocketUpgrade, request: Request) -> Response {
+        let Some(rule) = self.rule(request.uri().path()) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map_or("/", PathAndQuery::as_str);
+        let Some(uri) = rule.uri(path_and_query) else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+        let Ok(mut upstream_request) = uri.clone().into_client_request() else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
+        let client = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let headers = handshake_headers(request.headers(), client, self.options.change_origin);
+        upstream_request.headers_mut().extend(headers);
+        debug!("Tunnel {path_and_query} to {uri}");
+        let (upstream, response) = match tokio::time::timeout(
+            self.options.connect_timeout,
+            tokio_tungstenite::connect_async(upstream_request),
+        )
+        .await
+        {
+            Ok(Ok(connected)) => connected,
+            Ok(Err(Error::Http(response))) => {
+                warn!(
+                    "The upstream {uri} refused the upgrade with {}",
+                    response.status()
+                );
+                return response.status().into_response();
+            }
+            Ok(Err(error)) => {
+                warn!("The upstream {uri} failed: {error}");
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+            Err(_) => {
+                warn!("The upstream {uri} timed out");
+                return StatusCode::GATEWAY_TIMEOUT.into_response();
+            }
+        };
+        let protocol = response
+            .headers()
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let ws = match protocol {
+            Some(protocol) => ws.protocols([protocol]),
+            None => ws,
+        };
+        let options = self.options.clone();
+        ws.on_failed_upgrade(|error| warn!("Failed to upgrade the connection: {error}"))
+            .on_upgrade(move |client| tunnel(client, upstream, options))
+    }
+}
+
+/// The headers of the client forwarded in the handshake with the upstream,
+/// along with the forwarded headers of the proxy.
+fn handshake_headers(
+    headers: &HeaderMap,
+    client: Option<std::net::IpAddr>,
+    change_origin: bool,
+) -> HeaderMap {
+    let mut headers = headers.clone();
+    let host = headers.get(header::HOST).cloned();
+    remove_hop_by_hop(&mut headers);
+    forwarded_headers(&mut headers, client, host.as_ref());
+    for name in HANDSHAKE {
+        headers.remove(name);
+    }
+    if !change_origin && let Some(host) = host {
+        headers.insert(header::HOST, host);
+    }
+    headers
+}
+
+/// Forwards the messages in both directions until one side closes, then
+/// waits for the close handshake of both sides.
+async fn tunnel(mut client: WebSocket, mut upstream: Upstream, options: WebSocketOptions) {
+    let mut ping = options.ping_interval.map(|period| {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    });
+    let mut awaiting_pong = false;
+    let mut last_message = Instant::now();
+    loop {
+        let idle_deadline = options.idle_timeout.map(|timeout| last_message + timeout);
+        tokio::select! {
+            message = client.next() => {
+                awaiting_pong = false;
+                match message {
+                    Some(Ok(Message::Close(frame))) => {
+                        let _ = upstream.send(ts::Message::Close(frame.and_then(to_upstream_close))).await;
+                        break;
+                    }
+                    Some(Ok(message)) => {
+                        if let Some(message) = to_upstream(message) {
+                            last_message = Instant::now();
+                            if upstream.send(message).await.is_err() {
+                                let _ = client.send(close(BAD_GATEWAY, "Bad Gateway")).await;
+                                break;
+                            }
+                        }
+                    }
+                    Some(Err(_)) | None => {
+                        let _ = upstream.send(upstream_close(CloseCode::Away, "Going Away")).await;
+                        break;
+                    }
+                }
+            }
+            message = upstream.next() => match message {
+                Some(Ok(ts::Message::Close(frame))) => {
+                    let _ = client.send(Message::Close(frame.and_then(to_client_close))).await;
+                    break;
+                }
+                Some(Ok(message)) => {
+                    if let Some(message) = to_client(message) {
+                        last_message = Instant::now();
+                        if client.send(message).await.is_err() {
+                            let _ = upstream.send(upstream_close(CloseCode::Away, "Going Away")).await;
+                            break;
+                        }
+                    }
+                }
+                Some(Err(_)) | None => {
+                    let _ = client.send(close(BAD_GATEWAY, "Bad Gateway")).await;
+                    break;
+                }
+            },
+            _ = tick(&mut ping) => {
+                if awaiting_pong {
+                    debug!("The client didn't answer the ping");
+                    let _ = upstream.send(upstream_close(CloseCode::Away, "Going Away")).await;
+                    return;
+                }
+                awaiting_pong = true;
+                let _ = client.send(Message::Ping(Default::default())).await;
+            }
+            _ = sleep_until(idle_deadline) => {
+                debug!("The connection is idle");
+                let _ = client.send(close(ws::close_code::AWAY, "Idle Timeout")).await;
+                let _ = upstream.send(upstream_close(CloseCode::Away, "Idle Timeout")).await;
+                break;
+            }
+        }
+    }
+    // Reading on lets both sides send their close frames and finish the
+    // handshake
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+        tokio::join!(
+            async { while let Some(Ok(_)) = client.next().await {} },
+            async { while let Some(Ok(_)) = upstream.next().await {} },
+        )
+    })
+    .await;
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+fn close(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: Utf8Bytes::from_static(reason),
+    }))
+}
+
+fn upstream_close(code: CloseCode, reason: &'static str) -> ts::Message {
+    ts::Message::Close(Some(ts::protocol::CloseFrame {
+        code,
+        reason: ts::Utf8Bytes::from_static(reason),
+    }))
+}
+
+/// The close frame forwarded to the upstream, `None` for the codes that
+/// mustn't be sent in a close frame.
+fn to_upstream_close(frame: CloseFrame) -> Option<ts::protocol::CloseFrame> {
+    let code = CloseCode::from(frame.code);
+    code.is_allowed().then(|| ts::protocol::CloseFrame {
+        code,
+        reason: ts::Utf8Bytes::from(frame.reason.as_str()),
+    })
+}
+
+/// The close frame forwarded to the client, `None` for the codes that
+/// mustn't be sent in a close frame.
+fn to_client_close(frame: ts::protocol::CloseFrame) -> Option<CloseFrame> {
+    frame.code.is_allowed().then(|| CloseFrame {
+        code: frame.code.into(),
+        reason: Utf8Bytes::from(frame.reason.as_str()),
+    })
+}
+
+/// The data messages of the client, the control frames aren't forwarded.
+fn to_upstream(message: Message) -> Option<ts::Message> {
+    match message {
+        Message::Text(text) => Some(ts::Message::Text(text.as_str().into())),
+        Message::Binary(binary) => Some(ts::Message::Binary(binary)),
+        _ => None,
+    }
+}
+
+/// The data messages of the upstream, the control frames aren't forwarded.
+fn to_client(message: ts::Message) -> Option<Message> {
+    match message {
+        ts::Message::Text(text) => Some(Message::Text(text.as_str().into())),
+        ts::Message::Binary(binary) => Some(Message::Binary(binary)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::extract::State;
+    use axum::routing::any;
+    use http::HeaderValue;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// A stub upstream echoing the messages. It first sends the request line
+    /// and some headers, closes with `4001` on `bye`, and reports the close
+    /// frames of the proxy.
+    async fn stub() -> (SocketAddr, mpsc::UnboundedReceiver<Option<u16>>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let router = Router::new().route(
+            "/{*path}",
+            any(move |ws: WebSocketUpgrade, request: Request| {
+                let sender = sender.clone();
+                async move {
+                    let header = |name: &str| {
+                        request
+                            .headers()
+                            .get(name)
+                            .and_then(|value| value.to_str().ok())
+                            .unwrap_or("-")
+                            .to_owned()
+                    };
+                    let hello = format!(
+                        "{} host={} xfh={} cookie={}",
+                        request.uri(),
+                        header("host"),
+                        header("x-forwarded-host"),
+                        header("cookie"),
+                    );
+                    ws.protocols(["graphql-ws"])
+                        .on_upgrade(move |mut socket| async move {
+                            let _ = socket.send(Message::Text(hello.into())).await;
+                            while let Some(Ok(message)) = socket.next().await {
+                                match message {
+                                    Message::Text(text) if text.as_str() == "bye" => {
+                                        let _ = socket.send(close(4001, "bye")).await;
+                                    }
+                                    Message::Close(frame) => {
+                                        let _ = sender.send(frame.map(|frame| frame.code));
+                                    }
+                                    message @ (Message::Text(_) | Message::Binary(_)) => {
+                                        let _ = socket.send(message).await;
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        })
+                }
+            }),
+        );
+        (serve(router).await, receiver)
+    }
+
+    async fn serve(router: Router) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap()
+        });
+        addr
+    }
+
+    /// The proxy in front of the upstream.
+    async fn proxy(upstream: SocketAddr, options: WebSocketOptions) -> SocketAddr {
+        let rule = format!("/ws=http://{upstream}/socket").parse().unwrap();
+        let proxy = Arc::new(WebSocketProxy::new(vec![rule], options));
+        let router = Router::new()
+            .route(
+                "/ws/{*path}",
+                any(
+                    |State(proxy): State<Arc<WebSocketProxy>>,
+                     ws: WebSocketUpgrade,
+                     request: Request| async move {
+                        proxy.upgrade(ws, request).await
+                    },
+                ),
+            )
+            .with_state(proxy);
+        serve(router).await
+    }
+
+    async fn connect(proxy: SocketAddr, path: &str) -> Upstream {
+        let mut request = format!("ws://{proxy}{path}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(header::COOKIE, HeaderValue::from_static("token=abc"));
+        request.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static("graphql-ws"),
+        );
+        let (socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(
+            response.headers()[header::SEC_WEBSOCKET_PROTOCOL],
+            "graphql-ws"
+        );
+        socket
+    }
+
+    async fn text(socket: &mut Upstream) -> String {
+        match socket.next().await {
+            Some(Ok(ts::Message::Text(text))) => text.to_string(),
+            message => panic!("unexpected {message:?}"),
+        }
+    }
+
+    async fn close_code(socket: &mut Upstream) -> Option<u16> {
+        loop {
+            match socket.next().await {
+                Some(Ok(ts::Message::Close(frame))) => return frame.map(|frame| frame.code.into()),
+                Some(Ok(_)) => continue,
+                message => panic!("unexpected {message:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let rule: WebSocketRule = "/ws/=ws://backend:9000/socket/".parse().unwrap();
+        assert_eq!(rule.prefix(), "/ws");
+        assert_eq!(
+            rule.uri("/ws/chat?room=1").unwrap(),
+            "ws://backend:9000/socket/ws/chat?room=1"
+        );
+        assert!(rule.matches("/ws"));
+        assert!(!rule.matches("/wss"));
+        assert!("ws=ws://backend".parse::<WebSocketRule>().is_err());
+        assert!("/ws=ftp://backend".parse::<WebSocketRule>().is_err());
+        assert!("/ws=ws://backend?x=1".parse::<WebSocketRule>().is_err());
+    }
+
+    #[test]
+    fn handshake() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("host", "admin.example.com"),
+            ("connection", "Upgrade"),
+            ("upgrade", "websocket"),
+            ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-extensions", "permessage-deflate"),
+            ("sec-websocket-protocol", "graphql-ws"),
+            ("cookie", "token=abc"),
+        ] {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+        let forwarded = handshake_headers(&headers, None, true);
+        let mut names = forwarded.keys().map(HeaderName::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "cookie",
+                "sec-websocket-protocol",
+                "x-forwarded-host",
+                "x-forwarded-proto"
+            ]
+        );
+        let forwarded = handshake_headers(&headers, None, false);
+        assert_eq!(forwarded[header::HOST], "admin.example.com");
+    }
+
+    #[tokio::test]
+    async fn tunnel_messages() {
+        let (upstream, _) = stub().await;
+        let proxy = proxy(upstream, WebSocketOptions::default()).await;
+        let mut socket = connect(proxy, "/ws/chat?room=1").await;
+        assert_eq!(
+            text(&mut socket).await,
+            format!("/socket/ws/chat?room=1 host={upstream} xfh={proxy} cookie=token=abc")
+        );
+        socket.send(ts::Message::text("hello")).await.unwrap();
+        assert_eq!(text(&mut socket).await, "hello");
+        socket
+            .send(ts::Message::binary(vec![0u8, 1, 2]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            socket.next().await,
+            Some(Ok(ts::Message::Binary(binary))) if binary[..] == [0, 1, 2]
+        ));
+    }
+
+    #[tokio::test]
+    async fn close_codes() {
+        let (upstream, mut closed) = stub().await;
+        let proxy = proxy(upstream, WebSocketOptions::default()).await;
+        // The upstream closes
+        let mut socket = connect(proxy, "/ws/chat").await;
+        socket.send(ts::Message::text("bye")).await.unwrap();
+        assert_eq!(close_code(&mut socket).await, Some(4001));
+        // The close handshake finishes with the reply of the proxy
+        assert_eq!(closed.recv().await, Some(Some(4001)));
+        // The client closes
+        let mut socket = connect(proxy, "/ws/chat").await;
+        text(&mut socket).await;
+        socket
+            .close(Some(ts::protocol::CloseFrame {
+                code: CloseCode::Library(4002),
+                reason: "logout".into(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(closed.recv().await, Some(Some(4002)));
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let (upstream, mut closed) = stub().await;
+        let options = WebSocketOptions {
+            idle_timeout: Some(Duration::from_millis(100)),
+            ..WebSocketOptions::default()
+        };
+        let proxy = proxy(upstream, options).await;
+        let mut socket = connect(proxy, "/ws/chat").await;
+        text(&mut socket).await;
+        assert_eq!(close_code(&mut socket).await, Some(ws::close_code::AWAY));
+        assert_eq!(closed.recv().await, Some(Some(ws::close_code::AWAY)));
+    }
+
+    #[tokio::test]
+    async fn ping_timeout() {
+        let (upstream, mut closed) = stub().await;
+        let options = WebSocketOptions {
+            ping_interval: Some(Duration::from_millis(50)),
+            ..WebSocketOptions::default()
+        };
+        let proxy = proxy(upstream, options).await;
+        // The pongs are only sent while reading
+        let _socket = connect(proxy, "/ws/chat").await;
+        assert_eq!(closed.recv().await, Some(Some(ws::close_code::AWAY)));
+    }
+
+    #[tokio::test]
+    async fn upstream_down() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let upstream = listener.local_addr().unwrap();
+        drop(listener);
+        let proxy = proxy(upstream, WebSocketOptions::default()).await;
+        let request = format!("ws://{proxy}/ws/chat")
+            .into_client_request()
+            .unwrap();
+        match tokio_tungstenite::connect_async(request).await {
+            Err(Error::Http(response)) => assert_eq!(response.status(), StatusCode::BAD_GATEWAY),
+            result => panic!("unexpected {result:?}"),
+        }
+    }
+}